@@ -0,0 +1,455 @@
+//! Offline replay of exported Discord history against `SimBroker`.
+//!
+//! Inputs:
+//! * messages: JSON (flat array of `{timestamp, author, content}` or a DiscordChatExporter
+//!   export with a top-level `messages` array) or CSV with timestamp/date, author and
+//!   content columns and an optional author_id column;
+//! * prices: CSV `timestamp,instrument,price` where instrument is a stock symbol ("AAPL")
+//!   or an option, either "AAPL 150C 2025-08-16" or an OCC symbol
+//!   ("AAPL  250816C00150000").
+//!
+//! Every message from a tracked author goes through their signal grammar (expiries
+//! resolved against the message time), `RiskEngine::pre_check` and the executor's
+//! buy/sell monitors. Quotes are the latest price at or before the message time. Each
+//! signal's monitor runs to completion before the next message, and armed stop/target
+//! levels are checked after every price tick.
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::calendar;
+use crate::config::AppConfig;
+use crate::discord::AuthorFilter;
+use crate::executor::Executor;
+use crate::exits::ExitManager;
+use crate::grammar::Grammars;
+use crate::instrument::InstrumentId;
+use crate::parser::ParseOutcome;
+use crate::risk::RiskEngine;
+use crate::sim_broker::SimBroker;
+use crate::state::BotState;
+use crate::store::StateStore;
+use crate::types::Author;
+
+#[derive(Debug, Clone)]
+pub struct ReplayMessage {
+    pub timestamp: NaiveDateTime,
+    pub author: String,
+    /// Discord user ID, when the export carries one (DiscordChatExporter does).
+    pub author_id: Option<String>,
+    /// Server nickname and role IDs, from DiscordChatExporter exports.
+    pub nickname: Option<String>,
+    pub role_ids: Vec<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PricePoint {
+    pub timestamp: NaiveDateTime,
+    pub instrument: String,
+    pub price: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplaySummary {
+    pub messages: usize,
+    pub from_tracked: usize,
+    pub signals: usize,
+    /// Tracked messages that looked like a call but did not parse.
+    pub near_misses: usize,
+    pub orders: usize,
+    pub realized_pl: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayReport {
+    pub summary: ReplaySummary,
+    pub state: BotState,
+}
+
+/// Accepts RFC 3339 ("2025-08-16T14:30:00Z", offsets converted to UTC) or naive
+/// "YYYY-MM-DD HH:MM[:SS]" / "YYYY-MM-DDTHH:MM:SS".
+pub fn parse_timestamp(s: &str) -> Result<NaiveDateTime> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.naive_utc());
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(dt);
+        }
+    }
+    bail!("unrecognized timestamp: {}", s)
+}
+
+pub fn load_messages(path: impl AsRef<Path>) -> Result<Vec<ReplayMessage>> {
+    let path = path.as_ref();
+    let mut out = if is_csv(path) {
+        load_messages_csv(path)?
+    } else {
+        load_messages_json(path)?
+    };
+    out.sort_by_key(|m| m.timestamp);
+    Ok(out)
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+}
+
+fn load_messages_json(path: &Path) -> Result<Vec<ReplayMessage>> {
+    let s = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let v: Value = serde_json::from_str(&s)?;
+    let arr = v
+        .get("messages")
+        .unwrap_or(&v)
+        .as_array()
+        .context("expected a JSON array of messages")?;
+    let mut out = Vec::with_capacity(arr.len());
+    for it in arr {
+        let ts = it
+            .get("timestamp")
+            .or_else(|| it.get("date"))
+            .and_then(|x| x.as_str())
+            .context("message without timestamp")?;
+        // author may be a plain string or an object (DiscordChatExporter)
+        let author = it.get("author");
+        let field = |k: &str| {
+            author
+                .and_then(|a| a.get(k))
+                .and_then(|x| x.as_str())
+                .map(str::to_string)
+        };
+        let name = match author {
+            Some(Value::String(s)) => s.clone(),
+            _ => field("name").unwrap_or_default(),
+        };
+        let role_ids = author
+            .and_then(|a| a.get("roles"))
+            .and_then(|r| r.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.get("id").and_then(|x| x.as_str()).map(str::to_string))
+            .collect();
+        let content = it
+            .get("content")
+            .and_then(|x| x.as_str())
+            .unwrap_or_default()
+            .to_string();
+        out.push(ReplayMessage {
+            timestamp: parse_timestamp(ts)?,
+            author: name,
+            author_id: field("id"),
+            nickname: field("nickname"),
+            role_ids,
+            content,
+        });
+    }
+    Ok(out)
+}
+
+fn load_messages_csv(path: &Path) -> Result<Vec<ReplayMessage>> {
+    let mut rdr =
+        csv::Reader::from_path(path).with_context(|| format!("open {}", path.display()))?;
+    let headers = rdr.headers()?.clone();
+    let col = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+    };
+    let ts_i = col(&["timestamp", "date"]).context("CSV needs a timestamp/date column")?;
+    let au_i = col(&["author", "username"]).context("CSV needs an author column")?;
+    let ct_i = col(&["content", "message"]).context("CSV needs a content column")?;
+    let id_i = col(&["author_id", "user_id"]);
+    let mut out = Vec::new();
+    for rec in rdr.records() {
+        let rec = rec?;
+        out.push(ReplayMessage {
+            timestamp: parse_timestamp(rec.get(ts_i).unwrap_or_default())?,
+            author: rec.get(au_i).unwrap_or_default().to_string(),
+            author_id: id_i
+                .and_then(|i| rec.get(i))
+                .filter(|id| !id.is_empty())
+                .map(str::to_string),
+            nickname: None,
+            role_ids: Vec::new(),
+            content: rec.get(ct_i).unwrap_or_default().to_string(),
+        });
+    }
+    Ok(out)
+}
+
+pub fn load_prices(path: impl AsRef<Path>) -> Result<Vec<PricePoint>> {
+    let path = path.as_ref();
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(path)
+        .with_context(|| format!("open {}", path.display()))?;
+    let mut out = Vec::new();
+    for rec in rdr.records() {
+        let rec = rec?;
+        let (Some(ts), Some(ins), Some(px)) = (rec.get(0), rec.get(1), rec.get(2)) else {
+            bail!("price rows need timestamp,instrument,price");
+        };
+        out.push(PricePoint {
+            timestamp: parse_timestamp(ts)?,
+            instrument: ins.trim().to_ascii_uppercase(),
+            price: px.trim().parse().context("bad price")?,
+        });
+    }
+    out.sort_by_key(|p| p.timestamp);
+    Ok(out)
+}
+
+/// Register an instrument key from the price file with the simulator; returns its ticker id.
+fn register_instrument(sim: &SimBroker, id: &InstrumentId) -> i64 {
+    match id {
+        InstrumentId::Stock(sym) => sim.add_stock(sym),
+        InstrumentId::Option(k) => {
+            sim.add_option(&k.symbol, k.strike, k.call_put, &k.expiry.to_string())
+        }
+    }
+}
+
+/// Replay the `messages` whose author `authors` tracks, as the live listener would: by
+/// user or role ID when the export carries them, otherwise by name.
+/// `capital` is the simulated account's starting value.
+pub async fn replay(
+    mut cfg: AppConfig,
+    authors: &AuthorFilter,
+    messages: &[ReplayMessage],
+    prices: &[PricePoint],
+    capital: f64,
+) -> Result<ReplayReport> {
+    // Never trade "dry" in a replay, and resolve each order on its first poll so the
+    // replay does not wait on wall-clock timeouts.
+    cfg.exec.dry_run = false;
+    cfg.exec.buy_timeout_sec = 0;
+    cfg.exec.sell_timeout_sec = 0;
+
+    let sim = Arc::new(SimBroker::new());
+    sim.set_account_value(capital);
+    // Spellings of the same contract ("SPY 450C 2025-09-20" / OCC) share one ticker.
+    let mut by_id = std::collections::HashMap::new();
+    let mut ids = std::collections::HashMap::new();
+    for p in prices {
+        if !ids.contains_key(&p.instrument) {
+            let id: InstrumentId = p.instrument.parse()?;
+            let tid = *by_id
+                .entry(id.clone())
+                .or_insert_with(|| register_instrument(&sim, &id));
+            ids.insert(p.instrument.clone(), tid);
+        }
+    }
+
+    let state = Arc::new(Mutex::new(BotState::default()));
+    let risk = RiskEngine::from_cfg(&cfg.risk);
+    let store = Arc::new(StateStore::in_memory()?);
+    let grammars = Grammars::from_cfg(&cfg)?;
+    let exec = Executor::new(Arc::clone(&sim), Arc::clone(&state), store, risk, cfg);
+
+    let mut summary = ReplaySummary {
+        messages: messages.len(),
+        ..Default::default()
+    };
+    let exits = ExitManager::new(exec.clone());
+    let mut next_price = 0;
+    tokio::task::LocalSet::new()
+        .run_until(async {
+            // Apply every price tick up to `until`, placing signals queued for the open and
+            // checking stop/target levels after each.
+            let advance = |until: Option<NaiveDateTime>, next_price: &mut usize| {
+                let mut due = Vec::new();
+                while *next_price < prices.len()
+                    && until.is_none_or(|u| prices[*next_price].timestamp <= u)
+                {
+                    due.push(&prices[*next_price]);
+                    *next_price += 1;
+                }
+                due
+            };
+            for m in messages {
+                for p in advance(Some(m.timestamp), &mut next_price) {
+                    sim.set_time(p.timestamp);
+                    sim.set_price(ids[&p.instrument], p.price);
+                    let mut placed = exec.release_queued().await;
+                    placed.extend(exits.check().await);
+                    for h in placed {
+                        summary.orders += 1;
+                        let _ = h.await;
+                    }
+                }
+                sim.set_time(m.timestamp);
+
+                if !tracked(authors, m) {
+                    continue;
+                }
+                summary.from_tracked += 1;
+                let author = Author {
+                    id: m.author_id.clone(),
+                    name: m.author.clone(),
+                };
+                let sigs = match grammars
+                    .for_message(None, &author)
+                    .diagnose(&m.content, calendar::eastern(m.timestamp))
+                {
+                    ParseOutcome::Parsed(sigs) => sigs,
+                    ParseOutcome::NearMiss(miss) => {
+                        summary.near_misses += 1;
+                        warn!(
+                            "[{}] Near-miss signal from {}: {} in {:?}",
+                            m.timestamp, m.author, miss.issue, miss.line
+                        );
+                        continue;
+                    }
+                    ParseOutcome::NotASignal => continue,
+                };
+                for sig in sigs {
+                    summary.signals += 1;
+                    info!("[{}] Signal from {}: {:?}", m.timestamp, m.author, sig);
+                    if let Some(h) = exec.handle_author_signal(&author, sig).await {
+                        summary.orders += 1;
+                        let _ = h.await;
+                    }
+                }
+            }
+            for p in advance(None, &mut next_price) {
+                sim.set_time(p.timestamp);
+                sim.set_price(ids[&p.instrument], p.price);
+                let mut placed = exec.release_queued().await;
+                placed.extend(exits.check().await);
+                for h in placed {
+                    summary.orders += 1;
+                    let _ = h.await;
+                }
+            }
+        })
+        .await;
+
+    let state = std::mem::take(&mut *state.lock().await);
+    summary.realized_pl = state.daily_pl.iter().map(|e| e.realized_pl).sum();
+    Ok(ReplayReport { summary, state })
+}
+
+/// Whether `m`'s author passes `authors`. IDs the export lacks never match.
+fn tracked(authors: &AuthorFilter, m: &ReplayMessage) -> bool {
+    let id = |s: &str| s.parse::<u64>().unwrap_or(0);
+    let nick: Vec<&str> = m.nickname.as_deref().into_iter().collect();
+    let roles: Vec<u64> = m.role_ids.iter().map(|r| id(r)).collect();
+    authors.matches(
+        m.author_id.as_deref().map_or(0, id),
+        &m.author,
+        &nick,
+        &roles,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+
+    fn ts(s: &str) -> NaiveDateTime {
+        parse_timestamp(s).unwrap()
+    }
+
+    fn msg(t: &str, author: &str, content: &str) -> ReplayMessage {
+        ReplayMessage {
+            timestamp: ts(t),
+            author: author.into(),
+            author_id: None,
+            nickname: None,
+            role_ids: Vec::new(),
+            content: content.into(),
+        }
+    }
+
+    fn px(t: &str, instrument: &str, price: f64) -> PricePoint {
+        PricePoint {
+            timestamp: ts(t),
+            instrument: instrument.into(),
+            price,
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_follows_only_tracked_author() {
+        let mut cfg = test_config("replay");
+        cfg.risk.max_position_value = 5_000.0;
+        cfg.exec.dry_run = true;
+        cfg.exec.buy_mode = "MARKET".into();
+        cfg.exec.sell_mode = "MARKET".into();
+        let messages = vec![
+            msg("2025-08-12T14:00:00Z", "alice", "BTO 2 SPY 450C 08/16 @ m"),
+            msg(
+                "2025-08-12T14:05:00Z",
+                "mallory",
+                "BTO 1 SPY 450C 08/16 @ m",
+            ),
+            msg("2025-08-13T15:00:00Z", "alice", "nice move today"),
+            msg(
+                "2025-08-13T15:10:00Z",
+                "alice",
+                "BTO 2 SPY 450C 08/32 @ 1.50",
+            ),
+            msg("2025-08-13T15:30:00Z", "alice", "STC 2 SPY 450C 08/16 @ m"),
+        ];
+        let prices = vec![
+            px("2025-08-12 13:59:00", "SPY 450C 2025-08-16", 1.20),
+            px("2025-08-13 15:29:00", "SPY 450C 2025-08-16", 1.70),
+        ];
+
+        let alice = AuthorFilter {
+            usernames: vec!["Alice".into()],
+            ..Default::default()
+        };
+        let report = replay(cfg, &alice, &messages, &prices, 100_000.0)
+            .await
+            .unwrap();
+        assert_eq!(report.summary.from_tracked, 4);
+        assert_eq!(report.summary.signals, 2);
+        assert_eq!(report.summary.near_misses, 1);
+        assert_eq!(report.summary.orders, 2);
+        assert!(report.state.holdings.is_empty());
+        assert_eq!(report.state.daily_pl.len(), 1);
+        assert_eq!(report.state.daily_pl[0].date, ts("2025-08-13 00:00").date());
+        assert!((report.summary.realized_pl - 100.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn replay_tracks_export_ids_and_roles() {
+        let path = std::env::temp_dir().join(format!("dwt-export-{}.json", std::process::id()));
+        let export = r#"{ "messages": [
+            { "timestamp": "2025-08-12T14:00:00Z", "content": "hi",
+              "author": { "id": "42", "name": "renamed" } },
+            { "timestamp": "2025-08-12T14:01:00Z", "content": "hi",
+              "author": { "id": "9", "name": "carol", "roles": [{ "id": "7" }] } },
+            { "timestamp": "2025-08-12T14:02:00Z", "content": "hi",
+              "author": { "id": "5", "name": "dave", "nickname": "Alice Trades" } },
+            { "timestamp": "2025-08-12T14:03:00Z", "content": "hi",
+              "author": { "id": "6", "name": "mallory", "roles": [{ "id": "8" }] } }
+        ] }"#;
+        std::fs::write(&path, export).unwrap();
+        let messages = load_messages(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut cfg = test_config("replay-ids");
+        cfg.discord.tracked_user_ids = vec!["42".into()];
+        cfg.discord.tracked_role_ids = vec!["7".into()];
+        cfg.discord.tracked_display_names = vec!["alice trades".into()];
+        let authors = AuthorFilter::from_cfg(&cfg.discord);
+        let report = replay(cfg, &authors, &messages, &[], 100_000.0)
+            .await
+            .unwrap();
+        assert_eq!(report.summary.messages, 4);
+        assert_eq!(report.summary.from_tracked, 3);
+    }
+}
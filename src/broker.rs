@@ -0,0 +1,118 @@
+//! Broker abstraction used by the execution pipeline. `WbCtx` is the live/paper
+//! Webull implementation; `SimBroker` is a deterministic in-memory stand-in.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use webull_unofficial::models::{OptionContract, OrderAction, TimeInForce};
+
+use crate::calendar;
+use crate::instrument::OptionKey;
+use crate::types::Holding;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderStatus {
+    Working,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Unknown(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderInfo {
+    pub status: OrderStatus,
+    pub filled_qty: f64,
+    pub avg_fill_price: f64,
+}
+
+/// Everything the pipeline needs from a broker: discovery, quotes, orders and positions.
+///
+/// Futures are not required to be `Send`; the bot runs on a current-thread runtime and
+/// drives monitors with `spawn_local`.
+#[async_trait(?Send)]
+pub trait Broker {
+    /// Trading date used to stamp realized P/L entries: the US/Eastern calendar date.
+    fn trade_date(&self) -> NaiveDate {
+        self.market_time().date()
+    }
+
+    /// Current exchange-local (US/Eastern) time, for the market calendar.
+    fn market_time(&self) -> NaiveDateTime {
+        calendar::eastern_now()
+    }
+
+    // ---------- Discovery ----------
+
+    async fn find_stock_ticker_id(&self, symbol: &str) -> Result<i64>;
+
+    async fn find_option_contract(&self, key: &OptionKey) -> Result<OptionContract>;
+
+    /// Expiries listed at `key`'s root, strike and side, used when a relative expiry
+    /// ("0DTE", "weekly") lands on a date the underlying does not list.
+    async fn listed_expiries(&self, key: &OptionKey) -> Result<Vec<NaiveDate>>;
+
+    // ---------- Quotes ----------
+
+    async fn mid_price(&self, ticker_id: i64) -> Result<f64>;
+
+    // ---------- Account & positions ----------
+
+    /// Account net liquidation value (USD), used by percent-of-account sizing.
+    async fn net_liquidation(&self) -> Result<f64>;
+
+    async fn positions_simple(&self) -> Result<Vec<Holding>>;
+
+    // ---------- Order status & actions ----------
+
+    async fn get_order_info(&self, order_id: &str) -> Result<OrderInfo>;
+
+    async fn cancel_order(&self, order_id: &str) -> Result<()>;
+
+    // ---------- Orders ----------
+
+    async fn place_stock_market(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        tif: &TimeInForce,
+    ) -> Result<String>;
+
+    async fn place_stock_limit(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        tif: &TimeInForce,
+    ) -> Result<String>;
+
+    /// Stock limit order allowed to fill in the pre-market and after-hours sessions.
+    async fn place_stock_limit_extended(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        tif: &TimeInForce,
+    ) -> Result<String>;
+
+    async fn place_option_market(
+        &self,
+        contract: &OptionContract,
+        qty: f64,
+        side: OrderAction,
+        tif: &TimeInForce,
+    ) -> Result<String>;
+
+    async fn place_option_limit(
+        &self,
+        contract: &OptionContract,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        tif: &TimeInForce,
+    ) -> Result<String>;
+}
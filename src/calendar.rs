@@ -0,0 +1,383 @@
+//! US equity and option market calendar: NYSE holidays and early closes, the trading
+//! days option expiries fall on, and the pre-market / regular / after-hours sessions
+//! that decide whether an order can be placed now ([`placement`]).
+//!
+//! Times are exchange-local (US/Eastern) `NaiveDateTime`s; [`eastern`] converts from UTC.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+
+use crate::config::OutsideHours;
+use crate::types::TradeSignal;
+
+/// `n`-th `weekday` of `month` (1-based).
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+/// Last `weekday` of `month`.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// Easter Sunday (anonymous Gregorian algorithm).
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+/// A fixed-date holiday moved to Friday when on a Saturday, Monday when on a Sunday.
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+/// Full-day NYSE closures in `year`.
+pub fn holidays(year: i32) -> Vec<NaiveDate> {
+    let ymd = |m, d| NaiveDate::from_ymd_opt(year, m, d).unwrap();
+    let mut days = vec![
+        nth_weekday(year, 1, Weekday::Mon, 3), // Martin Luther King Jr. Day
+        nth_weekday(year, 2, Weekday::Mon, 3), // Washington's Birthday
+        easter(year) - Duration::days(2),      // Good Friday
+        last_weekday(year, 5, Weekday::Mon),   // Memorial Day
+        observed(ymd(7, 4)),
+        nth_weekday(year, 9, Weekday::Mon, 1),  // Labor Day
+        nth_weekday(year, 11, Weekday::Thu, 4), // Thanksgiving
+        observed(ymd(12, 25)),
+    ];
+    // New Year's Day on a Saturday is not moved back into the old year
+    if ymd(1, 1).weekday() != Weekday::Sat {
+        days.push(observed(ymd(1, 1)));
+    }
+    if year >= 2022 {
+        days.push(observed(ymd(6, 19))); // Juneteenth
+    }
+    days.sort();
+    days
+}
+
+pub fn is_holiday(date: NaiveDate) -> bool {
+    holidays(date.year()).contains(&date)
+}
+
+/// Weekday that is not an exchange holiday.
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !is_holiday(date)
+}
+
+/// `date` if it is a trading day, else the next one.
+pub fn trading_day_on_or_after(mut date: NaiveDate) -> NaiveDate {
+    while !is_trading_day(date) {
+        date += Duration::days(1);
+    }
+    date
+}
+
+/// `date` if it is a trading day, else the one before it (how Friday expiries move
+/// when Friday is a holiday).
+pub fn trading_day_on_or_before(mut date: NaiveDate) -> NaiveDate {
+    while !is_trading_day(date) {
+        date -= Duration::days(1);
+    }
+    date
+}
+
+/// The `n`-th trading day after `date` (0 = `date` itself, or the next trading day).
+pub fn add_trading_days(date: NaiveDate, n: u32) -> NaiveDate {
+    let mut day = trading_day_on_or_after(date);
+    for _ in 0..n {
+        day = trading_day_on_or_after(day + Duration::days(1));
+    }
+    day
+}
+
+/// Expiry of the weekly series for the Monday-Sunday week of `date` (its Friday, or
+/// Thursday when Friday is a holiday).
+pub fn weekly_expiry(date: NaiveDate) -> NaiveDate {
+    let friday = date + Duration::days(4 - date.weekday().num_days_from_monday() as i64);
+    trading_day_on_or_before(friday)
+}
+
+/// Standard monthly expiry: the third Friday of the month, moved earlier on a holiday.
+pub fn monthly_expiry(year: i32, month: u32) -> NaiveDate {
+    trading_day_on_or_before(nth_weekday(year, month, Weekday::Fri, 3))
+}
+
+/// Half-day sessions closing at 13:00: July 3, the day after Thanksgiving and
+/// Christmas Eve, when they are trading days.
+pub fn early_closes(year: i32) -> Vec<NaiveDate> {
+    let ymd = |m, d| NaiveDate::from_ymd_opt(year, m, d).unwrap();
+    [
+        ymd(7, 3),
+        nth_weekday(year, 11, Weekday::Thu, 4) + Duration::days(1),
+        ymd(12, 24),
+    ]
+    .into_iter()
+    .filter(|d| is_trading_day(*d))
+    .collect()
+}
+
+/// US/Eastern wall time for a UTC time. Daylight saving runs from 02:00 on the second
+/// Sunday of March to 02:00 on the first Sunday of November.
+pub fn eastern(utc: NaiveDateTime) -> NaiveDateTime {
+    let year = utc.year();
+    let at = |date: NaiveDate, hour| date.and_hms_opt(hour, 0, 0).unwrap();
+    // 02:00 EST = 07:00 UTC; 02:00 EDT = 06:00 UTC
+    let dst_start = at(nth_weekday(year, 3, Weekday::Sun, 2), 7);
+    let dst_end = at(nth_weekday(year, 11, Weekday::Sun, 1), 6);
+    let offset = if utc >= dst_start && utc < dst_end {
+        4
+    } else {
+        5
+    };
+    utc - Duration::hours(offset)
+}
+
+/// Current US/Eastern wall time.
+pub fn eastern_now() -> NaiveDateTime {
+    eastern(Utc::now().naive_utc())
+}
+
+/// Trading session at an exchange-local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    /// 04:00-09:30 on a trading day.
+    PreMarket,
+    /// 09:30-16:00, or to 13:00 on an early close.
+    Regular,
+    /// From the close to 20:00 (17:00 after an early close).
+    AfterHours,
+    /// Overnight, weekends and holidays.
+    Closed,
+}
+
+fn hm(h: u32, m: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(h, m, 0).unwrap()
+}
+
+/// Regular open and close on `date`; None when the market is closed all day.
+pub fn regular_hours(date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+    if !is_trading_day(date) {
+        return None;
+    }
+    let close = if early_closes(date.year()).contains(&date) {
+        hm(13, 0)
+    } else {
+        hm(16, 0)
+    };
+    Some((hm(9, 30), close))
+}
+
+pub fn session(now: NaiveDateTime) -> Session {
+    let Some((open, close)) = regular_hours(now.date()) else {
+        return Session::Closed;
+    };
+    let t = now.time();
+    let after_end = close + Duration::hours(4);
+    if t < hm(4, 0) || t >= after_end {
+        Session::Closed
+    } else if t < open {
+        Session::PreMarket
+    } else if t < close {
+        Session::Regular
+    } else {
+        Session::AfterHours
+    }
+}
+
+/// Start of the regular session in progress at `now`, or of the next one.
+pub fn next_open(now: NaiveDateTime) -> NaiveDateTime {
+    let mut date = now.date();
+    loop {
+        if let Some((open, close)) = regular_hours(date) {
+            if date > now.date() || now.time() < close {
+                return date.and_time(open).max(now);
+            }
+        }
+        date += Duration::days(1);
+    }
+}
+
+/// What to do with a signal arriving at `now` under `policy`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    /// Place as usual.
+    Now,
+    /// Place as an extended-hours (pre-market / after-hours) limit order.
+    Extended,
+    /// Hold the signal and place it at this regular open.
+    Queue(NaiveDateTime),
+    Reject(String),
+}
+
+/// Regular-session signals are placed now. Outside it, `policy` decides; extended-hours
+/// orders are only possible for stock limit orders in the pre-market or after-hours,
+/// other orders under `extended` wait for the open.
+pub fn placement(
+    policy: OutsideHours,
+    now: NaiveDateTime,
+    signal: &TradeSignal,
+    is_limit: bool,
+) -> Placement {
+    let session = session(now);
+    if policy == OutsideHours::Ignore || session == Session::Regular {
+        return Placement::Now;
+    }
+    let extended_ok = matches!(signal, TradeSignal::Stock(_))
+        && is_limit
+        && matches!(session, Session::PreMarket | Session::AfterHours);
+    match policy {
+        OutsideHours::Extended if extended_ok => Placement::Extended,
+        OutsideHours::Reject => Placement::Reject(format!("market {:?} at {}", session, now)),
+        _ => Placement::Queue(next_open(now)),
+    }
+}
+
+/// Listed expiry nearest to `target`; ties go to the later date.
+pub fn closest_expiry(listed: &[NaiveDate], target: NaiveDate) -> Option<NaiveDate> {
+    listed
+        .iter()
+        .copied()
+        .min_by_key(|d| ((*d - target).num_days().abs(), target > *d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(y: i32, m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, day).unwrap()
+    }
+
+    #[test]
+    fn nyse_holidays() {
+        let h = holidays(2025);
+        assert_eq!(
+            h,
+            vec![
+                d(2025, 1, 1),
+                d(2025, 1, 20),
+                d(2025, 2, 17),
+                d(2025, 4, 18),
+                d(2025, 5, 26),
+                d(2025, 6, 19),
+                d(2025, 7, 4),
+                d(2025, 9, 1),
+                d(2025, 11, 27),
+                d(2025, 12, 25),
+            ]
+        );
+        // observed on the Friday / Monday, except New Year's on a Saturday
+        assert!(is_holiday(d(2026, 7, 3)));
+        assert!(is_holiday(d(2022, 12, 26)));
+        assert!(!is_holiday(d(2021, 12, 31)));
+        assert!(!is_trading_day(d(2025, 8, 2)));
+    }
+
+    #[test]
+    fn expiry_dates() {
+        // Good Friday 2025: the weekly moves to Thursday
+        assert_eq!(weekly_expiry(d(2025, 4, 14)), d(2025, 4, 17));
+        assert_eq!(weekly_expiry(d(2025, 8, 1)), d(2025, 8, 1));
+        assert_eq!(weekly_expiry(d(2025, 8, 2)), d(2025, 8, 1));
+        assert_eq!(monthly_expiry(2025, 9), d(2025, 9, 19));
+        assert_eq!(add_trading_days(d(2025, 8, 29), 1), d(2025, 9, 2));
+        assert_eq!(add_trading_days(d(2025, 8, 30), 0), d(2025, 9, 2));
+
+        let listed = [d(2025, 8, 1), d(2025, 8, 8), d(2025, 8, 15)];
+        assert_eq!(closest_expiry(&listed, d(2025, 8, 4)), Some(d(2025, 8, 1)));
+        assert_eq!(closest_expiry(&listed, d(2025, 8, 5)), Some(d(2025, 8, 8)));
+        assert_eq!(closest_expiry(&[], d(2025, 8, 5)), None);
+    }
+
+    fn at(day: NaiveDate, h: u32, m: u32) -> NaiveDateTime {
+        day.and_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn sessions_and_early_closes() {
+        assert_eq!(
+            early_closes(2025),
+            vec![d(2025, 7, 3), d(2025, 11, 28), d(2025, 12, 24)]
+        );
+        // EDT in August, EST in December
+        assert_eq!(eastern(at(d(2025, 8, 1), 13, 30)), at(d(2025, 8, 1), 9, 30));
+        assert_eq!(
+            eastern(at(d(2025, 12, 1), 14, 30)),
+            at(d(2025, 12, 1), 9, 30)
+        );
+
+        let fri = d(2025, 8, 1);
+        assert_eq!(session(at(fri, 3, 59)), Session::Closed);
+        assert_eq!(session(at(fri, 8, 0)), Session::PreMarket);
+        assert_eq!(session(at(fri, 9, 30)), Session::Regular);
+        assert_eq!(session(at(fri, 16, 0)), Session::AfterHours);
+        assert_eq!(session(at(fri, 20, 0)), Session::Closed);
+        assert_eq!(session(at(d(2025, 11, 28), 13, 30)), Session::AfterHours);
+        assert_eq!(session(at(d(2025, 8, 2), 12, 0)), Session::Closed);
+
+        assert_eq!(next_open(at(fri, 8, 0)), at(fri, 9, 30));
+        assert_eq!(next_open(at(fri, 10, 0)), at(fri, 10, 0));
+        // Friday evening -> Monday; Labor Day weekend -> Tuesday
+        assert_eq!(next_open(at(fri, 17, 0)), at(d(2025, 8, 4), 9, 30));
+        assert_eq!(
+            next_open(at(d(2025, 8, 29), 18, 0)),
+            at(d(2025, 9, 2), 9, 30)
+        );
+    }
+
+    #[test]
+    fn placement_by_policy_and_session() {
+        let stock = crate::parser::parse_signal("BTO 10 AAPL @ 190").unwrap();
+        let option = crate::parser::parse_signal("BTO 1 SPY 450C 09/20 @ 2").unwrap();
+        let pre = at(d(2025, 8, 1), 8, 0);
+        let open = at(d(2025, 8, 1), 9, 30);
+        let regular = at(d(2025, 8, 1), 11, 0);
+
+        assert_eq!(
+            placement(OutsideHours::Ignore, pre, &stock, true),
+            Placement::Now
+        );
+        assert_eq!(
+            placement(OutsideHours::Queue, regular, &stock, true),
+            Placement::Now
+        );
+        assert_eq!(
+            placement(OutsideHours::Queue, pre, &stock, true),
+            Placement::Queue(open)
+        );
+        assert_eq!(
+            placement(OutsideHours::Extended, pre, &stock, true),
+            Placement::Extended
+        );
+        // no extended hours for market orders or options
+        assert_eq!(
+            placement(OutsideHours::Extended, pre, &stock, false),
+            Placement::Queue(open)
+        );
+        assert_eq!(
+            placement(OutsideHours::Extended, pre, &option, true),
+            Placement::Queue(open)
+        );
+        assert!(matches!(
+            placement(OutsideHours::Reject, pre, &option, true),
+            Placement::Reject(_)
+        ));
+    }
+}
@@ -1,12 +1,44 @@
 //! Load and validate runtime configuration.
 
 use serde::Deserialize;
-use std::{fs, path::Path};
+use tracing::info;
 
+use crate::grammar::GrammarCfg;
+use crate::reconcile::ReconcilePolicy;
+use crate::sizing::SizingMode;
+use crate::types::{Author, TradeSignal};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Which authors are followed. A message is tracked when any list matches; IDs are
+/// stable across renames and preferred over names.
 #[derive(Debug, Deserialize, Clone)]
 pub struct DiscordCfg {
     pub channel_ids: Vec<String>,
+    /// Usernames, matched exactly (case-insensitive) unless `substring_match`.
+    #[serde(default)]
     pub tracked_users: Vec<String>,
+    #[serde(default)]
+    pub tracked_user_ids: Vec<String>,
+    /// Global display names or server nicknames, matched like `tracked_users`.
+    #[serde(default)]
+    pub tracked_display_names: Vec<String>,
+    /// Guild roles whose members are all followed.
+    #[serde(default)]
+    pub tracked_role_ids: Vec<String>,
+    /// Match tracked names as case-insensitive substrings ("bob" also matches "bobby").
+    /// `authors` blocks are always matched exactly.
+    #[serde(default)]
+    pub substring_match: bool,
+    /// Signal grammar for every channel (see `grammar`); "standard" when unset.
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// Channel ID -> grammar, overriding `grammar`.
+    #[serde(default)]
+    pub channel_grammars: HashMap<String, String>,
+    /// Channel to post near-miss notices to (tracked messages that look like a call
+    /// but do not parse). They are always logged as warnings.
+    #[serde(default)]
+    pub near_miss_channel_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -18,6 +50,110 @@ pub struct WebullCfg {
 #[derive(Debug, Deserialize, Clone)]
 pub struct RiskCfg {
     pub max_position_value: f64,
+    /// Cap on gross short exposure (stock: shares × sale price, options: contracts ×
+    /// strike × 100). 0 disables STO.
+    #[serde(default)]
+    pub max_short_exposure: f64,
+    /// Day loss (realized today + unrealized on open positions, USD) at which new
+    /// entries are halted for the rest of the trading day. 0 disables the limit.
+    #[serde(default)]
+    pub max_daily_loss: f64,
+    /// Cap on the cost basis of all open holdings plus the new entry (USD). 0 disables.
+    #[serde(default)]
+    pub max_total_exposure: f64,
+    /// Cap on the cost basis held in one underlying, stock and options combined (USD).
+    /// 0 disables.
+    #[serde(default)]
+    pub max_underlying_exposure: f64,
+    /// Cap on the number of open holdings; adding to one already held is allowed.
+    /// 0 disables.
+    #[serde(default)]
+    pub max_open_positions: usize,
+}
+
+/// Overrides for one tracked author. Unset fields fall back to the global config.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthorCfg {
+    /// Discord username, matched exactly (case-insensitive) when `user_id` is unset.
+    pub name: String,
+    /// Discord user ID; when set, the block follows the author across renames and is
+    /// not matched by name.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Scales every absolute signal quantity (rounded down, at least 1), and sized
+    /// entries under a `sizing` mode.
+    #[serde(default)]
+    pub qty_multiplier: Option<f64>,
+    /// Entry size in shares/contracts/spreads, replacing the signal's and any `sizing`
+    /// mode. Exits keep theirs, capped at the quantity held.
+    #[serde(default)]
+    pub fixed_qty: Option<u32>,
+    #[serde(default = "default_true")]
+    pub follow_stocks: bool,
+    /// Single options and spreads.
+    #[serde(default = "default_true")]
+    pub follow_options: bool,
+    /// Per-order notional cap, applied in addition to `risk.max_position_value`.
+    #[serde(default)]
+    pub max_position_value: Option<f64>,
+    /// Realized loss today on this author's signals at which their entries are refused.
+    #[serde(default)]
+    pub max_daily_loss: Option<f64>,
+    #[serde(default)]
+    pub buy_mode: Option<String>,
+    #[serde(default)]
+    pub sell_mode: Option<String>,
+    /// Signal grammar for this author's messages, overriding the channel's.
+    #[serde(default)]
+    pub grammar: Option<String>,
+}
+
+impl AuthorCfg {
+    /// Key the author's signals, orders and realized P/L are attributed under: the user
+    /// ID when set, so a rename keeps the day's loss budget, else the name.
+    pub fn key(&self) -> &str {
+        self.user_id.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The author this block describes, for handling a signal as theirs.
+    pub fn as_author(&self) -> Author {
+        Author {
+            id: self.user_id.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    pub fn follows(&self, signal: &TradeSignal) -> bool {
+        match signal {
+            TradeSignal::Stock(_) => self.follow_stocks,
+            TradeSignal::Option(_) | TradeSignal::Spread(_) => self.follow_options,
+        }
+    }
+
+    /// Apply `fixed_qty` or `qty_multiplier` to an absolute quantity. Under `fixed_qty`
+    /// entries take the fixed size and exits are capped at `held`, the quantity they can
+    /// close, since the author's size no longer relates to ours. Sizes relative to
+    /// holdings ("STC half") are left alone.
+    pub fn resize(&self, signal: &mut TradeSignal, held: f64) {
+        let opening = signal.action().is_opening();
+        let Some(q) = signal.quantity_mut() else {
+            return;
+        };
+        let sized = match (self.fixed_qty, self.qty_multiplier) {
+            (Some(n), _) if opening => n,
+            (Some(_), _) if held >= 1.0 => (*q).min(held as u32),
+            (_, Some(m)) => ((*q as f64 * m).floor() as u32).max(1),
+            _ => *q,
+        };
+        if sized != *q {
+            info!("{}: quantity {} -> {}", self.name, q, sized);
+            *q = sized;
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,12 +168,98 @@ pub struct ExecCfg {
     pub sell_timeout_sec: u64,
     pub buy_limit_slippage_pct: f64,
     pub sell_limit_slippage_pct: f64,
+    /// How often armed stop/target levels are checked against marks.
+    #[serde(default = "default_exit_check_interval_sec")]
+    pub exit_check_interval_sec: u64,
+    /// Signals arriving outside the regular session (see `calendar::placement`).
+    #[serde(default)]
+    pub outside_hours: OutsideHours,
+}
+
+/// What happens to a signal outside the regular US session (pre-market, after-hours,
+/// overnight, weekends and holidays).
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutsideHours {
+    /// Place immediately, whatever the session; the market calendar is not consulted.
+    #[default]
+    Ignore,
+    /// Hold the signal and place it at the next regular open.
+    Queue,
+    /// Stock limit orders go out as extended-hours orders in the pre-market and
+    /// after-hours; everything else is queued for the open.
+    Extended,
+    /// Refuse the signal.
+    Reject,
+}
+
+fn default_exit_check_interval_sec() -> u64 {
+    5
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct StateCfg {
+    /// SQLite database file.
     pub path: String,
     pub flush_interval_sec: u64,
+    /// JSON state file from earlier versions, imported once into an empty database.
+    #[serde(default)]
+    pub import_json: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReconcileCfg {
+    #[serde(default)]
+    pub policy: ReconcilePolicy,
+    /// Average-cost difference (relative to the broker's, 0.01 = 1%) tolerated before
+    /// it is reported as cost drift.
+    #[serde(default = "default_cost_tolerance_pct")]
+    pub cost_tolerance_pct: f64,
+}
+
+impl Default for ReconcileCfg {
+    fn default() -> Self {
+        Self {
+            policy: ReconcilePolicy::default(),
+            cost_tolerance_pct: default_cost_tolerance_pct(),
+        }
+    }
+}
+
+fn default_cost_tolerance_pct() -> f64 {
+    0.01
+}
+
+/// What an edit to a signal message does to the orders it produced.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EditPolicy {
+    /// Log only.
+    Ignore,
+    /// Cancel the original's still-working orders.
+    Cancel,
+    /// Cancel them and trade the edited signal instead.
+    #[default]
+    Replace,
+}
+
+/// What deleting a signal message does to the orders it produced.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletePolicy {
+    Ignore,
+    /// Cancel the message's still-working orders.
+    #[default]
+    Cancel,
+}
+
+/// Handling of edited and deleted Discord messages. Filled orders are never undone.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct EditsCfg {
+    #[serde(default)]
+    pub on_edit: EditPolicy,
+    #[serde(default)]
+    pub on_delete: DeletePolicy,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -47,6 +269,17 @@ pub struct AppConfig {
     pub risk: RiskCfg,
     pub exec: ExecCfg,
     pub state: StateCfg,
+    #[serde(default)]
+    pub reconcile: ReconcileCfg,
+    #[serde(default)]
+    pub authors: Vec<AuthorCfg>,
+    #[serde(default)]
+    pub sizing: SizingMode,
+    #[serde(default)]
+    pub edits: EditsCfg,
+    /// Signal formats declared in config, usable by name like the built-in grammars.
+    #[serde(default)]
+    pub grammars: Vec<GrammarCfg>,
 }
 
 impl AppConfig {
@@ -55,4 +288,55 @@ impl AppConfig {
         let cfg: Self = serde_yaml::from_str(&s)?;
         Ok(cfg)
     }
+
+    /// The author block for `author` (see [`author_index`]).
+    pub fn author(&self, author: &Author) -> Option<&AuthorCfg> {
+        author_index(&self.authors, author).map(|i| &self.authors[i])
+    }
+
+    /// The author block with attribution key `key` (see [`AuthorCfg::key`]).
+    pub fn author_by_key(&self, key: &str) -> Option<&AuthorCfg> {
+        self.authors.iter().find(|a| a.key() == key)
+    }
+}
+
+/// Index of the block whose `user_id` is `author`'s ID, else of the first whose name
+/// equals the username (case-insensitive). Blocks with a `user_id` only match by name
+/// when the author's ID is unknown (backtests of name-only exports).
+pub fn author_index(authors: &[AuthorCfg], author: &Author) -> Option<usize> {
+    let by_id = author
+        .id
+        .as_ref()
+        .and_then(|id| authors.iter().position(|a| a.user_id.as_ref() == Some(id)));
+    by_id.or_else(|| {
+        authors.iter().position(|a| {
+            (a.user_id.is_none() || author.id.is_none())
+                && a.name.eq_ignore_ascii_case(&author.name)
+        })
+    })
+}
+
+/// Minimal LIMIT-mode config for unit tests; `state.path` is a per-process temp file.
+#[cfg(test)]
+pub(crate) fn test_config(name: &str) -> AppConfig {
+    let path = std::env::temp_dir().join(format!("dwt-{}-{}.db", name, std::process::id()));
+    let yaml = format!(
+        r#"
+discord: {{ channel_ids: [], tracked_users: [] }}
+webull: {{ mode: paper }}
+risk: {{ max_position_value: 10000 }}
+exec:
+  dry_run: false
+  tif: DAY
+  buy_mode: LIMIT
+  sell_mode: LIMIT
+  buy_timeout_sec: 0
+  sell_timeout_sec: 0
+  buy_limit_slippage_pct: 0.0
+  sell_limit_slippage_pct: 0.0
+state: {{ path: "{}", flush_interval_sec: 60 }}
+"#,
+        path.display()
+    );
+    serde_yaml::from_str(&yaml).expect("test config")
 }
@@ -1,60 +1,327 @@
-//! Serenity-self based Discord listener (self-bot). Filters channel and tracked users.
+//! Serenity-self based Discord listener (self-bot). Filters channel and tracked authors.
 
-use serenity_self::all::{Client, EventHandler, GatewayIntents, Message};
+use serenity_self::all::{
+    ChannelId, Client, EventHandler, GatewayIntents, GuildId, Message, MessageId,
+    MessageUpdateEvent, PartialMember, Ready, User, UserId,
+};
 use serenity_self::async_trait;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
-use crate::parser::parse_signal;
-use crate::types::TradeSignal;
+use crate::calendar;
+use crate::config::DiscordCfg;
+use crate::grammar::Grammars;
+use crate::parser::{parse_exit_reply, ExitReply, ParseOutcome};
+use crate::types::{Author, TradeSignal};
+use crate::utils::name_matches;
+
+/// Tracked-author rules from `DiscordCfg`.
+#[derive(Debug, Clone, Default)]
+pub struct AuthorFilter {
+    pub user_ids: Vec<String>,
+    pub usernames: Vec<String>,
+    pub display_names: Vec<String>,
+    pub role_ids: Vec<String>,
+    pub substring: bool,
+}
+
+impl AuthorFilter {
+    pub fn from_cfg(cfg: &DiscordCfg) -> Self {
+        Self {
+            user_ids: cfg.tracked_user_ids.clone(),
+            usernames: cfg.tracked_users.clone(),
+            display_names: cfg.tracked_display_names.clone(),
+            role_ids: cfg.tracked_role_ids.clone(),
+            substring: cfg.substring_match,
+        }
+    }
+
+    /// Whether a message author is tracked: by user ID, role ID, username or any of
+    /// their display names (global name, server nickname).
+    pub fn matches(
+        &self,
+        user_id: u64,
+        username: &str,
+        display_names: &[&str],
+        role_ids: &[u64],
+    ) -> bool {
+        let id = user_id.to_string();
+        self.user_ids.contains(&id)
+            || role_ids
+                .iter()
+                .any(|r| self.role_ids.contains(&r.to_string()))
+            || self
+                .usernames
+                .iter()
+                .any(|p| name_matches(p, username, self.substring))
+            || self.display_names.iter().any(|p| {
+                display_names
+                    .iter()
+                    .any(|n| name_matches(p, n, self.substring))
+            })
+    }
+}
+
+/// What the listener forwards to the trading loop.
+#[derive(Debug)]
+pub enum DiscordEvent {
+    /// A tracked author posted one or more signals.
+    Signals {
+        message_id: String,
+        author: Author,
+        signals: Vec<TradeSignal>,
+    },
+    /// A tracked author edited a message; `signals` is the new content re-parsed.
+    Edited {
+        message_id: String,
+        author: Author,
+        signals: Vec<TradeSignal>,
+    },
+    /// A tracked author replied to `reply_to` with a bare exit ("out", "STC @ 3.10").
+    /// `entry` is the referenced message re-parsed, when Discord included it and its
+    /// author is tracked too.
+    Reply {
+        message_id: String,
+        author: Author,
+        reply_to: String,
+        entry: Option<TradeSignal>,
+        exit: ExitReply,
+    },
+    /// A message in a watched channel was deleted (the author is not known by then).
+    Deleted { message_id: String },
+}
 
 pub struct Handler {
     pub channel_ids: Vec<String>,
-    pub tracked_users: Vec<String>,
-    pub tx: tokio::sync::mpsc::Sender<(String, TradeSignal)>, // (author, parsed signal)
+    pub authors: AuthorFilter,
+    pub grammars: Grammars,
+    pub near_miss_channel: Option<ChannelId>,
+    pub tx: tokio::sync::mpsc::Sender<DiscordEvent>,
+}
+
+impl Handler {
+    fn watched(&self, channel: ChannelId) -> bool {
+        let ch = channel.get().to_string();
+        self.channel_ids.iter().any(|id| id == &ch)
+    }
+
+    /// `content` under the grammar for this channel and author.
+    fn parse(&self, channel: ChannelId, author: &Author, content: &str) -> ParseOutcome {
+        let channel = channel.get().to_string();
+        self.grammars
+            .for_message(Some(&channel), author)
+            .diagnose(content, calendar::eastern_now())
+    }
+
+    /// Log why a tracked message was not traded; near misses are warnings and, with
+    /// `near_miss_channel_id`, posted there so a missed call gets noticed.
+    async fn report(
+        &self,
+        ctx: &serenity_self::all::Context,
+        author: &Author,
+        content: &str,
+        outcome: ParseOutcome,
+    ) {
+        let ParseOutcome::NearMiss(miss) = outcome else {
+            info!("Not a signal from {}: {}", author.name, content);
+            return;
+        };
+        warn!(
+            "Near-miss signal from {}: {} in {:?}",
+            author.name, miss.issue, miss.line
+        );
+        if let Some(channel) = self.near_miss_channel {
+            let note = format!(
+                "Not traded ({}): {}: {}",
+                author.name, miss.issue, miss.line
+            );
+            if let Err(e) = channel.say(&ctx.http, note).await {
+                error!("post near-miss notice failed: {}", e);
+            }
+        }
+    }
+
+    /// `user` by ID and current username.
+    fn author(user: &User) -> Author {
+        Author {
+            id: Some(user.id.get().to_string()),
+            name: user.name.clone(),
+        }
+    }
+
+    fn tracked(&self, user: &User, member: Option<&PartialMember>) -> bool {
+        let mut display_names: Vec<&str> = user.global_name.as_deref().into_iter().collect();
+        let mut role_ids = Vec::new();
+        if let Some(m) = member {
+            display_names.extend(m.nick.as_deref());
+            role_ids.extend(m.roles.iter().map(|r| r.get()));
+        }
+        self.authors
+            .matches(user.id.get(), &user.name, &display_names, &role_ids)
+    }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn message(&self, _ctx: serenity_self::all::Context, msg: Message) {
-        // Channel filter (multiple)
-        let ch = msg.channel_id.get().to_string();
-        if !self.channel_ids.iter().any(|id| id == &ch) {
-            return;
+    /// Log what each configured user and role ID currently resolves to, so a stale or
+    /// mistyped ID shows up at startup rather than as silently ignored signals.
+    async fn ready(&self, ctx: serenity_self::all::Context, ready: Ready) {
+        for id in &self.authors.user_ids {
+            let user = match id.parse::<u64>() {
+                Ok(n) if n > 0 => ctx.http.get_user(UserId::new(n)).await.ok(),
+                _ => None,
+            };
+            match user {
+                Some(u) => info!("Tracking user {} = {}", id, u.name),
+                None => warn!("Tracked user id {} did not resolve to a user", id),
+            }
         }
-        // Author filter (fuzzy, case-insensitive substring)
-        let author_name = msg.author.name.clone();
-        let author_lower = author_name.to_lowercase();
-        let auth_ok = self
-            .tracked_users
-            .iter()
-            .map(|s| s.to_lowercase())
-            .any(|needle| author_lower.contains(&needle));
-        if !auth_ok {
+        for id in &self.authors.role_ids {
+            let mut found = false;
+            for g in &ready.guilds {
+                let Ok(roles) = ctx.http.get_guild_roles(g.id).await else {
+                    continue;
+                };
+                if let Some(r) = roles.iter().find(|r| r.id.get().to_string() == *id) {
+                    info!("Tracking role {} = {} (guild {})", id, r.name, g.id);
+                    found = true;
+                }
+            }
+            if !found {
+                warn!("Tracked role id {} not found in any guild", id);
+            }
+        }
+    }
+
+    async fn message(&self, ctx: serenity_self::all::Context, msg: Message) {
+        // Channel and author filters
+        if !self.watched(msg.channel_id) || !self.tracked(&msg.author, msg.member.as_deref()) {
             return;
         }
 
         let content = msg.content.clone();
-        match parse_signal(&content) {
-            Some(sig) => {
-                let _ = self.tx.send((author_name, sig)).await;
+        let message_id = msg.id.get().to_string();
+        let author = Self::author(&msg.author);
+        let outcome = self.parse(msg.channel_id, &author, &content);
+        let reply = msg.message_reference.as_ref().and_then(|r| r.message_id);
+        let event = if let ParseOutcome::Parsed(signals) = outcome {
+            DiscordEvent::Signals {
+                message_id,
+                author,
+                signals,
             }
-            None => {
-                warn!("Unrecognized signal: {}", content);
+        } else if let (Some(exit), Some(reply_to)) = (parse_exit_reply(&content), reply) {
+            // Someone else's call is not an entry to close; recorded entries are looked
+            // up by message ID when the reply is handled.
+            let mut entry = match msg.referenced_message.as_deref() {
+                Some(m) if self.tracked(&m.author, m.member.as_deref()) => self
+                    .parse(m.channel_id, &Self::author(&m.author), &m.content)
+                    .signals(),
+                _ => Vec::new(),
+            };
+            if entry.len() > 1 {
+                warn!(
+                    "Exit reply from {} to {} with {} signals; not traded",
+                    author.name,
+                    reply_to,
+                    entry.len()
+                );
+                return;
             }
+            DiscordEvent::Reply {
+                message_id,
+                author,
+                reply_to: reply_to.get().to_string(),
+                entry: entry.pop(),
+                exit,
+            }
+        } else {
+            self.report(&ctx, &author, &content, outcome).await;
+            return;
+        };
+        let _ = self.tx.send(event).await;
+    }
+
+    /// Content edits carry the author and new content; other updates (embeds, pins)
+    /// are skipped.
+    async fn message_update(
+        &self,
+        ctx: serenity_self::all::Context,
+        _old: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        let (Some(user), Some(content)) = (&event.author, &event.content) else {
+            return;
+        };
+        let member = event.member.as_ref().and_then(|m| m.as_deref());
+        if !self.watched(event.channel_id) || !self.tracked(user, member) {
+            return;
         }
+        let author = Self::author(user);
+        let signals = match self.parse(event.channel_id, &author, content) {
+            ParseOutcome::Parsed(signals) => signals,
+            outcome => {
+                warn!("Edited message no longer a signal: {}", content);
+                if let ParseOutcome::NearMiss(_) = outcome {
+                    self.report(&ctx, &author, content, outcome).await;
+                }
+                Vec::new()
+            }
+        };
+        let _ = self
+            .tx
+            .send(DiscordEvent::Edited {
+                message_id: event.id.get().to_string(),
+                author,
+                signals,
+            })
+            .await;
+    }
+
+    async fn message_delete(
+        &self,
+        _ctx: serenity_self::all::Context,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        if !self.watched(channel_id) {
+            return;
+        }
+        let _ = self
+            .tx
+            .send(DiscordEvent::Deleted {
+                message_id: deleted_message_id.get().to_string(),
+            })
+            .await;
     }
 }
 
 pub async fn run(
     token: &str,
-    channel_ids: Vec<String>,
-    tracked_users: Vec<String>,
-    tx: tokio::sync::mpsc::Sender<(String, TradeSignal)>,
+    cfg: DiscordCfg,
+    grammars: Grammars,
+    tx: tokio::sync::mpsc::Sender<DiscordEvent>,
 ) -> anyhow::Result<()> {
     let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let near_miss_channel = match cfg.near_miss_channel_id.as_deref() {
+        Some(id) => match id.parse::<u64>() {
+            Ok(n) if n > 0 => Some(ChannelId::new(n)),
+            _ => {
+                warn!(
+                    "near_miss_channel_id {} is not a channel id; notices off",
+                    id
+                );
+                None
+            }
+        },
+        None => None,
+    };
     let handler = Handler {
-        channel_ids,
-        tracked_users,
+        authors: AuthorFilter::from_cfg(&cfg),
+        near_miss_channel,
+        channel_ids: cfg.channel_ids,
+        grammars,
         tx,
     };
 
@@ -66,3 +333,31 @@ pub async fn run(
     client.start().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ids_roles_and_exact_names() {
+        let f = AuthorFilter {
+            user_ids: vec!["42".into()],
+            usernames: vec!["bob".into()],
+            display_names: vec!["Alice Trades".into()],
+            role_ids: vec!["7".into()],
+            substring: false,
+        };
+        assert!(f.matches(42, "renamed", &[], &[]));
+        assert!(f.matches(1, "Bob", &[], &[]));
+        assert!(!f.matches(1, "bobby_scammer", &[], &[]));
+        assert!(f.matches(1, "x", &["alice trades"], &[]));
+        assert!(f.matches(1, "x", &[], &[3, 7]));
+        assert!(!f.matches(1, "x", &["alice"], &[3]));
+
+        let fuzzy = AuthorFilter {
+            substring: true,
+            ..f
+        };
+        assert!(fuzzy.matches(1, "bobby_scammer", &[], &[]));
+    }
+}
@@ -0,0 +1,2484 @@
+//! Signal execution: risk check, order placement and non-blocking fill monitors.
+//!
+//! Generic over [`Broker`] so the same flow runs against Webull or the simulator.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use webull_unofficial::models::{OptionContract, OrderAction, TimeInForce};
+
+use crate::broker::{Broker, OrderInfo, OrderStatus};
+use crate::calendar::{self, Placement, Session};
+use crate::config::{AppConfig, AuthorCfg, DeletePolicy, EditPolicy, OutsideHours};
+use crate::exits::bracket_for_entry;
+use crate::instrument::{contract_multiplier, InstrumentId, OptionKey};
+use crate::parser::ExitReply;
+use crate::reconcile;
+use crate::risk::RiskEngine;
+use crate::sizing::SizingMode;
+use crate::state::BotState;
+use crate::store::{OrderRecord, QueuedSignal, StateStore};
+use crate::types::{
+    Action, Author, ExitBracket, Holding, OptionSignal, OrderType, RelativeExpiry, Side, SpreadLeg,
+    SpreadSignal, StockSignal, TradeSignal,
+};
+use crate::utils::{sanitize_symbol, tif_from_str};
+
+/// Journal key for the `i`th signal of a Discord message: the message ID itself for the
+/// first, "<id>#2", "<id>#3", ... for the rest, so each follows edits on its own and
+/// replies to the message resolve against its first call.
+pub fn signal_key(message_id: &str, i: usize) -> String {
+    match i {
+        0 => message_id.to_string(),
+        _ => format!("{}#{}", message_id, i + 1),
+    }
+}
+
+/// A placed spread leg awaiting fills.
+struct LegOrder {
+    leg: SpreadLeg,
+    contract: OptionContract,
+    side: OrderAction,
+    /// Journal entry of the leg's live order.
+    rec: OrderRecord,
+    /// Earlier orders for the leg (replaced by a MARKET conversion); their fills count.
+    earlier: Vec<String>,
+}
+
+pub struct Executor<B: Broker> {
+    broker: Arc<B>,
+    state: Arc<Mutex<BotState>>,
+    store: Arc<StateStore>,
+    risk: Arc<RiskEngine>,
+    cfg: Arc<AppConfig>,
+    tif: TimeInForce,
+    queued: Arc<Mutex<Vec<QueuedSignal>>>,
+}
+
+impl<B: Broker> Clone for Executor<B> {
+    fn clone(&self) -> Self {
+        Self {
+            broker: Arc::clone(&self.broker),
+            state: Arc::clone(&self.state),
+            store: Arc::clone(&self.store),
+            risk: Arc::clone(&self.risk),
+            cfg: Arc::clone(&self.cfg),
+            tif: self.tif.clone(),
+            queued: Arc::clone(&self.queued),
+        }
+    }
+}
+
+impl<B: Broker + 'static> Executor<B> {
+    pub fn new(
+        broker: Arc<B>,
+        state: Arc<Mutex<BotState>>,
+        store: Arc<StateStore>,
+        risk: RiskEngine,
+        cfg: AppConfig,
+    ) -> Self {
+        let tif = tif_from_str(&cfg.exec.tif);
+        Self {
+            broker,
+            state,
+            store,
+            risk: Arc::new(risk),
+            cfg: Arc::new(cfg),
+            tif,
+            queued: Arc::default(),
+        }
+    }
+
+    pub fn tif(&self) -> &TimeInForce {
+        &self.tif
+    }
+
+    pub fn broker(&self) -> &Arc<B> {
+        &self.broker
+    }
+
+    /// True outside the regular session when `exec.outside_hours` holds or refuses
+    /// signals placed now.
+    pub fn outside_session(&self) -> bool {
+        self.cfg.exec.outside_hours != OutsideHours::Ignore
+            && calendar::session(self.broker.market_time()) != Session::Regular
+    }
+
+    pub fn state(&self) -> Arc<Mutex<BotState>> {
+        Arc::clone(&self.state)
+    }
+
+    /// Reconcile local holdings with the broker snapshot under the configured policy
+    /// and persist.
+    pub async fn sync_holdings(&self) -> anyhow::Result<()> {
+        let holdings = self.broker.positions_simple().await?;
+        let mut st = self.state.lock().await;
+        let rc = &self.cfg.reconcile;
+        let diffs = reconcile::diff(&st.holdings, &holdings, rc.cost_tolerance_pct);
+        reconcile::apply(&mut st, holdings, &diffs, rc.policy);
+        self.store.save(&st)?;
+        Ok(())
+    }
+
+    /// Journal entry for an order placed for `signal`; the deadline follows the
+    /// buy/sell timeout of the signal's action.
+    fn order_record(
+        &self,
+        order_id: String,
+        signal_id: Option<i64>,
+        signal: TradeSignal,
+        side: &OrderAction,
+        qty: f64,
+        limit: Option<f64>,
+    ) -> OrderRecord {
+        let timeout = self.timeout_sec(signal.action());
+        OrderRecord {
+            order_id,
+            signal_id,
+            signal,
+            side: match side {
+                OrderAction::Buy => Side::Buy,
+                OrderAction::Sell => Side::Sell,
+            },
+            qty,
+            limit,
+            deadline: self.broker.market_time() + chrono::Duration::seconds(timeout as i64),
+            leg: None,
+            author: None,
+        }
+    }
+
+    /// Seconds left until a journaled order's deadline (0 when past due). Deadlines are
+    /// on the market clock, so they survive a restart in another time zone.
+    fn time_left(&self, rec: &OrderRecord) -> u64 {
+        (rec.deadline - self.broker.market_time())
+            .num_seconds()
+            .max(0) as u64
+    }
+
+    /// Journal a placed order; it stays open until its monitor books the fills.
+    fn journal(&self, rec: &OrderRecord) {
+        if let Err(e) = self.store.record_order(rec) {
+            error!("journal order {} failed: {:#}", rec.order_id, e);
+        }
+    }
+
+    /// Persist the state and close the journal entries of `order_ids` in one transaction.
+    /// Failures are logged; the in-memory state stays current.
+    fn finish(&self, st: &BotState, order_ids: &[&str]) {
+        if let Err(e) = self.store.save_finished(st, order_ids) {
+            error!("persist state failed: {:#}", e);
+        }
+    }
+
+    /// Risk-check and place one signal. When an order is placed, its fill monitor is
+    /// spawned with `spawn_local` (must run inside a `LocalSet`) and the handle returned.
+    pub async fn handle_signal(&self, signal: TradeSignal) -> Option<JoinHandle<()>> {
+        self.handle(None, None, signal).await
+    }
+
+    /// [`Executor::handle_signal`] for a signal posted by `author`, under their
+    /// `authors` config block when one matches.
+    pub async fn handle_author_signal(
+        &self,
+        author: &Author,
+        signal: TradeSignal,
+    ) -> Option<JoinHandle<()>> {
+        self.handle(Some(author), None, signal).await
+    }
+
+    /// [`Executor::handle_author_signal`] for the configured author with attribution key
+    /// `key` (see `AuthorCfg::key`), e.g. an exit fired from their entry's bracket.
+    /// Unknown keys (the block was removed) are handled without an author.
+    pub async fn handle_attributed_signal(
+        &self,
+        key: &str,
+        signal: TradeSignal,
+    ) -> Option<JoinHandle<()>> {
+        let author = self.cfg.author_by_key(key).map(AuthorCfg::as_author);
+        self.handle(author.as_ref(), None, signal).await
+    }
+
+    /// [`Executor::handle_author_signal`] for a Discord message, remembered so later
+    /// edits and deletions of the message can reach its orders.
+    pub async fn handle_message_signal(
+        &self,
+        message_id: &str,
+        author: &Author,
+        signal: TradeSignal,
+    ) -> Option<JoinHandle<()>> {
+        self.handle(Some(author), Some(message_id), signal).await
+    }
+
+    /// Trade a bare exit posted as a reply to `reply_to`. The instrument comes from the
+    /// signal recorded for that message, falling back to `entry` (the referenced message
+    /// re-parsed) when it was never traded here. A reply to a message that carried
+    /// several signals is refused, since it does not say which one it closes.
+    pub async fn handle_reply(
+        &self,
+        message_id: &str,
+        author: &Author,
+        reply_to: &str,
+        entry: Option<TradeSignal>,
+        exit: &ExitReply,
+    ) -> Option<JoinHandle<()>> {
+        if self.message_keys(reply_to, 1).len() > 1 {
+            warn!(
+                "reply {} to {}: message has several signals; not traded",
+                message_id, reply_to
+            );
+            return None;
+        }
+        let recorded = match self.store.message_signal(reply_to) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("look up replied-to message {} failed: {:#}", reply_to, e);
+                None
+            }
+        };
+        let Some(entry) = recorded.or(entry) else {
+            warn!(
+                "reply {} to {}: no entry signal found",
+                message_id, reply_to
+            );
+            return None;
+        };
+        let Some(signal) = exit.against(&entry) else {
+            warn!(
+                "reply {} does not close entry {}: {:?}",
+                message_id,
+                entry.label(),
+                exit
+            );
+            return None;
+        };
+        info!("reply {} to {} -> {}", message_id, reply_to, signal.label());
+        self.handle(Some(author), Some(message_id), signal).await
+    }
+
+    /// Trade every signal of a Discord message, each under its own [`signal_key`].
+    pub async fn handle_message_signals(
+        &self,
+        message_id: &str,
+        author: &Author,
+        signals: Vec<TradeSignal>,
+    ) -> Vec<JoinHandle<()>> {
+        let mut handles = Vec::new();
+        for (i, sig) in signals.into_iter().enumerate() {
+            let key = signal_key(message_id, i);
+            handles.extend(self.handle_message_signal(&key, author, sig).await);
+        }
+        handles
+    }
+
+    /// Apply `edits.on_edit` to an edited message; `signals` is the edited content
+    /// re-parsed (empty when it no longer parses). Signals are matched up by position,
+    /// so editing the second call of a message leaves the first one alone.
+    pub async fn handle_edit(
+        &self,
+        message_id: &str,
+        author: &Author,
+        signals: Vec<TradeSignal>,
+    ) -> Vec<JoinHandle<()>> {
+        let keys = self.message_keys(message_id, signals.len());
+        let mut signals = signals.into_iter();
+        let mut handles = Vec::new();
+        for key in keys {
+            handles.extend(self.edit_signal(&key, author, signals.next()).await);
+        }
+        handles
+    }
+
+    /// One signal of an edited message. Working orders from the original are canceled,
+    /// and their monitors book whatever filled. With `replace` the edited signal is then
+    /// traded, unless every order of the original had already finished, so a late fix-up
+    /// never doubles a filled trade.
+    async fn edit_signal(
+        &self,
+        key: &str,
+        author: &Author,
+        signal: Option<TradeSignal>,
+    ) -> Option<JoinHandle<()>> {
+        let policy = self.cfg.edits.on_edit;
+        if policy == EditPolicy::Ignore {
+            return None;
+        }
+        self.unqueue(key).await;
+        let (before, orders) = match (
+            self.store.message_signal(key),
+            self.store.message_orders(key),
+        ) {
+            (Ok(b), Ok(o)) => (b, o),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("look up edited message {} failed: {:#}", key, e);
+                return None;
+            }
+        };
+        if let (Some(a), Some(b)) = (&before, &signal) {
+            if serde_json::to_value(a).ok() == serde_json::to_value(b).ok() {
+                return None;
+            }
+        }
+        if !orders.is_empty() && orders.iter().all(|(_, done)| *done) {
+            warn!(
+                "message {} edited after its orders finished; not amended",
+                key
+            );
+            return None;
+        }
+        let working: Vec<&OrderRecord> = orders
+            .iter()
+            .filter(|(_, done)| !done)
+            .map(|(o, _)| o)
+            .collect();
+        self.cancel_orders(&working, "edited").await;
+        match signal {
+            Some(sig) if policy == EditPolicy::Replace => {
+                info!("message {} edited -> {}", key, sig.label());
+                self.handle(Some(author), Some(key), sig).await
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply `edits.on_delete` to a deleted message.
+    pub async fn handle_delete(&self, message_id: &str) {
+        if self.cfg.edits.on_delete == DeletePolicy::Ignore {
+            return;
+        }
+        for key in self.message_keys(message_id, 1) {
+            self.unqueue(&key).await;
+            let orders = match self.store.message_orders(&key) {
+                Ok(o) => o,
+                Err(e) => {
+                    error!("look up deleted message {} failed: {:#}", key, e);
+                    continue;
+                }
+            };
+            let working: Vec<&OrderRecord> = orders
+                .iter()
+                .filter(|(_, done)| !done)
+                .map(|(o, _)| o)
+                .collect();
+            self.cancel_orders(&working, "deleted").await;
+        }
+    }
+
+    /// Keys of a message's signals: at least `n`, plus any further ones recorded.
+    fn message_keys(&self, message_id: &str, n: usize) -> Vec<String> {
+        let mut keys: Vec<String> = (0..n).map(|i| signal_key(message_id, i)).collect();
+        loop {
+            let key = signal_key(message_id, keys.len());
+            match self.store.message_signal(&key) {
+                Ok(Some(_)) => keys.push(key),
+                _ => return keys,
+            }
+        }
+    }
+
+    /// Cancel working orders of an edited or deleted message; their monitors see the
+    /// cancel on the next poll and book any partial fill.
+    async fn cancel_orders(&self, orders: &[&OrderRecord], why: &str) {
+        for o in orders {
+            match self.broker.cancel_order(&o.order_id).await {
+                Ok(()) => info!(
+                    "Canceled order {} ({}): signal message {}",
+                    o.order_id,
+                    o.signal.label(),
+                    why
+                ),
+                Err(e) => error!("cancel order {} failed: {:#}", o.order_id, e),
+            }
+        }
+    }
+
+    async fn handle(
+        &self,
+        author: Option<&Author>,
+        message_id: Option<&str>,
+        mut signal: TradeSignal,
+    ) -> Option<JoinHandle<()>> {
+        let signal_id = match self.store.record_signal(&signal, author, message_id) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                error!("record signal failed: {:#}", e);
+                None
+            }
+        };
+        let acfg = author.and_then(|a| self.cfg.author(a));
+        if let Some(a) = acfg {
+            if !a.follows(&signal) {
+                info!(
+                    "{}: signal kind not followed, skipped: {}",
+                    a.name,
+                    signal.label()
+                );
+                return None;
+            }
+            let held = self.state.lock().await.held_for(&signal);
+            a.resize(&mut signal, held);
+        }
+        self.place(author, message_id, signal_id, signal).await
+    }
+
+    /// Place `signal` now, as an extended-hours order, or hold it for the open, as the
+    /// market calendar decides under `exec.outside_hours`.
+    async fn place(
+        &self,
+        author: Option<&Author>,
+        key: Option<&str>,
+        signal_id: Option<i64>,
+        signal: TradeSignal,
+    ) -> Option<JoinHandle<()>> {
+        let acfg = author.and_then(|a| self.cfg.author(a));
+        let policy = self.cfg.exec.outside_hours;
+        let now = self.broker.market_time();
+        if let Err(e) = self.risk.check_session(&signal, now) {
+            error!("risk rejected: {:#}", e);
+            return None;
+        }
+        let is_limit = !self.is_market(signal.action(), acfg);
+        let extended = match calendar::placement(policy, now, &signal, is_limit) {
+            Placement::Now => false,
+            Placement::Extended => true,
+            Placement::Queue(open) => {
+                info!("{} queued for the open at {}", signal.label(), open);
+                let q = QueuedSignal {
+                    key: key.map(str::to_string),
+                    author: author.cloned(),
+                    signal_id,
+                    signal,
+                };
+                if let Err(e) = self.store.queue_signal(&q) {
+                    error!("persist queued signal failed: {:#}", e);
+                }
+                self.queued.lock().await.push(q);
+                return None;
+            }
+            Placement::Reject(why) => {
+                error!("{} not placed: {}", signal.label(), why);
+                return None;
+            }
+        };
+        if signal.action().is_opening() {
+            self.check_daily_loss().await;
+        }
+        match signal {
+            TradeSignal::Stock(s) => self.execute_stock(s, signal_id, acfg, extended).await,
+            TradeSignal::Option(o) => self.execute_option(o, signal_id, acfg).await,
+            TradeSignal::Spread(sp) => self.execute_spread(sp, signal_id, acfg).await,
+        }
+    }
+
+    /// Place the signals queued outside the regular session once it is open; call this
+    /// periodically.
+    pub async fn release_queued(&self) -> Vec<JoinHandle<()>> {
+        if calendar::session(self.broker.market_time()) != Session::Regular {
+            return Vec::new();
+        }
+        let queued = std::mem::take(&mut *self.queued.lock().await);
+        if queued.is_empty() {
+            return Vec::new();
+        }
+        if let Err(e) = self.store.unqueue_signals(None) {
+            error!("clear queued signals failed: {:#}", e);
+        }
+        let mut handles = Vec::new();
+        for q in queued {
+            info!("market open: placing queued {}", q.signal.label());
+            let (author, key) = (q.author.as_ref(), q.key.as_deref());
+            handles.extend(self.place(author, key, q.signal_id, q.signal).await);
+        }
+        handles
+    }
+
+    /// Reload the signals queued for the open before a restart.
+    pub async fn recover_queued(&self) -> usize {
+        match self.store.queued_signals() {
+            Ok(queued) => {
+                let n = queued.len();
+                *self.queued.lock().await = queued;
+                n
+            }
+            Err(e) => {
+                error!("read queued signals failed: {:#}", e);
+                0
+            }
+        }
+    }
+
+    /// Drop queued signals of an edited or deleted message.
+    async fn unqueue(&self, key: &str) {
+        if let Err(e) = self.store.unqueue_signals(Some(key)) {
+            error!("unqueue message {} failed: {:#}", key, e);
+        }
+        self.queued.lock().await.retain(|q| {
+            let drop = q.key.as_deref() == Some(key);
+            if drop {
+                info!(
+                    "dropped queued {}: message {} changed",
+                    q.signal.label(),
+                    key
+                );
+            }
+            !drop
+        });
+    }
+
+    /// Contract for `key`. A relative expiry ("0DTE", "weekly") resolved on the calendar
+    /// may not be listed for this underlying; the closest listed expiry at the same
+    /// strike and side is used instead and written back to `key`.
+    async fn find_contract(
+        &self,
+        key: &mut OptionKey,
+        relative: Option<RelativeExpiry>,
+    ) -> anyhow::Result<OptionContract> {
+        let err = match self.broker.find_option_contract(key).await {
+            Ok(c) => return Ok(c),
+            Err(e) => e,
+        };
+        let Some(rel) = relative else {
+            return Err(err);
+        };
+        let listed = self.broker.listed_expiries(key).await?;
+        let Some(expiry) = calendar::closest_expiry(&listed, key.expiry) else {
+            return Err(err);
+        };
+        warn!(
+            "{:?} expiry {} not listed for {}; using {}",
+            rel, key.expiry, key, expiry
+        );
+        key.expiry = expiry;
+        self.broker.find_option_contract(key).await
+    }
+
+    /// Re-evaluate the day's P/L (realized today + unrealized at current marks) and
+    /// record a persisted trading halt once it breaches `risk.max_daily_loss`. No-op when
+    /// the limit is disabled or today is already halted.
+    pub async fn check_daily_loss(&self) {
+        let today = self.broker.trade_date();
+        let holdings = {
+            let st = self.state.lock().await;
+            if !self.risk.daily_loss_enabled() || st.halted_on == Some(today) {
+                return;
+            }
+            st.holdings.clone()
+        };
+        let unrealized = self.unrealized_pl(&holdings).await;
+        let mut st = self.state.lock().await;
+        if self.risk.update_daily_loss(&mut st, today, unrealized) {
+            if let Err(e) = self.store.save(&st) {
+                error!("persist trading halt failed: {:#}", e);
+            }
+        }
+    }
+
+    /// Unrealized P/L of `holdings` at current mid prices (options ×100). Holdings
+    /// without a quote count as flat.
+    async fn unrealized_pl(&self, holdings: &[Holding]) -> f64 {
+        let mut total = 0.0;
+        for h in holdings {
+            let pl = match h {
+                Holding::Stock {
+                    symbol,
+                    quantity,
+                    avg_cost,
+                } => self
+                    .mark(&InstrumentId::stock(symbol))
+                    .await
+                    .map(|m| (m - avg_cost) * quantity),
+                Holding::Option {
+                    key,
+                    quantity,
+                    avg_cost,
+                } => self
+                    .mark(&InstrumentId::Option(key.clone()))
+                    .await
+                    .map(|m| (m - avg_cost) * *quantity as f64 * key.multiplier()),
+                Holding::Spread {
+                    symbol,
+                    legs,
+                    expiry,
+                    quantity,
+                    avg_cost,
+                    ..
+                } => {
+                    let mut net = Some(0.0);
+                    for l in legs {
+                        let key = OptionKey::new(symbol, l.strike, l.call_put, *expiry);
+                        let m = self.mark(&InstrumentId::Option(key)).await;
+                        net = net.zip(m).map(|(n, m)| n + l.ratio as f64 * m);
+                    }
+                    net.map(|n| (n - avg_cost) * *quantity as f64 * contract_multiplier(symbol))
+                }
+            };
+            match pl {
+                Some(v) => total += v,
+                None => warn!("no mark for {}; counted flat", h.label()),
+            }
+        }
+        total
+    }
+
+    async fn mark(&self, id: &InstrumentId) -> Option<f64> {
+        let tid = match id {
+            InstrumentId::Stock(s) => self.broker.find_stock_ticker_id(s).await.ok()?,
+            InstrumentId::Option(k) => self.broker.find_option_contract(k).await.ok()?.ticker_id,
+        };
+        self.broker.mid_price(tid).await.ok().filter(|m| *m > 0.0)
+    }
+
+    /// Whether the configured mode for this action is MARKET. Entries (BTO/STO) follow
+    /// `buy_mode`, exits (STC/BTC) follow `sell_mode`; the author's overrides win.
+    fn is_market(&self, action: Action, author: Option<&AuthorCfg>) -> bool {
+        let exec = &self.cfg.exec;
+        let mode = if action.is_opening() {
+            author
+                .and_then(|a| a.buy_mode.as_ref())
+                .unwrap_or(&exec.buy_mode)
+        } else {
+            author
+                .and_then(|a| a.sell_mode.as_ref())
+                .unwrap_or(&exec.sell_mode)
+        };
+        mode.eq_ignore_ascii_case("MARKET")
+    }
+
+    /// Monitor timeout for this action: `buy_timeout_sec` for entries, `sell_timeout_sec`
+    /// for exits. With `exec.outside_hours` set, an exit that would time out outside the
+    /// regular session waits until the next open plus its timeout, since the MARKET
+    /// order it converts to would not be accepted before then.
+    fn timeout_sec(&self, action: Action) -> u64 {
+        if action.is_opening() {
+            return self.cfg.exec.buy_timeout_sec;
+        }
+        let secs = self.cfg.exec.sell_timeout_sec;
+        if self.cfg.exec.outside_hours == OutsideHours::Ignore {
+            return secs;
+        }
+        let now = self.broker.market_time();
+        let due = now + chrono::Duration::seconds(secs as i64);
+        if calendar::session(due) == Session::Regular {
+            return secs;
+        }
+        (calendar::next_open(due) - now).num_seconds().max(0) as u64 + secs
+    }
+
+    /// Entry quantity under the configured sizing mode, scaled by the author's
+    /// `qty_multiplier`; None keeps the signal's. Authors with `fixed_qty` are not sized.
+    async fn size_entry(
+        &self,
+        signal: &TradeSignal,
+        est_price: f64,
+        author: Option<&AuthorCfg>,
+    ) -> anyhow::Result<Option<u32>> {
+        let mode = self.cfg.sizing;
+        if mode == SizingMode::Signal || author.is_some_and(|a| a.fixed_qty.is_some()) {
+            return Ok(None);
+        }
+        let account = if mode.needs_account_value() {
+            Some(self.broker.net_liquidation().await?)
+        } else {
+            None
+        };
+        let Some(mut qty) = mode.size(signal, est_price, account)? else {
+            return Ok(None);
+        };
+        if let Some(m) = author.and_then(|a| a.qty_multiplier) {
+            qty = ((qty as f64 * m).floor() as u32).max(1);
+        }
+        info!(
+            "Sized {} at {:.4} -> {} ({:?})",
+            signal.label(),
+            est_price,
+            qty,
+            mode
+        );
+        Ok(Some(qty))
+    }
+
+    /// Effective limit in LIMIT mode: signal price (or estimate) adjusted by slippage,
+    /// up for buy-side orders and down for sell-side orders.
+    fn effective_limit(&self, action: Action, limit_price: Option<f64>, est_price: f64) -> f64 {
+        let base = limit_price.unwrap_or(est_price);
+        let adj = match order_side(action) {
+            OrderAction::Buy => 1.0 + self.cfg.exec.buy_limit_slippage_pct,
+            OrderAction::Sell => 1.0 - self.cfg.exec.sell_limit_slippage_pct,
+        };
+        base * adj
+    }
+
+    /// `extended` places the order for the pre-market / after-hours session.
+    async fn execute_stock(
+        &self,
+        mut s: StockSignal,
+        signal_id: Option<i64>,
+        author: Option<&AuthorCfg>,
+        extended: bool,
+    ) -> Option<JoinHandle<()>> {
+        let symbol = sanitize_symbol(&s.symbol);
+        s.symbol = symbol.clone();
+        if let Some(rel) = s.relative_qty.take() {
+            let held = self.state.lock().await.position_qty_stock(&symbol);
+            s.quantity = rel.resolve(closable(s.action, held));
+            info!(
+                "Resolved {:?} of {} {} held -> {}",
+                rel, held, symbol, s.quantity
+            );
+            if s.quantity == 0 {
+                error!("nothing to close: no {} shares held", symbol);
+                return None;
+            }
+        }
+        let tid = match self.broker.find_stock_ticker_id(&symbol).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("find stock ticker failed: {:#}", e);
+                return None;
+            }
+        };
+
+        // Base price for risk & possible derived limit when needed
+        let est_price = if let (OrderType::Limit, Some(p)) = (s.order_type, s.limit_price) {
+            p
+        } else {
+            self.broker.mid_price(tid).await.unwrap_or(0.0)
+        };
+
+        if s.action.is_opening() {
+            let signal = TradeSignal::Stock(s.clone());
+            match self.size_entry(&signal, est_price, author).await {
+                Ok(Some(q)) => s.quantity = q,
+                Ok(None) => {}
+                Err(e) => {
+                    error!("sizing failed: {:#}", e);
+                    return None;
+                }
+            }
+        }
+
+        // risk check reads state under lock
+        {
+            let today = self.broker.trade_date();
+            let st = self.state.lock().await;
+            if let Err(e) = self.risk.pre_check(
+                &TradeSignal::Stock(s.clone()),
+                est_price,
+                &st,
+                today,
+                author,
+            ) {
+                error!("risk rejected: {:#}", e);
+                return None;
+            }
+        }
+
+        if self.cfg.exec.dry_run {
+            info!(
+                "[DRY-RUN] STOCK {:?} {} @ {:?}",
+                s.action,
+                symbol,
+                s.limit_price.unwrap_or(est_price)
+            );
+            return None;
+        }
+
+        let side = order_side(s.action);
+        let qty = s.quantity as f64;
+
+        // Choose mode & compute effective limit price if needed
+        let is_market = self.is_market(s.action, author);
+        let limit = (!is_market).then(|| self.effective_limit(s.action, s.limit_price, est_price));
+        let order_id = match limit {
+            None => {
+                self.broker
+                    .place_stock_market(&symbol, qty, side.clone(), &self.tif)
+                    .await
+            }
+            Some(limit) if extended => {
+                self.broker
+                    .place_stock_limit_extended(&symbol, qty, side.clone(), limit, &self.tif)
+                    .await
+            }
+            Some(limit) => {
+                self.broker
+                    .place_stock_limit(&symbol, qty, side.clone(), limit, &self.tif)
+                    .await
+            }
+        };
+        let order_id = match order_id {
+            Ok(id) => id,
+            Err(e) => {
+                error!("place stock order failed: {:#}", e);
+                return None;
+            }
+        };
+        info!("Placed STOCK order id={}", order_id);
+        let signal = TradeSignal::Stock(s.clone());
+        let author = author.map(|a| a.key().to_string());
+        let bracket = bracket_for_entry(&signal, tid, author.clone());
+        let mut rec = self.order_record(order_id, signal_id, signal, &side, qty, limit);
+        rec.author = author;
+        self.journal(&rec);
+
+        // ---- spawn monitor task (NON-blocking) ----
+        let this = self.clone();
+        let timeout = self.timeout_sec(s.action);
+        Some(tokio::task::spawn_local(async move {
+            if s.action.is_opening() {
+                this.monitor_open_stock(&rec, s.action, &symbol, bracket, timeout)
+                    .await;
+            } else {
+                this.monitor_close_stock(rec, s.action, &symbol, timeout)
+                    .await;
+            }
+        }))
+    }
+
+    async fn execute_option(
+        &self,
+        mut o: OptionSignal,
+        signal_id: Option<i64>,
+        author: Option<&AuthorCfg>,
+    ) -> Option<JoinHandle<()>> {
+        o.key.symbol = sanitize_symbol(&o.key.symbol);
+        let relative = o.relative_expiry.take();
+        let contract = match self.find_contract(&mut o.key, relative).await {
+            Ok(c) => c,
+            Err(e) => {
+                error!("find option contract failed: {:#}", e);
+                return None;
+            }
+        };
+
+        if let Some(rel) = o.relative_qty.take() {
+            let held = self.state.lock().await.position_qty_option(&o.key);
+            o.quantity = rel.resolve(closable(o.action, held as f64));
+            info!(
+                "Resolved {:?} of {} {} held -> {}",
+                rel, held, o.key, o.quantity
+            );
+            if o.quantity == 0 {
+                error!("nothing to close: no {} contracts held", o.key);
+                return None;
+            }
+        }
+
+        // Base price for risk & possible derived limit when needed
+        let est_price = if let (OrderType::Limit, Some(p)) = (o.order_type, o.limit_price) {
+            p
+        } else {
+            self.broker
+                .mid_price(contract.ticker_id)
+                .await
+                .unwrap_or(0.0)
+        };
+
+        if o.action.is_opening() {
+            let signal = TradeSignal::Option(o.clone());
+            match self.size_entry(&signal, est_price, author).await {
+                Ok(Some(q)) => o.quantity = q,
+                Ok(None) => {}
+                Err(e) => {
+                    error!("sizing failed: {:#}", e);
+                    return None;
+                }
+            }
+        }
+
+        {
+            let today = self.broker.trade_date();
+            let st = self.state.lock().await;
+            if let Err(e) = self.risk.pre_check(
+                &TradeSignal::Option(o.clone()),
+                est_price,
+                &st,
+                today,
+                author,
+            ) {
+                error!("risk rejected: {:#}", e);
+                return None;
+            }
+        }
+
+        if self.cfg.exec.dry_run {
+            info!(
+                "[DRY-RUN] OPTION {:?} {} @ {:?}",
+                o.action,
+                o.key,
+                o.limit_price.unwrap_or(est_price)
+            );
+            return None;
+        }
+
+        let side = order_side(o.action);
+        let qty = o.quantity as f64;
+
+        // Choose mode & compute effective limit price if needed
+        let is_market = self.is_market(o.action, author);
+        let limit = (!is_market).then(|| self.effective_limit(o.action, o.limit_price, est_price));
+        let order_id = match limit {
+            None => {
+                self.broker
+                    .place_option_market(&contract, qty, side.clone(), &self.tif)
+                    .await
+            }
+            Some(limit) => {
+                self.broker
+                    .place_option_limit(&contract, qty, side.clone(), limit, &self.tif)
+                    .await
+            }
+        };
+        let order_id = match order_id {
+            Ok(id) => id,
+            Err(e) => {
+                error!("place option order failed: {:#}", e);
+                return None;
+            }
+        };
+        info!("Placed OPTION order id={}", order_id);
+        let signal = TradeSignal::Option(o.clone());
+        let author = author.map(|a| a.key().to_string());
+        let bracket = bracket_for_entry(&signal, contract.ticker_id, author.clone());
+        let mut rec = self.order_record(order_id, signal_id, signal, &side, qty, limit);
+        rec.author = author;
+        self.journal(&rec);
+
+        // ---- spawn monitor task (NON-blocking) ----
+        let this = self.clone();
+        let timeout = self.timeout_sec(o.action);
+        Some(tokio::task::spawn_local(async move {
+            if o.action.is_opening() {
+                this.monitor_open_option(&rec, &o, bracket, timeout).await;
+            } else {
+                this.monitor_close_option(rec, &o, &contract, timeout).await;
+            }
+        }))
+    }
+
+    async fn execute_spread(
+        &self,
+        mut sp: SpreadSignal,
+        signal_id: Option<i64>,
+        author: Option<&AuthorCfg>,
+    ) -> Option<JoinHandle<()>> {
+        sp.symbol = sanitize_symbol(&sp.symbol);
+        let label = sp.label();
+        if sp.action.is_short() {
+            error!("spreads are opened with BTO and closed with STC: {}", label);
+            return None;
+        }
+
+        // Resolve every leg before touching the market; the other legs follow the first
+        // leg's expiry when a relative one falls back to another listed date
+        let mut contracts = Vec::with_capacity(sp.legs.len());
+        let mut relative = sp.relative_expiry.take();
+        for leg in sp.legs.clone() {
+            let mut key = sp.leg_key(&leg);
+            match self.find_contract(&mut key, relative.take()).await {
+                Ok(c) => {
+                    sp.expiry = key.expiry;
+                    contracts.push(c);
+                }
+                Err(e) => {
+                    error!(
+                        "find spread leg {}{} failed: {:#}",
+                        leg.strike, leg.call_put, e
+                    );
+                    return None;
+                }
+            }
+        }
+
+        if let Some(rel) = sp.relative_qty.take() {
+            let held = self.state.lock().await.position_qty_spread(&sp);
+            sp.quantity = rel.resolve(held as f64);
+            info!(
+                "Resolved {:?} of {} {} held -> {}",
+                rel, held, label, sp.quantity
+            );
+            if sp.quantity == 0 {
+                error!("nothing to close: no {} spreads held", label);
+                return None;
+            }
+        }
+        let mut mids = Vec::with_capacity(contracts.len());
+        for c in &contracts {
+            mids.push(self.broker.mid_price(c.ticker_id).await.unwrap_or(0.0));
+        }
+        let net_mid: f64 = sp
+            .legs
+            .iter()
+            .zip(&mids)
+            .map(|(l, m)| l.ratio as f64 * m)
+            .sum();
+
+        // Base net price for risk & possible derived limit when needed
+        let est_price = if let (OrderType::Limit, Some(p)) = (sp.order_type, sp.limit_price) {
+            p
+        } else {
+            net_mid
+        };
+
+        if sp.action.is_opening() {
+            let signal = TradeSignal::Spread(sp.clone());
+            match self.size_entry(&signal, est_price, author).await {
+                Ok(Some(q)) => sp.quantity = q,
+                Ok(None) => {}
+                Err(e) => {
+                    error!("sizing failed: {:#}", e);
+                    return None;
+                }
+            }
+        }
+
+        {
+            let today = self.broker.trade_date();
+            let st = self.state.lock().await;
+            if let Err(e) = self.risk.pre_check(
+                &TradeSignal::Spread(sp.clone()),
+                est_price,
+                &st,
+                today,
+                author,
+            ) {
+                error!("risk rejected: {:#}", e);
+                return None;
+            }
+        }
+
+        if self.cfg.exec.dry_run {
+            info!(
+                "[DRY-RUN] SPREAD {:?} {} @ {:?}",
+                sp.action, label, est_price
+            );
+            return None;
+        }
+
+        // Opening buys long legs and sells short legs; closing does the reverse.
+        // In LIMIT mode the gap between the net limit and the net mid is spread evenly
+        // across legs so the leg limits add up to the net limit.
+        let is_market = self.is_market(sp.action, author);
+        let net_limit = self.effective_limit(sp.action, sp.limit_price, est_price);
+        let adj = (net_limit - net_mid) / (sp.legs.len() as f64);
+        let qty = sp.quantity as f64;
+        let mut legs: Vec<LegOrder> = Vec::with_capacity(sp.legs.len());
+        for (i, ((leg, contract), mid)) in sp.legs.iter().zip(contracts).zip(&mids).enumerate() {
+            let side = match (sp.action, leg.ratio > 0) {
+                (Action::BTO, true) | (Action::STC, false) => OrderAction::Buy,
+                _ => OrderAction::Sell,
+            };
+            let limit = (!is_market).then(|| (mid + leg.ratio.signum() as f64 * adj).max(0.01));
+            let placed = match limit {
+                None => {
+                    self.broker
+                        .place_option_market(&contract, qty, side.clone(), &self.tif)
+                        .await
+                }
+                Some(limit) => {
+                    self.broker
+                        .place_option_limit(&contract, qty, side.clone(), limit, &self.tif)
+                        .await
+                }
+            };
+            match placed {
+                Ok(order_id) => {
+                    let group = legs
+                        .first()
+                        .map_or(order_id.clone(), |l| l.rec.order_id.clone());
+                    let signal = TradeSignal::Spread(sp.clone());
+                    let mut rec = self.order_record(order_id, signal_id, signal, &side, qty, limit);
+                    rec.leg = Some((i, group));
+                    rec.author = author.map(|a| a.key().to_string());
+                    self.journal(&rec);
+                    legs.push(LegOrder {
+                        leg: *leg,
+                        contract,
+                        side,
+                        rec,
+                        earlier: Vec::new(),
+                    });
+                }
+                Err(e) => {
+                    error!("place spread leg failed: {:#}", e);
+                    self.abandon_legs(&legs).await;
+                    return None;
+                }
+            }
+        }
+        info!(
+            "Placed SPREAD {} order ids={:?}",
+            label,
+            legs.iter()
+                .map(|l| l.rec.order_id.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // ---- spawn monitor task (NON-blocking) ----
+        let this = self.clone();
+        let timeout = self.timeout_sec(sp.action);
+        Some(tokio::task::spawn_local(async move {
+            this.monitor_spread(&sp, legs, is_market, timeout).await;
+        }))
+    }
+
+    /// Cancel the legs of a spread that could not be placed in full and close their
+    /// journal entries.
+    async fn abandon_legs(&self, legs: &[LegOrder]) {
+        for l in legs {
+            let _ = self.broker.cancel_order(&l.rec.order_id).await;
+        }
+        let ids: Vec<&str> = legs
+            .iter()
+            .flat_map(|l| l.earlier.iter().chain([&l.rec.order_id]))
+            .map(String::as_str)
+            .collect();
+        let st = self.state.lock().await;
+        self.finish(&st, &ids);
+    }
+
+    // ---------------- Monitoring & state updates ----------------
+
+    async fn poll_until_filled(&self, order_id: &str, max_sec: u64) -> anyhow::Result<OrderInfo> {
+        let mut infos = self.poll_all_until_filled(&[order_id], max_sec).await;
+        infos.pop().expect("one order polled")
+    }
+
+    /// Poll several orders in one loop until each is filled, canceled or rejected, or
+    /// `max_sec` has passed for all of them; one result per order, in order. An order
+    /// whose lookup fails is not polled again.
+    async fn poll_all_until_filled(
+        &self,
+        order_ids: &[&str],
+        max_sec: u64,
+    ) -> Vec<anyhow::Result<OrderInfo>> {
+        let start = std::time::Instant::now();
+        let mut infos: Vec<Option<anyhow::Result<OrderInfo>>> =
+            order_ids.iter().map(|_| None).collect();
+        loop {
+            for (id, info) in order_ids.iter().zip(infos.iter_mut()) {
+                let done = match info {
+                    Some(Ok(i)) => matches!(
+                        i.status,
+                        OrderStatus::Filled | OrderStatus::Canceled | OrderStatus::Rejected
+                    ),
+                    Some(Err(_)) => true,
+                    None => false,
+                };
+                if !done {
+                    *info = Some(self.broker.get_order_info(id).await);
+                }
+            }
+            let pending = infos.iter().any(|i| {
+                matches!(
+                    i,
+                    Some(Ok(OrderInfo {
+                        status: OrderStatus::PartiallyFilled
+                            | OrderStatus::Working
+                            | OrderStatus::Unknown(_),
+                        ..
+                    }))
+                )
+            });
+            if !pending || start.elapsed() >= Duration::from_secs(max_sec) {
+                return infos.into_iter().flatten().collect();
+            }
+            tokio::time::sleep(Duration::from_millis(800)).await;
+        }
+    }
+
+    async fn monitor_open_stock(
+        &self,
+        rec: &OrderRecord,
+        action: Action,
+        symbol: &str,
+        bracket: Option<ExitBracket>,
+        timeout: u64,
+    ) {
+        let date = self.broker.trade_date();
+        let order_id = rec.order_id.as_str();
+        let info = match self.poll_until_filled(order_id, timeout).await {
+            Ok(i) => i,
+            Err(e) => {
+                error!("poll {:?} stock failed: {:#}", action, e);
+                return;
+            }
+        };
+        let filled = match info.status {
+            OrderStatus::Filled => rec.qty,
+            OrderStatus::PartiallyFilled => {
+                let _ = self.broker.cancel_order(order_id).await;
+                info.filled_qty
+            }
+            OrderStatus::Working | OrderStatus::Unknown(_) => {
+                let _ = self.broker.cancel_order(order_id).await;
+                info!("{:?} stock timeout -> canceled pending order", action);
+                0.0
+            }
+            // canceled elsewhere (e.g. the signal message was edited): keep what filled
+            OrderStatus::Canceled => info.filled_qty,
+            OrderStatus::Rejected => 0.0,
+        };
+        let mut st = self.state.lock().await;
+        if filled > 0.0 {
+            book(&mut st, rec, |st| {
+                st.apply_stock_fill(action, symbol, filled, info.avg_fill_price, date)
+            });
+            if let Some(b) = bracket {
+                st.set_bracket(b);
+            }
+        }
+        self.finish(&st, &[order_id]);
+    }
+
+    /// Close-side monitor. A LIMIT order still open at the timeout is canceled and the
+    /// remainder re-placed (and journaled) as MARKET, which is then monitored the same way.
+    async fn monitor_close_stock(
+        &self,
+        mut rec: OrderRecord,
+        action: Action,
+        symbol: &str,
+        mut timeout: u64,
+    ) {
+        let date = self.broker.trade_date();
+        loop {
+            let info = match self.poll_until_filled(&rec.order_id, timeout).await {
+                Ok(i) => i,
+                Err(e) => {
+                    error!("poll {:?} stock failed: {:#}", action, e);
+                    return;
+                }
+            };
+            let was_market = rec.limit.is_none();
+            let filled = match info.status {
+                OrderStatus::Filled => rec.qty,
+                OrderStatus::Canceled => info.filled_qty,
+                OrderStatus::Rejected => 0.0,
+                OrderStatus::PartiallyFilled | OrderStatus::Working | OrderStatus::Unknown(_) => {
+                    if !was_market {
+                        let _ = self.broker.cancel_order(&rec.order_id).await;
+                    }
+                    info.filled_qty
+                }
+            };
+            {
+                let mut st = self.state.lock().await;
+                if filled > 0.0 {
+                    book(&mut st, &rec, |st| {
+                        st.apply_stock_fill(action, symbol, filled, info.avg_fill_price, date)
+                    });
+                }
+                self.finish(&st, &[&rec.order_id]);
+            }
+            let remaining = (rec.qty - filled).max(0.0);
+            if was_market
+                || remaining <= 0.0
+                || matches!(
+                    info.status,
+                    OrderStatus::Filled | OrderStatus::Canceled | OrderStatus::Rejected
+                )
+            {
+                return;
+            }
+            let side = order_side(action);
+            match self
+                .broker
+                .place_stock_market(symbol, remaining, side.clone(), &self.tif)
+                .await
+            {
+                Ok(mid) => {
+                    info!(
+                        "{:?} stock timeout -> converted remaining to MARKET (new id={})",
+                        action, mid
+                    );
+                    let author = rec.author.take();
+                    rec = self.order_record(mid, rec.signal_id, rec.signal, &side, remaining, None);
+                    rec.author = author;
+                    self.journal(&rec);
+                    timeout = self.cfg.exec.sell_timeout_sec;
+                }
+                Err(e) => {
+                    error!("convert {:?} to market failed: {:#}", action, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn monitor_open_option(
+        &self,
+        rec: &OrderRecord,
+        o: &OptionSignal,
+        bracket: Option<ExitBracket>,
+        timeout: u64,
+    ) {
+        let date = self.broker.trade_date();
+        let order_id = rec.order_id.as_str();
+        let info = match self.poll_until_filled(order_id, timeout).await {
+            Ok(i) => i,
+            Err(e) => {
+                error!("poll {:?} option failed: {:#}", o.action, e);
+                return;
+            }
+        };
+        let filled = match info.status {
+            OrderStatus::Filled => rec.qty as u32,
+            OrderStatus::PartiallyFilled => {
+                let _ = self.broker.cancel_order(order_id).await;
+                info.filled_qty as u32
+            }
+            OrderStatus::Working | OrderStatus::Unknown(_) => {
+                let _ = self.broker.cancel_order(order_id).await;
+                info!("{:?} option timeout -> canceled pending order", o.action);
+                0
+            }
+            OrderStatus::Canceled => info.filled_qty as u32,
+            OrderStatus::Rejected => 0,
+        };
+        let mut st = self.state.lock().await;
+        if filled > 0 {
+            book(&mut st, rec, |st| {
+                st.apply_option_fill(o.action, &o.key, filled, info.avg_fill_price, date)
+            });
+            if let Some(b) = bracket {
+                st.set_bracket(b);
+            }
+        }
+        self.finish(&st, &[order_id]);
+    }
+
+    /// Close-side monitor; see `monitor_close_stock`.
+    async fn monitor_close_option(
+        &self,
+        mut rec: OrderRecord,
+        o: &OptionSignal,
+        contract: &OptionContract,
+        mut timeout: u64,
+    ) {
+        let date = self.broker.trade_date();
+        loop {
+            let info = match self.poll_until_filled(&rec.order_id, timeout).await {
+                Ok(i) => i,
+                Err(e) => {
+                    error!("poll {:?} option failed: {:#}", o.action, e);
+                    return;
+                }
+            };
+            let was_market = rec.limit.is_none();
+            let filled = match info.status {
+                OrderStatus::Filled => rec.qty as u32,
+                OrderStatus::Canceled => info.filled_qty as u32,
+                OrderStatus::Rejected => 0,
+                OrderStatus::PartiallyFilled | OrderStatus::Working | OrderStatus::Unknown(_) => {
+                    if !was_market {
+                        let _ = self.broker.cancel_order(&rec.order_id).await;
+                    }
+                    info.filled_qty as u32
+                }
+            };
+            {
+                let mut st = self.state.lock().await;
+                if filled > 0 {
+                    book(&mut st, &rec, |st| {
+                        st.apply_option_fill(o.action, &o.key, filled, info.avg_fill_price, date)
+                    });
+                }
+                self.finish(&st, &[&rec.order_id]);
+            }
+            let remaining = (rec.qty as u32).saturating_sub(filled);
+            if was_market
+                || remaining == 0
+                || matches!(
+                    info.status,
+                    OrderStatus::Filled | OrderStatus::Canceled | OrderStatus::Rejected
+                )
+            {
+                return;
+            }
+            let side = order_side(o.action);
+            match self
+                .broker
+                .place_option_market(contract, remaining as f64, side.clone(), &self.tif)
+                .await
+            {
+                Ok(mid) => {
+                    info!(
+                        "{:?} option timeout -> converted remaining to MARKET (new id={})",
+                        o.action, mid
+                    );
+                    let qty = remaining as f64;
+                    let author = rec.author.take();
+                    rec = self.order_record(mid, rec.signal_id, rec.signal, &side, qty, None);
+                    rec.author = author;
+                    self.journal(&rec);
+                    timeout = self.cfg.exec.sell_timeout_sec;
+                }
+                Err(e) => {
+                    error!("convert {:?} option to market failed: {:#}", o.action, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Wait for every leg, then book complete spreads as one position. Opening legs that
+    /// time out are canceled; closing legs have their remainder converted to MARKET.
+    /// Fills that do not pair up into whole spreads are logged (long leftovers are booked
+    /// as single options so they are not lost).
+    async fn monitor_spread(
+        &self,
+        sp: &SpreadSignal,
+        mut legs: Vec<LegOrder>,
+        was_market: bool,
+        timeout: u64,
+    ) {
+        let closing = !sp.action.is_opening();
+        let date = self.broker.trade_date();
+
+        // (filled qty, notional) per leg; orders a leg replaced before a restart still
+        // count towards its fill
+        let mut fills: Vec<(f64, f64)> = Vec::with_capacity(legs.len());
+        for l in &legs {
+            let (mut q, mut notional) = (0.0, 0.0);
+            for id in &l.earlier {
+                if let Ok(i) = self.broker.get_order_info(id).await {
+                    q += i.filled_qty;
+                    notional += i.filled_qty * i.avg_fill_price;
+                }
+            }
+            fills.push((q, notional));
+        }
+
+        // All legs are polled together under one deadline
+        let ids: Vec<&str> = legs.iter().map(|l| l.rec.order_id.as_str()).collect();
+        let infos = self.poll_all_until_filled(&ids, timeout).await;
+        let mut converted = Vec::new();
+        for (i, (l, info)) in legs.iter_mut().zip(infos).enumerate() {
+            let info = match info {
+                Ok(info) => info,
+                Err(e) => {
+                    error!("poll spread leg failed: {:#}", e);
+                    continue;
+                }
+            };
+            fills[i].0 += info.filled_qty;
+            fills[i].1 += info.filled_qty * info.avg_fill_price;
+            if info.status != OrderStatus::Filled {
+                let _ = self.broker.cancel_order(&l.rec.order_id).await;
+                let remaining = (l.rec.qty - info.filled_qty).max(0.0);
+                // a leg canceled elsewhere stays canceled
+                let canceled = matches!(info.status, OrderStatus::Canceled | OrderStatus::Rejected);
+                if closing && !was_market && remaining > 0.0 && !canceled {
+                    match self
+                        .broker
+                        .place_option_market(&l.contract, remaining, l.side.clone(), &self.tif)
+                        .await
+                    {
+                        Ok(mid) => {
+                            info!(
+                                "SPREAD leg timeout -> converted remaining to MARKET (new id={})",
+                                mid
+                            );
+                            let mut rec = self.order_record(
+                                mid,
+                                l.rec.signal_id,
+                                l.rec.signal.clone(),
+                                &l.side,
+                                remaining,
+                                None,
+                            );
+                            rec.leg = l.rec.leg.clone();
+                            rec.author = l.rec.author.clone();
+                            self.journal(&rec);
+                            l.earlier.push(std::mem::replace(&mut l.rec, rec).order_id);
+                            converted.push(i);
+                        }
+                        Err(e) => error!("convert spread leg to market failed: {:#}", e),
+                    }
+                }
+            }
+        }
+        // and the MARKET conversions under a second one
+        let ids: Vec<&str> = converted
+            .iter()
+            .map(|&i| legs[i].rec.order_id.as_str())
+            .collect();
+        let infos = self.poll_all_until_filled(&ids, timeout).await;
+        for (&i, info) in converted.iter().zip(infos) {
+            if let Ok(info) = info {
+                fills[i].0 += info.filled_qty;
+                fills[i].1 += info.filled_qty * info.avg_fill_price;
+            }
+        }
+        // (filled qty, average price) per leg
+        let fills: Vec<(f64, f64)> = fills
+            .into_iter()
+            .map(|(q, notional)| (q, if q > 0.0 { notional / q } else { 0.0 }))
+            .collect();
+
+        let complete = fills.iter().map(|(q, _)| *q as u32).min().unwrap_or(0);
+        // Net debit when opening, net credit when closing (same sign convention)
+        let net: f64 = legs
+            .iter()
+            .zip(&fills)
+            .map(|(l, (_, px))| l.leg.ratio as f64 * px)
+            .sum();
+
+        let mut st = self.state.lock().await;
+        if complete > 0 {
+            if closing {
+                if let Some(l) = legs.first() {
+                    book(&mut st, &l.rec, |st| {
+                        st.realize_spread_sell(sp, complete, net, date);
+                    });
+                }
+            } else {
+                st.upsert_spread_buy_with_cost(sp, complete, net);
+            }
+        }
+        for (l, (q, px)) in legs.iter().zip(&fills) {
+            let extra = (*q as u32).saturating_sub(complete);
+            if extra == 0 {
+                continue;
+            }
+            warn!(
+                "SPREAD {} leg {}{} filled {} beyond {} complete spreads",
+                sp.label(),
+                l.leg.strike,
+                l.leg.call_put,
+                extra,
+                complete
+            );
+            if !closing {
+                let key = sp.leg_key(&l.leg);
+                if l.leg.ratio > 0 {
+                    st.upsert_option_buy_with_cost(&key, extra, *px);
+                } else {
+                    st.upsert_option_short_with_proceeds(&key, extra, *px);
+                }
+            }
+        }
+        let ids: Vec<&str> = legs
+            .iter()
+            .flat_map(|l| l.earlier.iter().chain([&l.rec.order_id]))
+            .map(String::as_str)
+            .collect();
+        self.finish(&st, &ids);
+    }
+
+    // ---------------- Restart recovery ----------------
+
+    /// Re-attach monitors to orders the journal still has open, i.e. placed before a
+    /// restart and not yet booked. Each resumes with the time left until its deadline;
+    /// past-due orders get one poll and then the usual timeout rule (cancel entries,
+    /// convert exits to MARKET). Must run inside a `LocalSet`.
+    pub async fn recover_orders(&self) -> Vec<JoinHandle<()>> {
+        let open = match self.store.open_orders() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("read order journal failed: {:#}", e);
+                return Vec::new();
+            }
+        };
+        let mut handles = Vec::new();
+        let mut spreads: Vec<(String, Vec<OrderRecord>)> = Vec::new();
+        for rec in open {
+            info!(
+                "Recovering order id={} ({:?} {})",
+                rec.order_id,
+                rec.signal.action(),
+                rec.signal.label()
+            );
+            if let Some((_, group)) = &rec.leg {
+                match spreads.iter_mut().find(|(g, _)| g == group) {
+                    Some((_, recs)) => recs.push(rec),
+                    None => spreads.push((group.clone(), vec![rec])),
+                }
+                continue;
+            }
+            let timeout = self.time_left(&rec);
+            let this = self.clone();
+            match rec.signal.clone() {
+                TradeSignal::Stock(s) => {
+                    let bracket = if s.action.is_opening() {
+                        match self.broker.find_stock_ticker_id(&s.symbol).await {
+                            Ok(tid) => bracket_for_entry(&rec.signal, tid, rec.author.clone()),
+                            Err(e) => {
+                                error!("recover {}: find stock ticker failed: {:#}", s.symbol, e);
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    };
+                    handles.push(tokio::task::spawn_local(async move {
+                        if s.action.is_opening() {
+                            this.monitor_open_stock(&rec, s.action, &s.symbol, bracket, timeout)
+                                .await;
+                        } else {
+                            this.monitor_close_stock(rec, s.action, &s.symbol, timeout)
+                                .await;
+                        }
+                    }));
+                }
+                TradeSignal::Option(o) => {
+                    let contract = match self.broker.find_option_contract(&o.key).await {
+                        Ok(c) => c,
+                        Err(e) => {
+                            error!("recover {}: find option contract failed: {:#}", o.key, e);
+                            continue;
+                        }
+                    };
+                    let bracket =
+                        bracket_for_entry(&rec.signal, contract.ticker_id, rec.author.clone());
+                    handles.push(tokio::task::spawn_local(async move {
+                        if o.action.is_opening() {
+                            this.monitor_open_option(&rec, &o, bracket, timeout).await;
+                        } else {
+                            this.monitor_close_option(rec, &o, &contract, timeout).await;
+                        }
+                    }));
+                }
+                TradeSignal::Spread(_) => {
+                    error!("journaled spread order {} has no leg", rec.order_id);
+                }
+            }
+        }
+        for (_, recs) in spreads {
+            if let Some(h) = self.recover_spread(recs).await {
+                handles.push(h);
+            }
+        }
+        handles
+    }
+
+    /// Rebuild a spread's legs from its journal entries (oldest first; a leg converted
+    /// to MARKET has several) and resume `monitor_spread`. A spread whose legs were not
+    /// all placed is abandoned like a failed placement.
+    async fn recover_spread(&self, recs: Vec<OrderRecord>) -> Option<JoinHandle<()>> {
+        let TradeSignal::Spread(sp) = recs[0].signal.clone() else {
+            return None;
+        };
+        let was_market = recs[0].limit.is_none();
+        let timeout = self.time_left(&recs[0]);
+        let mut legs: Vec<LegOrder> = Vec::with_capacity(sp.legs.len());
+        let mut missing = false;
+        for (i, leg) in sp.legs.iter().enumerate() {
+            let mut mine: Vec<OrderRecord> = recs
+                .iter()
+                .filter(|r| matches!(&r.leg, Some((j, _)) if *j == i))
+                .cloned()
+                .collect();
+            let Some(rec) = mine.pop() else {
+                missing = true;
+                continue;
+            };
+            let contract = match self.broker.find_option_contract(&sp.leg_key(leg)).await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("recover spread leg {}: {:#}", rec.order_id, e);
+                    return None;
+                }
+            };
+            let side = match rec.side {
+                Side::Buy => OrderAction::Buy,
+                Side::Sell => OrderAction::Sell,
+            };
+            legs.push(LegOrder {
+                leg: *leg,
+                contract,
+                side,
+                rec,
+                earlier: mine.into_iter().map(|r| r.order_id).collect(),
+            });
+        }
+        if missing {
+            error!(
+                "spread {} was not fully placed; canceling its legs",
+                sp.label()
+            );
+            self.abandon_legs(&legs).await;
+            return None;
+        }
+        let this = self.clone();
+        Some(tokio::task::spawn_local(async move {
+            this.monitor_spread(&sp, legs, was_market, timeout).await;
+        }))
+    }
+}
+
+/// Book fills through `f`, attributing any P/L it realizes to the order's author.
+fn book(st: &mut BotState, rec: &OrderRecord, f: impl FnOnce(&mut BotState)) {
+    let since = st.daily_pl.len();
+    f(st);
+    if let Some(author) = &rec.author {
+        st.attribute_pl(since, author);
+    }
+}
+
+/// Order side for an action: BTO/BTC buy, STC/STO sell.
+fn order_side(action: Action) -> OrderAction {
+    match action {
+        Action::BTO | Action::BTC => OrderAction::Buy,
+        Action::STC | Action::STO => OrderAction::Sell,
+    }
+}
+
+/// Quantity a relative exit can close: the long for STC, the short for BTC.
+fn closable(action: Action, held: f64) -> f64 {
+    match action {
+        Action::BTC => (-held).max(0.0),
+        _ => held.max(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::instrument::OptionKey;
+    use crate::parser::{parse_exit_reply, parse_signal_on};
+    use crate::sim_broker::SimBroker;
+    use crate::types::Holding;
+    use chrono::NaiveDate;
+
+    /// The simulated clock starts on the day signals are parsed (10:00 EDT, 2025-08-01).
+    fn executor(sim: &Arc<SimBroker>, cfg: AppConfig) -> Executor<SimBroker> {
+        sim.set_time(ymd(2025, 8, 1).and_hms_opt(14, 0, 0).unwrap());
+        let risk = RiskEngine::from_cfg(&cfg.risk);
+        Executor::new(
+            Arc::clone(sim),
+            Arc::new(Mutex::new(BotState::default())),
+            Arc::new(StateStore::in_memory().unwrap()),
+            risk,
+            cfg,
+        )
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// Parse as of 2025-08-01 so "MM/DD" expiries land in 2025.
+    async fn run(exec: &Executor<SimBroker>, msg: &str) -> bool {
+        let sig = parse_signal_on(msg, ymd(2025, 8, 1).into()).unwrap();
+        match exec.handle_signal(sig).await {
+            Some(h) => {
+                h.await.unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn run_as(exec: &Executor<SimBroker>, author: &str, msg: &str) -> bool {
+        run_by(exec, &Author::named(author), msg).await
+    }
+
+    async fn run_by(exec: &Executor<SimBroker>, author: &Author, msg: &str) -> bool {
+        let sig = parse_signal_on(msg, ymd(2025, 8, 1).into()).unwrap();
+        match exec.handle_author_signal(author, sig).await {
+            Some(h) => {
+                h.await.unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn stock_round_trip_records_holding_and_pl() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let exec = executor(&sim, test_config("exec-stock"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 10 AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+
+                sim.set_price(tid, 110.0);
+                assert!(run(&exec, "STC 10 AAPL @ 110").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        assert_eq!(st.position_qty_stock("AAPL"), 0.0);
+        assert_eq!(st.daily_pl.len(), 1);
+        assert!((st.daily_pl[0].realized_pl - 100.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn outside_hours_signals_queue_or_go_extended() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let utc = |d: u32, h: u32, m: u32| ymd(2025, 8, d).and_hms_opt(h, m, 0).unwrap();
+        let mut cfg = test_config("exec-queue");
+        cfg.exec.outside_hours = OutsideHours::Queue;
+        let exec = executor(&sim, cfg.clone());
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                // Saturday: held until Monday's open (09:30 EDT = 13:30 UTC)
+                sim.set_time(utc(2, 15, 0));
+                assert!(!run(&exec, "BTO 10 AAPL @ 100").await);
+                exec.handle_message_signal(
+                    "m1",
+                    &Author::named("alice"),
+                    parse_signal_on("BTO 5 AAPL @ 100", ymd(2025, 8, 1).into()).unwrap(),
+                )
+                .await;
+                exec.handle_delete("m1").await;
+                assert!(exec.release_queued().await.is_empty());
+                assert_eq!(sim.order_count(), 0);
+            })
+            .await;
+
+        // the queue survives a restart before the open
+        let exec = Executor::new(
+            Arc::clone(&sim),
+            Arc::new(Mutex::new(BotState::default())),
+            Arc::clone(&exec.store),
+            RiskEngine::from_cfg(&cfg.risk),
+            cfg,
+        );
+        assert_eq!(exec.recover_queued().await, 1);
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                sim.set_time(utc(4, 13, 31));
+                let placed = exec.release_queued().await;
+                assert_eq!(placed.len(), 1);
+                for h in placed {
+                    h.await.unwrap();
+                }
+            })
+            .await;
+        assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+        assert!(exec.store.queued_signals().unwrap().is_empty());
+
+        // pre-market stock limit orders go out as extended-hours orders
+        let mut cfg = test_config("exec-extended");
+        cfg.exec.outside_hours = OutsideHours::Extended;
+        let exec = executor(&sim, cfg);
+        sim.set_time(utc(5, 12, 0));
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 10 AAPL @ 100").await);
+            })
+            .await;
+        assert_eq!(sim.extended_order_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn restart_resumes_journaled_orders() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let cfg = test_config("exec-recover");
+        let exec = Executor::new(
+            Arc::clone(&sim),
+            Arc::new(Mutex::new(BotState::default())),
+            Arc::clone(&store),
+            RiskEngine::from_cfg(&cfg.risk),
+            cfg.clone(),
+        );
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 10 AAPL @ 100").await);
+                sim.set_price(tid, 105.0);
+                // the process "dies" before either monitor runs
+                for msg in ["STC 10 AAPL @ 110", "BTO 5 AAPL @ 90"] {
+                    let sig = parse_signal_on(msg, ymd(2025, 8, 1).into()).unwrap();
+                    exec.handle_signal(sig).await.unwrap().abort();
+                }
+            })
+            .await;
+        assert_eq!(store.open_orders().unwrap().len(), 2);
+
+        let restarted = Executor::new(
+            Arc::clone(&sim),
+            Arc::new(Mutex::new(store.load().unwrap())),
+            Arc::clone(&store),
+            RiskEngine::from_cfg(&cfg.risk),
+            cfg,
+        );
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                for h in restarted.recover_orders().await {
+                    h.await.unwrap();
+                }
+            })
+            .await;
+
+        // past-due exit converted to MARKET at 105, past-due entry canceled
+        let st = restarted.state.lock().await;
+        assert_eq!(st.position_qty_stock("AAPL"), 0.0);
+        assert_eq!(st.daily_pl.len(), 1);
+        assert!((st.daily_pl[0].realized_pl - 50.0).abs() < 1e-9);
+        assert_eq!(sim.order_count(), 4);
+        assert!(store.open_orders().unwrap().is_empty());
+        assert_eq!(store.load().unwrap().daily_pl.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn daily_loss_halts_entries_but_not_exits() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let mut cfg = test_config("exec-halt");
+        cfg.risk.max_daily_loss = 50.0;
+        let exec = executor(&sim, cfg);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 10 AAPL @ 100").await);
+                sim.set_price(tid, 94.0);
+                // unrealized -60 breaches the limit
+                assert!(!run(&exec, "BTO 1 AAPL @ 94").await);
+                assert!(run(&exec, "STC 5 AAPL @ 94").await);
+                sim.set_price(tid, 120.0);
+                // still halted for the day even though the loss recovered
+                assert!(!run(&exec, "BTO 1 AAPL @ 120").await);
+            })
+            .await;
+
+        let today = sim.trade_date();
+        assert_eq!(exec.state.lock().await.halted_on, Some(today));
+        assert_eq!(exec.store.load().unwrap().halted_on, Some(today));
+    }
+
+    #[tokio::test]
+    async fn edits_replace_and_deletes_cancel_working_orders() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let mut cfg = test_config("exec-edits");
+        cfg.exec.buy_timeout_sec = 30;
+        let exec = executor(&sim, cfg);
+        let sig = |msg: &str| parse_signal_on(msg, ymd(2025, 8, 1).into()).unwrap();
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                // a typo'd limit keeps the entry working until the edit replaces it
+                let typo = exec
+                    .handle_message_signal("m1", &Author::named("alice"), sig("BTO 10 AAPL @ 90"))
+                    .await
+                    .unwrap();
+                let fixed = exec
+                    .handle_edit(
+                        "m1",
+                        &Author::named("alice"),
+                        vec![sig("BTO 10 AAPL @ 100")],
+                    )
+                    .await
+                    .pop()
+                    .unwrap();
+                typo.await.unwrap();
+                fixed.await.unwrap();
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+                // once filled, further edits leave the trade alone
+                let late = exec
+                    .handle_edit(
+                        "m1",
+                        &Author::named("alice"),
+                        vec![sig("BTO 10 AAPL @ 101")],
+                    )
+                    .await;
+                assert!(late.is_empty());
+
+                let h = exec
+                    .handle_message_signal("m2", &Author::named("alice"), sig("BTO 5 AAPL @ 90"))
+                    .await
+                    .unwrap();
+                exec.handle_delete("m2").await;
+                h.await.unwrap();
+
+                // two calls in one message: editing the second away cancels only it
+                let first = sig("BTO 5 AAPL @ 90");
+                let hs = exec
+                    .handle_message_signals(
+                        "m3",
+                        &Author::named("alice"),
+                        vec![first.clone(), sig("BTO 5 AAPL @ 91")],
+                    )
+                    .await;
+                assert_eq!(hs.len(), 2);
+                assert!(exec
+                    .handle_edit("m3", &Author::named("alice"), vec![first])
+                    .await
+                    .is_empty());
+                let status = |key: &str| {
+                    let id = exec.store.message_orders(key).unwrap()[0]
+                        .0
+                        .order_id
+                        .clone();
+                    let sim = sim.clone();
+                    async move { sim.get_order_info(&id).await.unwrap().status }
+                };
+                assert_eq!(status("m3#2").await, OrderStatus::Canceled);
+                assert_ne!(status("m3").await, OrderStatus::Canceled);
+                exec.handle_delete("m3").await;
+                for h in hs {
+                    h.await.unwrap();
+                }
+            })
+            .await;
+
+        assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+        assert_eq!(sim.order_count(), 5);
+        assert!(exec.store.open_orders().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replies_close_the_referenced_entry() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        sim.set_price(tid, 2.0);
+        let exec = executor(&sim, test_config("exec-replies"));
+        let sig = |msg: &str| parse_signal_on(msg, ymd(2025, 8, 1).into()).unwrap();
+        let reply = |msg: &str| parse_exit_reply(msg).unwrap();
+        let key = OptionKey::new("SPY", 450.0, 'C', ymd(2025, 9, 20));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let h = exec
+                    .handle_message_signal(
+                        "m1",
+                        &Author::named("alice"),
+                        sig("BTO 4 SPY 450C 09/20 @ 2.00"),
+                    )
+                    .await
+                    .unwrap();
+                h.await.unwrap();
+                let h = exec
+                    .handle_reply(
+                        "m2",
+                        &Author::named("alice"),
+                        "m1",
+                        None,
+                        &reply("trim half @ 2.00"),
+                    )
+                    .await
+                    .unwrap();
+                h.await.unwrap();
+                assert_eq!(exec.state.lock().await.position_qty_option(&key), 2);
+                // "BTC" does not close a long
+                let wrong = exec
+                    .handle_reply(
+                        "m3",
+                        &Author::named("alice"),
+                        "m1",
+                        None,
+                        &reply("BTC all @ m"),
+                    )
+                    .await;
+                assert!(wrong.is_none());
+                // an entry never traded here comes from the referenced message
+                let entry = Some(sig("BTO 1 SPY 450C 09/20 @ 2.50"));
+                let h = exec
+                    .handle_reply("m4", &Author::named("alice"), "other", entry, &reply("out"))
+                    .await
+                    .unwrap();
+                h.await.unwrap();
+                assert!(exec
+                    .handle_reply(
+                        "m5",
+                        &Author::named("alice"),
+                        "unknown",
+                        None,
+                        &reply("out")
+                    )
+                    .await
+                    .is_none());
+                // a reply to a message with several calls does not pick one
+                let two = vec![sig("BTO 1 SPY 450C 09/20 @ 2.00"), sig("BTO 1 AAPL @ 90")];
+                for h in exec
+                    .handle_message_signals("m6", &Author::named("alice"), two)
+                    .await
+                {
+                    h.await.unwrap();
+                }
+                assert!(exec
+                    .handle_reply("m7", &Author::named("alice"), "m6", None, &reply("out"))
+                    .await
+                    .is_none());
+                assert_eq!(exec.state.lock().await.position_qty_option(&key), 1);
+                let done = exec
+                    .handle_reply("m8", &Author::named("alice"), "m1", None, &reply("out"))
+                    .await
+                    .unwrap();
+                done.await.unwrap();
+            })
+            .await;
+
+        assert_eq!(exec.state.lock().await.position_qty_option(&key), 0);
+    }
+
+    #[tokio::test]
+    async fn entries_sized_from_account_value_before_risk() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        sim.set_account_value(10_000.0);
+        let mut cfg = test_config("exec-sizing");
+        cfg.sizing = SizingMode::PercentOfAccount { pct: 0.1 };
+        cfg.risk.max_position_value = 1_500.0;
+        cfg.authors = serde_yaml::from_str("[{ name: bob, qty_multiplier: 0.5 }]").unwrap();
+        let exec = executor(&sim, cfg);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+                assert!(run_as(&exec, "bob", "BTO 40 AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 15.0);
+                // exits keep the signal's size
+                assert!(run(&exec, "STC 3 AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 12.0);
+                // 20% of the account sizes to 2000 notional, over max_position_value
+                sim.set_account_value(20_000.0);
+                assert!(!run(&exec, "BTO AAPL @ 100").await);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn author_config_sizes_filters_and_budgets() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let mut cfg = test_config("exec-author");
+        cfg.authors = serde_yaml::from_str(
+            "[{ name: bob, qty_multiplier: 2, follow_options: false, max_daily_loss: 50 }]",
+        )
+        .unwrap();
+        let exec = executor(&sim, cfg);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run_as(&exec, "Bob", "BTO 5 AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 10.0);
+                assert!(!run_as(&exec, "Bob", "BTO 1 SPY 450C 09/20 @ 2").await);
+                sim.set_price(tid, 94.0);
+                assert!(run_as(&exec, "Bob", "STC 5 AAPL @ 94").await);
+                // bob's -60 exhausts the budget; other authors still trade
+                assert!(!run_as(&exec, "Bob", "BTO 1 AAPL @ 94").await);
+                assert!(run_as(&exec, "alice", "BTO 1 AAPL @ 94").await);
+            })
+            .await;
+
+        let st = exec.store.load().unwrap();
+        assert_eq!(st.position_qty_stock("AAPL"), 1.0);
+        assert_eq!(st.daily_pl.len(), 1);
+        assert_eq!(st.daily_pl[0].author.as_deref(), Some("bob"));
+        assert_eq!(sim.order_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn fixed_qty_exits_are_capped_at_the_holding() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let mut cfg = test_config("exec-fixed-qty");
+        cfg.authors = serde_yaml::from_str("[{ name: bob, fixed_qty: 2 }]").unwrap();
+        let exec = executor(&sim, cfg);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run_as(&exec, "bob", "BTO 10 AAPL @ 100").await);
+                assert_eq!(exec.state.lock().await.position_qty_stock("AAPL"), 2.0);
+                // bob closes his 10; we close our 2 rather than being refused
+                assert!(run_as(&exec, "bob", "STC 10 AAPL @ 100").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        assert_eq!(st.position_qty_stock("AAPL"), 0.0);
+        assert_eq!(st.daily_pl.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn author_budget_follows_the_user_id() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("AAPL");
+        sim.set_price(tid, 100.0);
+        let mut cfg = test_config("exec-author-id");
+        cfg.authors =
+            serde_yaml::from_str(r#"[{ name: bob, user_id: "42", max_daily_loss: 50 }]"#).unwrap();
+        let exec = executor(&sim, cfg);
+        let by = |id: &str, name: &str| Author {
+            id: Some(id.into()),
+            name: name.into(),
+        };
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run_by(&exec, &by("42", "bob"), "BTO 5 AAPL @ 100").await);
+                sim.set_price(tid, 88.0);
+                assert!(run_by(&exec, &by("42", "bob"), "STC 5 AAPL @ 88").await);
+                // renamed, still bob's budget; a new "bob" is someone else
+                assert!(!run_by(&exec, &by("42", "bobby"), "BTO 1 AAPL @ 88").await);
+                assert!(run_by(&exec, &by("7", "bob"), "BTO 1 AAPL @ 88").await);
+            })
+            .await;
+
+        let st = exec.store.load().unwrap();
+        assert_eq!(st.daily_pl.len(), 1);
+        assert_eq!(st.daily_pl[0].author.as_deref(), Some("42"));
+        assert_eq!(st.author_realized_pl("42", st.daily_pl[0].date), -60.0);
+    }
+
+    #[tokio::test]
+    async fn unfilled_buy_is_canceled_on_timeout() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        sim.set_price(tid, 2.0);
+        let exec = executor(&sim, test_config("exec-timeout"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 1 SPY 450C 09/20 @ 1.50").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        assert_eq!(
+            st.position_qty_option(&OptionKey::new("SPY", 450.0, 'C', ymd(2025, 9, 20))),
+            0
+        );
+        assert!(sim.positions_simple().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_contracts_are_refused_whatever_the_hours_policy() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("SPY", 450.0, 'C', "2025-08-01");
+        sim.set_price(tid, 2.0);
+        let exec = executor(&sim, test_config("exec-expired"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 1 SPY 450C 08/01 @ 2").await);
+                // 16:30 EDT: the 08/01 contract no longer trades
+                sim.set_time(ymd(2025, 8, 1).and_hms_opt(20, 30, 0).unwrap());
+                assert!(!run(&exec, "STC 1 SPY 450C 08/01 @ 2").await);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn relative_expiry_falls_back_to_closest_listed() {
+        // "0DTE" on 2025-08-01 but AAPL only lists the 08/08 weekly
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("AAPL", 150.0, 'C', "2025-08-08");
+        sim.add_option("AAPL", 150.0, 'C', "2025-08-15");
+        sim.set_price(tid, 2.0);
+        let exec = executor(&sim, test_config("exec-rel-expiry"));
+        let weekly = OptionKey::new("AAPL", 150.0, 'C', ymd(2025, 8, 8));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 2 AAPL 150C 0DTE @ 2").await);
+                assert_eq!(exec.state.lock().await.position_qty_option(&weekly), 2);
+                // an explicit date that is not listed does not fall back
+                assert!(!run(&exec, "BTO 1 AAPL 150C 08/01 @ 2").await);
+                assert!(run(&exec, "STC all AAPL 150C 0dte @ 2").await);
+            })
+            .await;
+
+        assert_eq!(exec.state.lock().await.position_qty_option(&weekly), 0);
+    }
+
+    #[tokio::test]
+    async fn trim_half_sells_half_of_actual_holding() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("AAPL", 150.0, 'C', "2025-08-16");
+        sim.set_price(tid, 2.0);
+        let exec = executor(&sim, test_config("exec-trim"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 4 AAPL 150C 08/16 @ 2").await);
+                sim.set_price(tid, 3.0);
+                assert!(run(&exec, "trim half AAPL 150C 08/16 @ 3").await);
+                assert!(run(&exec, "STC all AAPL 150C 08/16 @ 3").await);
+                assert!(!run(&exec, "STC all AAPL 150C 08/16 @ 3").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        let closed: Vec<f64> = st.daily_pl.iter().map(|e| e.qty).collect();
+        assert_eq!(closed, vec![2.0, 2.0]);
+        assert_eq!(
+            st.position_qty_option(&OptionKey::new("AAPL", 150.0, 'C', ymd(2025, 8, 16))),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn vertical_spread_tracked_as_one_position() {
+        let sim = Arc::new(SimBroker::new());
+        let long = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        let short = sim.add_option("SPY", 455.0, 'C', "2025-09-20");
+        sim.set_price(long, 3.0);
+        sim.set_price(short, 1.8);
+        let exec = executor(&sim, test_config("exec-spread"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "BTO 2 SPY 450/455C 09/20 @ 1.20").await);
+                {
+                    let st = exec.state.lock().await;
+                    assert_eq!(st.holdings.len(), 1);
+                    assert!(matches!(
+                        st.holdings[0],
+                        Holding::Spread { quantity: 2, avg_cost, .. } if (avg_cost - 1.2).abs() < 1e-9
+                    ));
+                }
+
+                sim.set_price(long, 5.0);
+                sim.set_price(short, 2.5);
+                assert!(run(&exec, "STC 2 SPY 450/455C 09/20 @ 2.50").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        assert!(st.holdings.is_empty());
+        assert_eq!(st.daily_pl.len(), 1);
+        assert_eq!(st.daily_pl[0].asset, "SPY 450/455C 2025-09-20");
+        assert!((st.daily_pl[0].realized_pl - 260.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn spread_legs_share_one_deadline() {
+        let sim = Arc::new(SimBroker::new());
+        let long = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        let short = sim.add_option("SPY", 455.0, 'C', "2025-09-20");
+        sim.set_price(long, 3.0);
+        sim.set_price(short, 1.8);
+        let mut cfg = test_config("exec-spread-deadline");
+        cfg.exec.buy_timeout_sec = 1;
+        let exec = executor(&sim, cfg);
+
+        let start = std::time::Instant::now();
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                // 0.80 is under the 1.20 mid: neither leg fills
+                assert!(run(&exec, "BTO 1 SPY 450/455C 09/20 @ 0.80").await);
+            })
+            .await;
+
+        // one timeout for both legs (plus a poll interval), not one per leg
+        assert!(start.elapsed() < Duration::from_millis(2400));
+        assert!(exec.state.lock().await.holdings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn spread_closed_in_full_is_not_resized() {
+        let sim = Arc::new(SimBroker::new());
+        let long = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        let short = sim.add_option("SPY", 455.0, 'C', "2025-09-20");
+        sim.set_price(long, 3.0);
+        sim.set_price(short, 1.8);
+        let mut cfg = test_config("exec-spread-out");
+        cfg.authors = serde_yaml::from_str("[{ name: bob, qty_multiplier: 2 }]").unwrap();
+        let exec = executor(&sim, cfg);
+        let bob = Author::named("bob");
+        let spread = parse_signal_on("BTO 2 SPY 450/455C 09/20 @ 1.20", ymd(2025, 8, 1).into());
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let h = exec
+                    .handle_message_signal("m1", &bob, spread.unwrap())
+                    .await
+                    .unwrap();
+                h.await.unwrap();
+                assert!(matches!(
+                    exec.state.lock().await.holdings[..],
+                    [Holding::Spread { quantity: 4, .. }]
+                ));
+                // "out" closes the 4 held, not 4 × 2
+                let out = parse_exit_reply("out @ 1.20").unwrap();
+                let h = exec
+                    .handle_reply("m2", &bob, "m1", None, &out)
+                    .await
+                    .unwrap();
+                h.await.unwrap();
+            })
+            .await;
+
+        assert!(exec.state.lock().await.holdings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn short_put_round_trip_realizes_premium() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_option("SPY", 440.0, 'P', "2025-09-20");
+        sim.set_price(tid, 1.5);
+        let mut cfg = test_config("exec-short");
+        cfg.risk.max_short_exposure = 100_000.0;
+        let exec = executor(&sim, cfg);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(run(&exec, "STO 2 SPY 440P 09/20 @ 1.50").await);
+                assert_eq!(
+                    exec.state.lock().await.position_qty_option(&OptionKey::new(
+                        "SPY",
+                        440.0,
+                        'P',
+                        ymd(2025, 9, 20)
+                    )),
+                    -2
+                );
+                // exposure cap: 2 more contracts would be 4 × 440 × 100 = 176k
+                assert!(!run(&exec, "STO 2 SPY 440P 09/20 @ 1.50").await);
+                assert!(!run(&exec, "BTO 1 SPY 440P 09/20 @ 1.50").await);
+
+                sim.set_price(tid, 0.5);
+                assert!(run(&exec, "BTC all SPY 440P 09/20 @ 0.50").await);
+            })
+            .await;
+
+        let st = exec.state.lock().await;
+        assert!(st.holdings.is_empty());
+        assert!((st.daily_pl[0].realized_pl - 200.0).abs() < 1e-6);
+        assert!(sim.positions_simple().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn short_disabled_without_exposure_cap() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("TSLA");
+        sim.set_price(tid, 200.0);
+        let exec = executor(&sim, test_config("exec-noshort"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(!run(&exec, "STO 5 TSLA @ m").await);
+            })
+            .await;
+        assert_eq!(sim.order_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn unheld_stc_is_rejected_before_placement() {
+        let sim = Arc::new(SimBroker::new());
+        let tid = sim.add_stock("TSLA");
+        sim.set_price(tid, 200.0);
+        let exec = executor(&sim, test_config("exec-reject"));
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert!(!run(&exec, "STC 5 TSLA @ m").await);
+            })
+            .await;
+        assert_eq!(sim.order_count(), 0);
+    }
+}
@@ -0,0 +1,277 @@
+//! Exit management: watch marks for holdings with armed stop/target levels and fire
+//! an STC (long) or BTC (short) for the whole position through the executor's close
+//! monitor when one triggers.
+
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::broker::Broker;
+use crate::executor::Executor;
+use crate::types::{
+    Action, ExitBracket, ExitLevels, OrderType, RelativeQty, StockSignal, TradeSignal,
+};
+
+/// Closing action for an opening one.
+fn exit_action(entry: Action) -> Option<Action> {
+    match entry {
+        Action::BTO => Some(Action::STC),
+        Action::STO => Some(Action::BTC),
+        _ => None,
+    }
+}
+
+/// Bracket for a filled entry, or None when the entry carried no levels. `author` is
+/// the attribution key of the entry's configured author, who the exit is later
+/// attributed to.
+pub fn bracket_for_entry(
+    entry: &TradeSignal,
+    ticker_id: i64,
+    author: Option<String>,
+) -> Option<ExitBracket> {
+    let exit = match entry {
+        TradeSignal::Stock(s) if !s.exit_levels.is_empty() => TradeSignal::Stock(StockSignal {
+            action: exit_action(s.action)?,
+            symbol: s.symbol.clone(),
+            quantity: 0,
+            order_type: OrderType::Market,
+            limit_price: None,
+            relative_qty: Some(RelativeQty::All),
+            exit_levels: ExitLevels::default(),
+        }),
+        TradeSignal::Option(o) if !o.exit_levels.is_empty() => {
+            let mut x = o.clone();
+            x.action = exit_action(o.action)?;
+            x.quantity = 0;
+            x.order_type = OrderType::Market;
+            x.limit_price = None;
+            x.relative_qty = Some(RelativeQty::All);
+            x.exit_levels = ExitLevels::default();
+            TradeSignal::Option(x)
+        }
+        _ => return None,
+    };
+    let levels = match entry {
+        TradeSignal::Stock(s) => s.exit_levels,
+        TradeSignal::Option(o) => o.exit_levels,
+        TradeSignal::Spread(_) => return None,
+    };
+    Some(ExitBracket {
+        ticker_id,
+        exit,
+        levels,
+        author,
+    })
+}
+
+/// "stop" / "target" when `mark` crosses a level. Shorts stop out on the way up and
+/// take profit on the way down.
+pub fn triggered(levels: &ExitLevels, mark: f64, short: bool) -> Option<&'static str> {
+    let (hit_stop, hit_target) = if short {
+        (
+            levels.stop.is_some_and(|sl| mark >= sl),
+            levels.target.is_some_and(|pt| mark <= pt),
+        )
+    } else {
+        (
+            levels.stop.is_some_and(|sl| mark <= sl),
+            levels.target.is_some_and(|pt| mark >= pt),
+        )
+    };
+    if hit_stop {
+        Some("stop")
+    } else if hit_target {
+        Some("target")
+    } else {
+        None
+    }
+}
+
+pub struct ExitManager<B: Broker> {
+    exec: Executor<B>,
+}
+
+impl<B: Broker + 'static> ExitManager<B> {
+    pub fn new(exec: Executor<B>) -> Self {
+        Self { exec }
+    }
+
+    /// One pass over all armed brackets. Brackets whose holding is gone are dropped;
+    /// triggered ones are disarmed and their exit handed to the executor, and re-armed
+    /// when the exit is not placed or leaves the position open. Outside the regular
+    /// session under an `exec.outside_hours` policy nothing triggers. Returns the handles
+    /// of the close monitors that were spawned. Must run inside a `LocalSet`.
+    pub async fn check(&self) -> Vec<JoinHandle<()>> {
+        let state = self.exec.state();
+        let mut handles = Vec::new();
+        if self.exec.outside_session() {
+            return handles;
+        }
+        let brackets = state.lock().await.brackets.clone();
+        for b in brackets {
+            let held = state.lock().await.held_for(&b.exit);
+            if held <= 0.0 {
+                state.lock().await.remove_bracket(b.ticker_id);
+                continue;
+            }
+            let mark = match self.exec.broker().mid_price(b.ticker_id).await {
+                Ok(m) if m > 0.0 => m,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("exit check quote failed (ticker {}): {:#}", b.ticker_id, e);
+                    continue;
+                }
+            };
+            let Some(kind) = triggered(&b.levels, mark, b.exit.action() == Action::BTC) else {
+                continue;
+            };
+            info!(
+                "Exit {} hit at mark {:.4} ({:?}) -> {:?}",
+                kind, mark, b.levels, b.exit
+            );
+            // disarmed while the exit is in flight so the next pass does not fire it again
+            state.lock().await.remove_bracket(b.ticker_id);
+            let exit = b.exit.clone();
+            let placed = match &b.author {
+                Some(key) => self.exec.handle_attributed_signal(key, exit).await,
+                None => self.exec.handle_signal(exit).await,
+            };
+            let Some(monitor) = placed else {
+                error!("{} exit not placed; levels stay armed", b.exit.label());
+                state.lock().await.set_bracket(b);
+                continue;
+            };
+            let state = self.exec.state();
+            handles.push(tokio::task::spawn_local(async move {
+                let _ = monitor.await;
+                let mut st = state.lock().await;
+                let rearmed = st.brackets.iter().any(|x| x.ticker_id == b.ticker_id);
+                if st.held_for(&b.exit) > 0.0 && !rearmed {
+                    info!(
+                        "{} still held after its exit; levels re-armed",
+                        b.exit.label()
+                    );
+                    st.set_bracket(b);
+                }
+            }));
+        }
+        handles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::instrument::InstrumentId;
+    use crate::parser::parse_signal_on;
+    use crate::risk::RiskEngine;
+    use crate::sim_broker::SimBroker;
+    use crate::state::BotState;
+    use crate::store::StateStore;
+    use chrono::{NaiveDate, NaiveDateTime};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 8, 1).unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn target_then_stop_fire_stc_for_whole_position() {
+        let sim = Arc::new(SimBroker::new());
+        let aapl = sim.add_stock("AAPL");
+        let spy = sim.add_option("SPY", 450.0, 'C', "2025-09-20");
+        sim.set_price(aapl, 100.0);
+        sim.set_price(spy, 2.0);
+        sim.set_time(now() + chrono::Duration::hours(14));
+        let cfg = test_config("exits");
+        let risk = RiskEngine::from_cfg(&cfg.risk);
+        let state = Arc::new(Mutex::new(BotState::default()));
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let exec = Executor::new(Arc::clone(&sim), Arc::clone(&state), store, risk, cfg);
+        let mgr = ExitManager::new(exec.clone());
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                for msg in [
+                    "BTO 10 AAPL @ 100 SL 95 PT 110",
+                    "BTO 3 SPY 450C 09/20 @ 2 SL 1.5",
+                ] {
+                    let h = exec
+                        .handle_signal(parse_signal_on(msg, now()).unwrap())
+                        .await;
+                    h.unwrap().await.unwrap();
+                }
+                assert_eq!(state.lock().await.brackets.len(), 2);
+
+                // nothing triggers inside the range
+                sim.set_price(aapl, 105.0);
+                assert!(mgr.check().await.is_empty());
+
+                sim.set_price(aapl, 111.0);
+                sim.set_price(spy, 1.4);
+                for h in mgr.check().await {
+                    h.await.unwrap();
+                }
+            })
+            .await;
+
+        let st = state.lock().await;
+        assert!(st.holdings.is_empty());
+        assert!(st.brackets.is_empty());
+        let pl: Vec<f64> = st.daily_pl.iter().map(|e| e.realized_pl).collect();
+        // sell_mode is LIMIT, so both exits go out limited at the current mark
+        assert!((pl[0] - 110.0).abs() < 1e-6);
+        assert!((pl[1] + 180.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn rejected_exit_keeps_its_levels_armed() {
+        let sim = Arc::new(SimBroker::new());
+        let aapl = sim.add_stock("AAPL");
+        sim.set_price(aapl, 100.0);
+        let cfg = test_config("exits-rejected");
+        let risk = RiskEngine::from_cfg(&cfg.risk);
+        let state = Arc::new(Mutex::new(BotState::default()));
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let exec = Executor::new(Arc::clone(&sim), Arc::clone(&state), store, risk, cfg);
+        let mgr = ExitManager::new(exec.clone());
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let entry = parse_signal_on("BTO 10 AAPL @ 100 SL 95", now()).unwrap();
+                exec.handle_signal(entry).await.unwrap().await.unwrap();
+
+                // reconciliation froze AAPL: the stop fires but risk refuses the exit
+                state.lock().await.frozen.push(InstrumentId::stock("AAPL"));
+                sim.set_price(aapl, 94.0);
+                assert!(mgr.check().await.is_empty());
+                assert_eq!(state.lock().await.brackets.len(), 1);
+
+                state.lock().await.frozen.clear();
+                for h in mgr.check().await {
+                    h.await.unwrap();
+                }
+            })
+            .await;
+
+        let st = state.lock().await;
+        assert!(st.holdings.is_empty());
+        assert!(st.brackets.is_empty());
+    }
+
+    #[test]
+    fn short_levels_trigger_in_reverse() {
+        let levels = ExitLevels {
+            stop: Some(3.0),
+            target: Some(0.5),
+        };
+        assert_eq!(triggered(&levels, 3.2, true), Some("stop"));
+        assert_eq!(triggered(&levels, 0.4, true), Some("target"));
+        assert_eq!(triggered(&levels, 1.5, true), None);
+
+        let sto = parse_signal_on("STO 2 SPY 440P 09/20 @ 1.50 SL 3 PT 0.5", now()).unwrap();
+        let b = bracket_for_entry(&sto, 7, None).unwrap();
+        assert_eq!(b.exit.action(), Action::BTC);
+    }
+}
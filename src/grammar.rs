@@ -0,0 +1,669 @@
+//! Signal grammars: one [`SignalParser`] per message format, chosen per author
+//! (`authors[].grammar`), per channel (`discord.channel_grammars`) or for all channels
+//! (`discord.grammar`).
+//!
+//! Built in:
+//! * `standard`: the `parser` module ("BTO 2 SPY 450C 09/20 @ 2.50");
+//! * `cashtag`: "$AAPL 8/16 150c @2.5", "sold 2 $AAPL 8/16 150c @ 3.10", "$AAPL @ 190";
+//! * `prose`: "Bought AAPL 150 calls exp 8/16 for 2.50", "Sold 100 AAPL at 195".
+//!
+//! More formats can be declared in `config.yaml` under `grammars` ([`GrammarCfg`]):
+//! regexes whose capture groups map to signal fields, compiled once at startup and
+//! checked against sample messages.
+//!
+//! Entries default to BTO when a grammar allows the action to be left out.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDateTime;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::calendar;
+use crate::config::{author_index, AppConfig, AuthorCfg};
+use crate::instrument::OptionKey;
+use crate::parser::{diagnose_on, parse_signals_on, ParseOutcome, SYMBOL};
+use crate::types::{Action, Author, ExitLevels, OptionSignal, OrderType, StockSignal, TradeSignal};
+use crate::utils::{parse_expiry, parse_relative_expiry, sanitize_symbol};
+
+/// One message format.
+pub trait SignalParser: Send + Sync {
+    /// Every signal in `text` posted at `now` (US/Eastern), which resolves year-less and
+    /// relative expiries.
+    fn parse(&self, text: &str, now: NaiveDateTime) -> Vec<TradeSignal>;
+
+    /// [`SignalParser::parse`] with the reason nothing was found, where the grammar
+    /// can tell. By default an empty result is simply not a signal.
+    fn diagnose(&self, text: &str, now: NaiveDateTime) -> ParseOutcome {
+        match self.parse(text, now) {
+            s if s.is_empty() => ParseOutcome::NotASignal,
+            s => ParseOutcome::Parsed(s),
+        }
+    }
+}
+
+/// The `parser` module's grammar.
+pub struct Standard;
+
+impl SignalParser for Standard {
+    fn parse(&self, text: &str, now: NaiveDateTime) -> Vec<TradeSignal> {
+        parse_signals_on(text, now)
+    }
+
+    fn diagnose(&self, text: &str, now: NaiveDateTime) -> ParseOutcome {
+        diagnose_on(text, now)
+    }
+}
+
+/// "$AAPL 8/16 150c @2.5": cashtag, expiry, strike with C/P, then price. An optional
+/// leading action ("sold", "STC") and quantity ("2" or "2x") come before the cashtag.
+pub struct Cashtag {
+    option: Regex,
+    stock: Regex,
+}
+
+impl Cashtag {
+    pub fn new() -> Self {
+        Self {
+            option: Regex::new(r"(?i)^(?:(BTO|STC|STO|BTC|buy|bought|sell|sold)\s+)?(?:(\d+)x?\s+)?\$([A-Z]{1,6}(?:\.[A-Z]{1,2})?)\s+(\d{1,2}/\d{1,2}(?:/\d{2}(?:\d{2})?)?)\s+(\d+(?:\.\d+)?)\s*([CP])\s*@\s*(m|\d*\.?\d+)$").unwrap(),
+            stock: Regex::new(r"(?i)^(?:(BTO|STC|STO|BTC|buy|bought|sell|sold)\s+)?(?:(\d+)x?\s+)?\$([A-Z]{1,6}(?:\.[A-Z]{1,2})?)\s*@\s*(m|\d*\.?\d+)$").unwrap(),
+        }
+    }
+}
+
+impl Default for Cashtag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalParser for Cashtag {
+    fn parse(&self, text: &str, now: NaiveDateTime) -> Vec<TradeSignal> {
+        each_line(text, |line| {
+            if let Some(c) = self.option.captures(line) {
+                let key = option_key(&c[3], &c[5], &c[6], &c[4], now)?;
+                return option(&c, key, 7);
+            }
+            let c = self.stock.captures(line)?;
+            stock(&c, &c[3], 4)
+        })
+    }
+}
+
+/// "Bought AAPL 150 calls exp 8/16 for 2.50", "Sold 2 SPY 450 puts 09/20 at market",
+/// "Bought 100 shares of AAPL at 190".
+pub struct Prose {
+    option: Regex,
+    stock: Regex,
+}
+
+impl Prose {
+    pub fn new() -> Self {
+        Self {
+            option: Regex::new(&format!(r"(?i)^(bought|sold|buying|selling)\s+(?:(\d+)\s+)?({SYMBOL})\s+(\d+(?:\.\d+)?)\s*(calls?|puts?|c|p)\s+(?:exp(?:iring|iry)?\.?\s+)?(\d{{1,2}}/\d{{1,2}}(?:/\d{{2}}(?:\d{{2}})?)?|\d{{4}}-\d{{2}}-\d{{2}})\s+(?:for|at|@)\s*(market|m|\d*\.?\d+)$")).unwrap(),
+            stock: Regex::new(&format!(r"(?i)^(bought|sold|buying|selling)\s+(?:(\d+)\s+(?:shares?\s+(?:of\s+)?)?)?({SYMBOL})\s+(?:for|at|@)\s*(market|m|\d*\.?\d+)$")).unwrap(),
+        }
+    }
+}
+
+impl Default for Prose {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalParser for Prose {
+    fn parse(&self, text: &str, now: NaiveDateTime) -> Vec<TradeSignal> {
+        each_line(text, |line| {
+            if let Some(c) = self.option.captures(line) {
+                let cp = &c[5][..1];
+                let key = option_key(&c[3], &c[4], cp, &c[6], now)?;
+                return option(&c, key, 7);
+            }
+            let c = self.stock.captures(line)?;
+            stock(&c, &c[3], 4)
+        })
+    }
+}
+
+/// Signal fields a declared grammar can capture.
+const FIELDS: [&str; 7] = [
+    "action", "symbol", "quantity", "strike", "call_put", "expiry", "price",
+];
+
+/// A signal format declared in config.
+///
+/// ```yaml
+/// grammars:
+///   - name: alerts
+///     patterns:
+///       - '(?i)^(in|out)\s+\$([A-Z]+)\s+(\d+)([cp])\s+(\d+/\d+)\s+@\s*([\d.]+|m)$'
+///     fields: { action: "1", symbol: "2", strike: "3", call_put: "4", expiry: "5",
+///               price: "6" }
+///     actions: { in: BTO, out: STC }
+///     samples: ["in $SPY 450c 9/20 @ 1.10"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct GrammarCfg {
+    pub name: String,
+    /// Regexes tried in order on each line of a message; the first that yields a
+    /// signal wins. Anchor them (`^...$`) to refuse lines with extra text.
+    pub patterns: Vec<String>,
+    /// Field -> capture group name or number, shared by all patterns (prefer names
+    /// when they differ). Fields left out use the group named like the field.
+    /// `symbol` is required; `strike`, `call_put` and `expiry` make an option; a
+    /// missing `quantity` is 1 and a missing `price` a market order.
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Words captured by `action`, beyond BTO/STC/STO/BTC, buy/bought and sell/sold.
+    #[serde(default)]
+    pub actions: HashMap<String, Action>,
+    /// Action when the pattern captures none.
+    #[serde(default = "default_action")]
+    pub default_action: Action,
+    /// Messages that must each parse to at least one signal; checked at startup.
+    #[serde(default)]
+    pub samples: Vec<String>,
+}
+
+fn default_action() -> Action {
+    Action::BTO
+}
+
+/// A [`GrammarCfg`] compiled.
+pub struct RegexGrammar {
+    patterns: Vec<Regex>,
+    /// Capture group per entry of [`FIELDS`].
+    groups: Vec<String>,
+    actions: HashMap<String, Action>,
+    default_action: Action,
+}
+
+impl RegexGrammar {
+    /// Compile the patterns and check that the mapped groups exist and every sample
+    /// parses.
+    pub fn compile(cfg: &GrammarCfg) -> Result<Self> {
+        if let Some(f) = cfg.fields.keys().find(|f| !FIELDS.contains(&f.as_str())) {
+            bail!("grammar {}: unknown field {:?}", cfg.name, f);
+        }
+        if cfg.patterns.is_empty() {
+            bail!("grammar {}: no patterns", cfg.name);
+        }
+        let patterns = cfg
+            .patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("grammar {}: bad pattern", cfg.name)))
+            .collect::<Result<Vec<_>>>()?;
+        let groups: Vec<String> = FIELDS
+            .iter()
+            .map(|f| cfg.fields.get(*f).cloned().unwrap_or_else(|| f.to_string()))
+            .collect();
+        let has = |re: &Regex, g: &str| match g.parse::<usize>() {
+            Ok(i) => i > 0 && i < re.captures_len(),
+            Err(_) => re.capture_names().any(|n| n == Some(g)),
+        };
+        for re in &patterns {
+            if !has(re, &groups[1]) {
+                bail!("grammar {}: pattern {} has no symbol group", cfg.name, re);
+            }
+            let option = groups[3..6].iter().filter(|g| has(re, g)).count();
+            if option != 0 && option != 3 {
+                bail!(
+                    "grammar {}: pattern {} needs strike, call_put and expiry together",
+                    cfg.name,
+                    re
+                );
+            }
+        }
+        for (f, g) in &cfg.fields {
+            if !patterns.iter().any(|re| has(re, g)) {
+                bail!(
+                    "grammar {}: no pattern has group {:?} for {}",
+                    cfg.name,
+                    g,
+                    f
+                );
+            }
+        }
+        let grammar = Self {
+            patterns,
+            groups,
+            actions: cfg
+                .actions
+                .iter()
+                .map(|(w, a)| (w.to_lowercase(), *a))
+                .collect(),
+            default_action: cfg.default_action,
+        };
+        let now = calendar::eastern_now();
+        if let Some(bad) = cfg
+            .samples
+            .iter()
+            .find(|m| grammar.parse(m, now).is_empty())
+        {
+            bail!("grammar {}: sample {:?} does not parse", cfg.name, bad);
+        }
+        Ok(grammar)
+    }
+
+    fn signal(&self, c: &Captures, now: NaiveDateTime) -> Option<TradeSignal> {
+        let field = |i: usize| {
+            let g = &self.groups[i];
+            match g.parse::<usize>() {
+                Ok(n) => c.get(n),
+                Err(_) => c.name(g),
+            }
+            .map(|m| m.as_str().trim())
+        };
+        let action = match field(0) {
+            Some(w) => match self.actions.get(&w.to_lowercase()) {
+                Some(a) => *a,
+                None => action(Some(w))?,
+            },
+            None => self.default_action,
+        };
+        let symbol = sanitize_symbol(field(1)?);
+        let quantity = match field(2) {
+            Some(q) => q.parse().ok().filter(|q| *q > 0)?,
+            None => 1,
+        };
+        let (order_type, limit_price) = match field(6) {
+            Some(p) => price(p)?,
+            None => (OrderType::Market, None),
+        };
+        let signal = match (field(3), field(4), field(5)) {
+            (Some(strike), Some(cp), Some(expiry)) => {
+                let key = option_key(&symbol, strike, cp, expiry, now)?;
+                if !matches!(key.call_put, 'C' | 'P') {
+                    return None;
+                }
+                TradeSignal::Option(OptionSignal {
+                    action,
+                    key,
+                    quantity,
+                    order_type,
+                    limit_price,
+                    relative_qty: None,
+                    exit_levels: ExitLevels::default(),
+                    relative_expiry: parse_relative_expiry(expiry),
+                })
+            }
+            (None, None, None) => TradeSignal::Stock(StockSignal {
+                action,
+                symbol,
+                quantity,
+                order_type,
+                limit_price,
+                relative_qty: None,
+                exit_levels: ExitLevels::default(),
+            }),
+            _ => return None,
+        };
+        Some(signal)
+    }
+}
+
+impl SignalParser for RegexGrammar {
+    fn parse(&self, text: &str, now: NaiveDateTime) -> Vec<TradeSignal> {
+        each_line(text, |line| {
+            self.patterns
+                .iter()
+                .find_map(|re| self.signal(&re.captures(line)?, now))
+        })
+    }
+}
+
+/// A built-in grammar by name.
+pub fn builtin(name: &str) -> Option<Arc<dyn SignalParser>> {
+    match &name.to_ascii_lowercase()[..] {
+        "standard" => Some(Arc::new(Standard)),
+        "cashtag" => Some(Arc::new(Cashtag::new())),
+        "prose" => Some(Arc::new(Prose::new())),
+        _ => None,
+    }
+}
+
+/// Grammar selection from config: the author's grammar, else the channel's, else the
+/// default.
+pub struct Grammars {
+    default: Arc<dyn SignalParser>,
+    channels: HashMap<String, Arc<dyn SignalParser>>,
+    /// Every author block, and the grammar each names (by index).
+    authors: Vec<AuthorCfg>,
+    author_grammars: Vec<Option<Arc<dyn SignalParser>>>,
+}
+
+impl Grammars {
+    /// Compiles the declared grammars; fails on a bad one or on a grammar name that is
+    /// not defined.
+    pub fn from_cfg(cfg: &AppConfig) -> Result<Self> {
+        let mut declared: HashMap<String, Arc<dyn SignalParser>> = HashMap::new();
+        for g in &cfg.grammars {
+            let name = g.name.to_lowercase();
+            if builtin(&name).is_some() || declared.contains_key(&name) {
+                bail!("signal grammar {:?} defined twice", g.name);
+            }
+            declared.insert(name, Arc::new(RegexGrammar::compile(g)?));
+        }
+        let lookup = |name: &str| match declared.get(&name.to_lowercase()) {
+            Some(g) => Ok(Arc::clone(g)),
+            None => match builtin(name) {
+                Some(g) => Ok(g),
+                None => bail!("unknown signal grammar {:?}", name),
+            },
+        };
+        let default = match &cfg.discord.grammar {
+            Some(name) => lookup(name)?,
+            None => Arc::new(Standard),
+        };
+        let mut channels = HashMap::new();
+        for (channel, name) in &cfg.discord.channel_grammars {
+            channels.insert(channel.clone(), lookup(name)?);
+        }
+        let author_grammars = cfg
+            .authors
+            .iter()
+            .map(|a| a.grammar.as_deref().map(lookup).transpose())
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            default,
+            channels,
+            authors: cfg.authors.clone(),
+            author_grammars,
+        })
+    }
+
+    /// The grammar for a message from `author`, in `channel` when known. The author's
+    /// block is found by [`author_index`].
+    pub fn for_message(&self, channel: Option<&str>, author: &Author) -> &dyn SignalParser {
+        if let Some(g) =
+            author_index(&self.authors, author).and_then(|i| self.author_grammars[i].as_ref())
+        {
+            return g.as_ref();
+        }
+        channel
+            .and_then(|c| self.channels.get(c))
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+impl Default for Grammars {
+    /// `standard` everywhere.
+    fn default() -> Self {
+        Self {
+            default: Arc::new(Standard),
+            channels: HashMap::new(),
+            authors: Vec::new(),
+            author_grammars: Vec::new(),
+        }
+    }
+}
+
+/// Signals from the lines of `text` that `f` accepts; the rest is commentary.
+fn each_line(text: &str, f: impl Fn(&str) -> Option<TradeSignal>) -> Vec<TradeSignal> {
+    text.lines().filter_map(|l| f(l.trim())).collect()
+}
+
+/// Entry/exit words; a missing action means an entry.
+fn action(raw: Option<&str>) -> Option<Action> {
+    let Some(raw) = raw else {
+        return Some(Action::BTO);
+    };
+    match &raw.to_ascii_lowercase()[..] {
+        "bto" | "buy" | "bought" | "buying" => Some(Action::BTO),
+        "stc" | "sell" | "sold" | "selling" => Some(Action::STC),
+        "sto" => Some(Action::STO),
+        "btc" => Some(Action::BTC),
+        _ => None,
+    }
+}
+
+/// "m" / "market" -> market order, otherwise a limit price.
+fn price(raw: &str) -> Option<(OrderType, Option<f64>)> {
+    match &raw.to_ascii_lowercase()[..] {
+        "m" | "market" => Some((OrderType::Market, None)),
+        p => Some((OrderType::Limit, Some(p.parse().ok()?))),
+    }
+}
+
+fn option_key(
+    symbol: &str,
+    strike: &str,
+    cp: &str,
+    expiry: &str,
+    now: NaiveDateTime,
+) -> Option<OptionKey> {
+    let cp = cp.chars().next()?.to_ascii_uppercase();
+    Some(OptionKey::new(
+        &sanitize_symbol(symbol),
+        strike.parse().ok()?,
+        cp,
+        parse_expiry(expiry, now)?,
+    ))
+}
+
+/// Action in group 1, optional quantity in group 2 (1 when omitted), price at `px`.
+fn option(c: &Captures, key: OptionKey, px: usize) -> Option<TradeSignal> {
+    let (order_type, limit_price) = price(&c[px])?;
+    Some(TradeSignal::Option(OptionSignal {
+        action: action(c.get(1).map(|m| m.as_str()))?,
+        key,
+        quantity: quantity(c)?,
+        order_type,
+        limit_price,
+        relative_qty: None,
+        exit_levels: ExitLevels::default(),
+        relative_expiry: None,
+    }))
+}
+
+/// Like [`option`], for a stock `symbol`.
+fn stock(c: &Captures, symbol: &str, px: usize) -> Option<TradeSignal> {
+    let (order_type, limit_price) = price(&c[px])?;
+    Some(TradeSignal::Stock(StockSignal {
+        action: action(c.get(1).map(|m| m.as_str()))?,
+        symbol: sanitize_symbol(symbol),
+        quantity: quantity(c)?,
+        order_type,
+        limit_price,
+        relative_qty: None,
+        exit_levels: ExitLevels::default(),
+    }))
+}
+
+fn quantity(c: &Captures) -> Option<u32> {
+    match c.get(2) {
+        Some(q) => q.as_str().parse().ok().filter(|q| *q > 0),
+        None => Some(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use chrono::NaiveDate;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 8, 1).unwrap().into()
+    }
+
+    fn one(g: &dyn SignalParser, msg: &str) -> TradeSignal {
+        let mut sigs = g.parse(msg, now());
+        assert_eq!(sigs.len(), 1, "{msg}");
+        sigs.pop().unwrap()
+    }
+
+    fn key(symbol: &str, strike: f64, cp: char, m: u32, d: u32) -> OptionKey {
+        OptionKey::new(
+            symbol,
+            strike,
+            cp,
+            NaiveDate::from_ymd_opt(2025, m, d).unwrap(),
+        )
+    }
+
+    #[test]
+    fn cashtag_and_prose_formats() {
+        let cashtag = Cashtag::new();
+        let TradeSignal::Option(o) = one(&cashtag, "$AAPL 8/16 150c @2.5") else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!((o.action, o.quantity), (Action::BTO, 1));
+        assert_eq!(o.key, key("AAPL", 150.0, 'C', 8, 16));
+        assert_eq!(o.limit_price, Some(2.5));
+        let TradeSignal::Option(o) = one(&cashtag, "sold 2x $spy 9/20 450P @ m") else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!((o.action, o.quantity), (Action::STC, 2));
+        assert_eq!(o.order_type, OrderType::Market);
+        assert!(matches!(
+            one(&cashtag, "$AAPL @ 190"),
+            TradeSignal::Stock(_)
+        ));
+        assert!(cashtag.parse("AAPL 8/16 150c @2.5", now()).is_empty());
+
+        let prose = Prose::new();
+        let TradeSignal::Option(o) = one(&prose, "Bought AAPL 150 calls exp 8/16 for 2.50") else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!(o.key, key("AAPL", 150.0, 'C', 8, 16));
+        assert_eq!(o.limit_price, Some(2.5));
+        let TradeSignal::Option(o) = one(&prose, "sold 3 SPY 450 puts 09/20 at market") else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!(
+            (o.action, o.quantity, o.key.call_put),
+            (Action::STC, 3, 'P')
+        );
+        let TradeSignal::Stock(s) = one(&prose, "Bought 100 shares of AAPL at 190") else {
+            panic!("expected StockSignal");
+        };
+        assert_eq!((s.symbol.as_str(), s.quantity), ("AAPL", 100));
+        assert!(prose.parse("BTO 1 AAPL @ 190", now()).is_empty());
+    }
+
+    #[test]
+    fn declared_grammars_compile_and_check_samples() {
+        let yaml = r#"
+name: alerts
+patterns:
+  - '(?i)^(?P<action>in|out)\s+\$(?P<symbol>[A-Z]+)\s+(?P<strike>\d+)(?P<call_put>[cp])\s+(?P<expiry>\d+/\d+)\s+@\s*(?P<price>[\d.]+|m)$'
+  - '(?i)^(?P<qty>\d+)\s+shares\s+(?P<symbol>[A-Z]+)$'
+fields: { quantity: qty }
+actions: { in: BTO, out: STC }
+samples: ["in $SPY 450c 9/20 @ 1.10", "100 shares AAPL"]
+"#;
+        let cfg: GrammarCfg = serde_yaml::from_str(yaml).unwrap();
+        let g = RegexGrammar::compile(&cfg).unwrap();
+        let TradeSignal::Option(o) = one(&g, "OUT $spy 450P 9/20 @ m") else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!(o.action, Action::STC);
+        assert_eq!(o.key, key("SPY", 450.0, 'P', 9, 20));
+        assert_eq!(o.order_type, OrderType::Market);
+        let TradeSignal::Stock(s) = one(&g, "lots of text\n25 shares msft") else {
+            panic!("expected StockSignal");
+        };
+        assert_eq!(
+            (s.action, s.symbol.as_str(), s.quantity),
+            (Action::BTO, "MSFT", 25)
+        );
+        assert!(g.parse("in $SPY 450c @ 1.10", now()).is_empty());
+
+        let broken = |edit: &dyn Fn(&mut GrammarCfg)| {
+            let mut c = cfg.clone();
+            edit(&mut c);
+            RegexGrammar::compile(&c).is_err()
+        };
+        assert!(broken(&|c| c.samples.push("in $SPY".into())));
+        assert!(broken(&|c| c.patterns.push("(?P<sym>[A-Z]+)".into())));
+        assert!(broken(&|c| c.patterns.push("(".into())));
+        assert!(broken(&|c| {
+            c.fields.insert("strik".into(), "x".into());
+        }));
+        assert!(broken(
+            &|c| c.patterns[0] = c.patterns[0].replace("call_put", "cp")
+        ));
+    }
+
+    #[test]
+    fn grammar_chosen_by_author_then_channel() {
+        let mut cfg = test_config("grammars");
+        cfg.discord.channel_grammars = HashMap::from([("1".to_string(), "cashtag".into())]);
+        cfg.authors = serde_yaml::from_str("[{ name: bob, grammar: prose }]").unwrap();
+        let g = Grammars::from_cfg(&cfg).unwrap();
+        let msg = "Bought AAPL 150 calls exp 8/16 for 2.50";
+        assert_eq!(
+            g.for_message(Some("1"), &Author::named("bob"))
+                .parse(msg, now())
+                .len(),
+            1
+        );
+        assert!(g
+            .for_message(Some("1"), &Author::named("alice"))
+            .parse(msg, now())
+            .is_empty());
+        let cash = "$AAPL 8/16 150c @2.5";
+        assert_eq!(
+            g.for_message(Some("1"), &Author::named("alice"))
+                .parse(cash, now())
+                .len(),
+            1
+        );
+        assert!(g
+            .for_message(Some("2"), &Author::named("alice"))
+            .parse(cash, now())
+            .is_empty());
+
+        cfg.discord.grammar = Some("klingon".into());
+        assert!(Grammars::from_cfg(&cfg).is_err());
+        cfg.grammars = serde_yaml::from_str(
+            r#"[{ name: klingon, patterns: ['^qapla ([A-Z]+)$'], fields: { symbol: "1" },
+                 samples: [qapla AAPL] }]"#,
+        )
+        .unwrap();
+        let g = Grammars::from_cfg(&cfg).unwrap();
+        assert_eq!(
+            g.for_message(None, &Author::named("alice"))
+                .parse("qapla TSLA", now())
+                .len(),
+            1
+        );
+        cfg.grammars[0].name = "Prose".into();
+        assert!(Grammars::from_cfg(&cfg).is_err());
+    }
+
+    #[test]
+    fn author_blocks_match_by_id_then_exact_name() {
+        let mut cfg = test_config("grammar-authors");
+        cfg.discord.substring_match = true;
+        cfg.authors = serde_yaml::from_str(
+            r#"[{ name: alice, user_id: "42", grammar: prose },
+                { name: bob, grammar: cashtag }]"#,
+        )
+        .unwrap();
+        let g = Grammars::from_cfg(&cfg).unwrap();
+        let by = |id: Option<&str>, name: &str| Author {
+            id: id.map(str::to_string),
+            name: name.into(),
+        };
+        let prose = "Bought AAPL 150 calls exp 8/16 for 2.50";
+        let cash = "$AAPL 8/16 150c @2.5";
+        let parses = |a: &Author, msg: &str| !g.for_message(None, a).parse(msg, now()).is_empty();
+
+        // alice renamed keeps her block; someone else calling themselves alice does not
+        assert!(parses(&by(Some("42"), "alice_v2"), prose));
+        assert_eq!(cfg.author(&by(Some("42"), "x")).unwrap().name, "alice");
+        assert!(!parses(&by(Some("7"), "alice"), prose));
+        assert!(cfg.author(&by(Some("7"), "alice")).is_none());
+        // name-only blocks match exactly, never as substrings
+        assert!(parses(&by(Some("7"), "Bob"), cash));
+        assert!(!parses(&by(Some("8"), "bobby_scammer"), cash));
+        assert!(cfg.author(&by(Some("8"), "bobby_scammer")).is_none());
+        // without an ID (name-only exports) any block matches by name
+        assert!(parses(&Author::named("alice"), prose));
+    }
+}
@@ -0,0 +1,280 @@
+//! Canonical instrument identifiers shared by signals, holdings, risk and brokers.
+//!
+//! Two option keys are the same contract when the underlying (case-insensitive), expiry,
+//! call/put and strike (to OCC precision, 1/1000) agree; `PartialEq` and `Hash` follow
+//! that rule so keys can be compared and used in maps directly. Class-share dots are
+//! not part of the comparison ("BRK.B" and OCC root "BRKB" are one contract).
+//!
+//! Index options trade under roots of their own (SPXW for SPX weeklies, NDXP for NDX
+//! PM-settled); [`index_underlying`] and [`contract_multiplier`] map a root to the
+//! index Webull lists the chain under and to its contract size.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Index option roots: (root, index the chain is listed under, shares per contract).
+const INDEX_ROOTS: &[(&str, &str, f64)] = &[
+    ("SPX", "SPX", 100.0),
+    ("SPXW", "SPX", 100.0),
+    ("SPXPM", "SPX", 100.0),
+    ("XSP", "XSP", 100.0),
+    ("NDX", "NDX", 100.0),
+    ("NDXP", "NDX", 100.0),
+    ("XND", "XND", 100.0),
+    ("RUT", "RUT", 100.0),
+    ("RUTW", "RUT", 100.0),
+    ("MRUT", "MRUT", 100.0),
+    ("VIX", "VIX", 100.0),
+    ("VIXW", "VIX", 100.0),
+    ("DJX", "DJX", 100.0),
+    ("OEX", "OEX", 100.0),
+    ("XEO", "OEX", 100.0),
+    ("NANOS", "NANOS", 1.0),
+];
+
+/// The index an index option root trades on ("SPXW" -> "SPX"); None for equity roots.
+pub fn index_underlying(root: &str) -> Option<&'static str> {
+    INDEX_ROOTS
+        .iter()
+        .find(|(r, _, _)| r.eq_ignore_ascii_case(root.trim()))
+        .map(|(_, index, _)| *index)
+}
+
+/// Shares (or index units) per option contract under `root`: 100 unless listed otherwise.
+pub fn contract_multiplier(root: &str) -> f64 {
+    INDEX_ROOTS
+        .iter()
+        .find(|(r, _, _)| r.eq_ignore_ascii_case(root.trim()))
+        .map_or(100.0, |(_, _, m)| *m)
+}
+
+/// One listed option contract.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionKey {
+    pub symbol: String,
+    pub strike: f64,
+    pub call_put: char, // 'C' or 'P'
+    #[serde(alias = "expiry_mmdd", deserialize_with = "crate::types::de_expiry")]
+    pub expiry: NaiveDate,
+}
+
+impl OptionKey {
+    pub fn new(symbol: &str, strike: f64, call_put: char, expiry: NaiveDate) -> Self {
+        Self {
+            symbol: symbol.trim().to_ascii_uppercase(),
+            strike,
+            call_put: call_put.to_ascii_uppercase(),
+            expiry,
+        }
+    }
+
+    fn strike_millis(&self) -> i64 {
+        (self.strike * 1000.0).round() as i64
+    }
+
+    /// OCC root: the symbol without class-share dots.
+    fn root(&self) -> String {
+        self.symbol.replace('.', "").to_ascii_uppercase()
+    }
+
+    /// Index or stock the contract is on: "SPX" for an SPXW option.
+    pub fn underlying(&self) -> &str {
+        index_underlying(&self.symbol).unwrap_or(&self.symbol)
+    }
+
+    /// Contract size; premium × multiplier is the USD price of one contract.
+    pub fn multiplier(&self) -> f64 {
+        contract_multiplier(&self.symbol)
+    }
+
+    /// OCC symbol: root padded to 6, YYMMDD, C/P, strike × 1000 in 8 digits,
+    /// e.g. "SPY   250920C00450000".
+    pub fn to_occ(&self) -> String {
+        format!(
+            "{:<6}{}{}{:08}",
+            self.root(),
+            self.expiry.format("%y%m%d"),
+            self.call_put,
+            self.strike_millis()
+        )
+    }
+
+    /// Parse an OCC symbol, padded ("SPY   250920C00450000") or compact ("SPY250920C00450000").
+    pub fn from_occ(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.len() < 16 || !s.is_ascii() {
+            return None;
+        }
+        let (root, tail) = s.split_at(s.len() - 15);
+        let root = root.trim_end();
+        if root.is_empty() || root.len() > 6 {
+            return None;
+        }
+        let expiry = NaiveDate::parse_from_str(&tail[..6], "%y%m%d").ok()?;
+        let cp = tail[6..7].chars().next()?.to_ascii_uppercase();
+        if !matches!(cp, 'C' | 'P') || !tail[7..].bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let millis: i64 = tail[7..].parse().ok()?;
+        Some(Self::new(root, millis as f64 / 1000.0, cp, expiry))
+    }
+}
+
+impl PartialEq for OptionKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.root() == other.root()
+            && self.call_put.eq_ignore_ascii_case(&other.call_put)
+            && self.expiry == other.expiry
+            && self.strike_millis() == other.strike_millis()
+    }
+}
+
+impl Eq for OptionKey {}
+
+impl Hash for OptionKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root().hash(state);
+        self.call_put.to_ascii_uppercase().hash(state);
+        self.expiry.hash(state);
+        self.strike_millis().hash(state);
+    }
+}
+
+/// "SPY 450C 2025-09-20" (also the realized P/L asset label).
+impl fmt::Display for OptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}{} {}",
+            self.symbol, self.strike, self.call_put, self.expiry
+        )
+    }
+}
+
+/// A tradable instrument: a stock by symbol or an option contract.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InstrumentId {
+    Stock(String),
+    Option(OptionKey),
+}
+
+impl InstrumentId {
+    pub fn stock(symbol: &str) -> Self {
+        InstrumentId::Stock(symbol.trim().to_ascii_uppercase())
+    }
+}
+
+impl fmt::Display for InstrumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstrumentId::Stock(s) => f.write_str(s),
+            InstrumentId::Option(k) => k.fmt(f),
+        }
+    }
+}
+
+/// Accepts "AAPL", "AAPL 150C 2025-08-16" or an OCC symbol.
+impl FromStr for InstrumentId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            [sym, strike_cp, expiry] => {
+                let cp = strike_cp.chars().last().unwrap_or(' ').to_ascii_uppercase();
+                if !matches!(cp, 'C' | 'P') {
+                    anyhow::bail!("bad option instrument: {}", s);
+                }
+                let strike: f64 = strike_cp[..strike_cp.len() - 1].parse()?;
+                let expiry = NaiveDate::parse_from_str(expiry, "%Y-%m-%d")
+                    .map_err(|_| anyhow::anyhow!("option expiry must be YYYY-MM-DD: {}", s))?;
+                Ok(InstrumentId::Option(OptionKey::new(
+                    sym, strike, cp, expiry,
+                )))
+            }
+            _ => {
+                if let Some(k) = OptionKey::from_occ(s) {
+                    return Ok(InstrumentId::Option(k));
+                }
+                match parts.as_slice() {
+                    [sym] => Ok(InstrumentId::stock(sym)),
+                    _ => anyhow::bail!("bad instrument: {}", s),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spy_450c() -> OptionKey {
+        OptionKey::new(
+            "spy",
+            450.0,
+            'c',
+            NaiveDate::from_ymd_opt(2025, 9, 20).unwrap(),
+        )
+    }
+
+    #[test]
+    fn occ_round_trip() {
+        let k = spy_450c();
+        assert_eq!(k.to_occ(), "SPY   250920C00450000");
+        assert_eq!(
+            OptionKey::from_occ("SPY   250920C00450000"),
+            Some(k.clone())
+        );
+        assert_eq!(OptionKey::from_occ("SPY250920C00450000"), Some(k));
+
+        let half = OptionKey::from_occ("AAPL  250816P00152500").unwrap();
+        assert_eq!(half.strike, 152.5);
+        assert_eq!(half.call_put, 'P');
+        assert!(OptionKey::from_occ("SPY250920X00450000").is_none());
+        assert!(OptionKey::from_occ("SPY").is_none());
+    }
+
+    #[test]
+    fn keys_match_on_normalized_fields() {
+        let mut other = spy_450c();
+        other.symbol = "Spy".into();
+        other.strike = 450.0000001;
+        assert_eq!(spy_450c(), other);
+        other.expiry = NaiveDate::from_ymd_opt(2026, 9, 20).unwrap();
+        assert_ne!(spy_450c(), other);
+    }
+
+    #[test]
+    fn instrument_strings() {
+        let id: InstrumentId = "SPY 450C 2025-09-20".parse().unwrap();
+        assert_eq!(id, InstrumentId::Option(spy_450c()));
+        assert_eq!(id.to_string(), "SPY 450C 2025-09-20");
+        assert_eq!("SPY   250920C00450000".parse::<InstrumentId>().unwrap(), id);
+        assert_eq!(
+            "aapl".parse::<InstrumentId>().unwrap(),
+            InstrumentId::Stock("AAPL".into())
+        );
+        assert!("SPY 450X 2025-09-20".parse::<InstrumentId>().is_err());
+    }
+
+    #[test]
+    fn index_roots_and_class_shares() {
+        let day = NaiveDate::from_ymd_opt(2025, 9, 19).unwrap();
+        let spxw = OptionKey::new("spxw", 5600.0, 'C', day);
+        assert_eq!(spxw.underlying(), "SPX");
+        assert_eq!(spxw.multiplier(), 100.0);
+        assert_eq!(spxw.to_occ(), "SPXW  250919C05600000");
+        assert_eq!(contract_multiplier("NANOS"), 1.0);
+        assert_eq!(index_underlying("AAPL"), None);
+
+        let brk = OptionKey::new("BRK.B", 450.0, 'P', day);
+        assert_eq!(brk.underlying(), "BRK.B");
+        assert_eq!(brk.to_occ(), "BRKB  250919P00450000");
+        assert_eq!(OptionKey::from_occ(&brk.to_occ()), Some(brk));
+    }
+}
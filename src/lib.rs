@@ -0,0 +1,22 @@
+//! Discord -> Webull trader library. The `discord-webull-trader` binary wires these
+//! modules to a live account; tools and tests can drive them against `SimBroker`.
+
+pub mod backtest;
+pub mod broker;
+pub mod calendar;
+pub mod config;
+pub mod discord;
+pub mod executor;
+pub mod exits;
+pub mod grammar;
+pub mod instrument;
+pub mod parser;
+pub mod reconcile;
+pub mod risk;
+pub mod sim_broker;
+pub mod sizing;
+pub mod state;
+pub mod store;
+pub mod types;
+pub mod utils;
+pub mod webull_client;
@@ -1,29 +1,329 @@
 //! Parse trade signals from Discord messages.
 //! Supported (v1.0): Stocks & Options (Market/Limit).
+//! Option expiries: "08/16" (nearest future date), "08/16/26", "08/16/2026" or "2026-08-16",
+//! or relative to the signal date: "0DTE", "weekly", "exp Fri", "next Friday", "monthly".
+//! Shorts: "STO 2 SPY 440P 09/20 @ 1.50" opens, "BTC 2 SPY 440P 09/20 @ 0.50" closes.
+//! Exits may be sized relative to the position: "STC half", "trim 1/3", "STC all", "STC 25%",
+//! "BTC all" for shorts.
+//! Any signal may end with stop/target levels: "... @ 2.50 SL 1.80 PT 3.50".
+//! Spreads: verticals "BTO 1 SPY 450/455C 09/20 @ 1.20", straddles/strangles "450C/450P".
+//! Replies to an entry may name just the exit: "out", "STC @ 3.10", "trim half @ m".
+//! [`parse_signals_on`] finds every call in a multi-line message with commentary around it.
+//! Symbols may be cashtags ("$AAPL"), class shares ("BRK.B") or index option roots ("SPXW").
 
-use crate::types::{Action, OptionSignal, OrderType, StockSignal, TradeSignal};
+use crate::calendar;
+use crate::instrument::OptionKey;
+use crate::types::{
+    Action, ExitLevels, OptionSignal, OrderType, RelativeQty, SpreadKind, SpreadLeg, SpreadSignal,
+    StockSignal, TradeSignal,
+};
+use crate::utils::{parse_expiry, parse_relative_expiry, sanitize_symbol};
+use chrono::NaiveDateTime;
 use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+/// Ticker: 1-6 letters, an optional class suffix ("BRK.B") and an optional cashtag "$".
+pub(crate) const SYMBOL: &str = r"\$?[A-Z]{1,6}(?:\.[A-Z]{1,2})?";
+/// Option expiry: "MM/DD[/YY[YY]]", "YYYY-MM-DD" or a relative expiry ("0DTE", "exp Fri").
+const EXPIRY: &str = r"\d{2}/\d{2}(?:/\d{2}(?:\d{2})?)?|\d{4}-\d{2}-\d{2}|(?:exp\.?\s+)?(?:\d+\s*DTE|weekly|monthly|opex|(?:this\s+|next\s+)?fri(?:day)?|(?:this|next)\s+week)";
+
+// Compiled once on first use.
+static HEDGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(maybe|might|watch(?:ing)?|thinking|consider(?:ing)?|wait(?:ing)?|possibly|plan(?:ning)?|don'?t|never)\b").unwrap()
+});
+static OPT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)^(BTO|STC|STO|BTC)\s+(\d+)\s+({SYMBOL})\s+(\d+(?:\.\d+)?)\s*([CP])\s+({EXPIRY})\s*@\s*(m|M|[\d\.]+)$")).unwrap()
+});
+static OPT_NOQTY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)^(BTO|STC|STO|BTC)\s+({SYMBOL})\s+(\d+(?:\.\d+)?)\s*([CP])\s+({EXPIRY})\s*@\s*(m|[\d\.]+)$")).unwrap()
+});
+static OPT_REL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)^(STC|TRIM|BTC)\s+(all|half|\d+/\d+|\d+(?:\.\d+)?%)\s+({SYMBOL})\s+(\d+(?:\.\d+)?)\s*([CP])\s+({EXPIRY})\s*@\s*(m|[\d\.]+)$")).unwrap()
+});
+static VERTICAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)^(BTO|STC)\s+(?:(\d+)\s+)?({SYMBOL})\s+(\d+(?:\.\d+)?)/(\d+(?:\.\d+)?)\s*([CP])\s+({EXPIRY})\s*@\s*(m|[\d\.]+)$")).unwrap()
+});
+static STRADDLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)^(BTO|STC)\s+(?:(\d+)\s+)?({SYMBOL})\s+(\d+(?:\.\d+)?)\s*([CP])\s*/\s*(\d+(?:\.\d+)?)\s*([CP])\s+({EXPIRY})\s*@\s*(m|[\d\.]+)$")).unwrap()
+});
+static STK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)^(BTO|STC|STO|BTC)\s+(\d+)\s+({SYMBOL})\s*@\s*(m|M|[\d\.]+)$"
+    ))
+    .unwrap()
+});
+static STK_NOQTY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)^(BTO|STC|STO|BTC)\s+({SYMBOL})\s*@\s*(m|[\d\.]+)$"
+    ))
+    .unwrap()
+});
+static STK_REL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)^(STC|TRIM|BTC)\s+(all|half|\d+/\d+|\d+(?:\.\d+)?%)\s+({SYMBOL})\s*@\s*(m|[\d\.]+)$"
+    ))
+    .unwrap()
+});
+static EXIT_REPLY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(STC|BTC|TRIM|SOLD|OUT|ALL\s+OUT|CLOSED?|EXIT(?:ED)?)(?:\s+(all|half|\d+/\d+|\d+(?:\.\d+)?%|\d+))?(?:\s*@\s*(m|\d+(?:\.\d+)?))?[.!]*$").unwrap()
+});
+static EXIT_LEVEL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s+(SL|STOP|PT|TP|TARGET)\s*:?\s*(\d+(?:\.\d+)?)$").unwrap());
 
 pub fn parse_signal(text: &str) -> Option<TradeSignal> {
-    // Normalize whitespace
-    let t = text.trim();
+    parse_signal_on(text, calendar::eastern_now())
+}
 
-    // Options: "BTO 10 AAPL 150C 08/16 @ 2.50" or market with @ m
-    let re_opt = Regex::new(r"(?i)^(BTO|STC)\s+(\d+)\s+([A-Z]{1,6})\s+(\d+(?:\.\d+)?)\s*([CP])\s+(\d{2}/\d{2})\s*@\s*(m|M|[\d\.]+)$").unwrap();
-    // --- Options without quantity: "BTO AAPL 150C 08/16 @ 2.50" ---
-    let re_opt_noqty = Regex::new(r"(?i)^(BTO|STC)\s+([A-Z]{1,6})\s+(\d+(?:\.\d+)?)\s*([CP])\s+(\d{2}/\d{2})\s*@\s*(m|[\d\.]+)$").unwrap();
+pub fn parse_signals(text: &str) -> Vec<TradeSignal> {
+    parse_signals_on(text, calendar::eastern_now())
+}
 
-    if let Some(c) = re_opt.captures(t) {
-        let action = match &c[1].to_uppercase()[..] {
-            "BTO" => Action::BTO,
-            "STC" => Action::STC,
-            _ => return None,
+/// Why text that looks like a call was not read as a signal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseIssue {
+    /// Hedging word ("maybe", "watching") before a call's action on its line.
+    Hedged(String),
+    /// A "?" on a call's line.
+    Question,
+    /// A parsed call runs on into this token ("@ 2.50 3.00").
+    RunsOn(String),
+    MissingPrice,
+    BadPrice(String),
+    /// Not 1-6 letters with an optional class suffix ("BRK-B", "AAPL!").
+    BadSymbol(String),
+    SymbolTooLong(String),
+    MissingExpiry,
+    UnknownExpiry(String),
+    /// Repeated stop/target, or a stop on the wrong side of the target.
+    BadLevels,
+    /// Fields look right but the layout matches no supported form.
+    Unrecognized,
+}
+
+impl fmt::Display for ParseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseIssue::Hedged(w) => write!(f, "hedged ({:?})", w),
+            ParseIssue::Question => write!(f, "question mark on the call's line"),
+            ParseIssue::RunsOn(t) => write!(f, "{:?} follows the call", t),
+            ParseIssue::MissingPrice => write!(f, "no \"@ price\""),
+            ParseIssue::BadPrice(p) => write!(f, "bad price {:?}", p),
+            ParseIssue::BadSymbol(s) => write!(f, "unsupported symbol {:?}", s),
+            ParseIssue::SymbolTooLong(s) => write!(f, "symbol {:?} too long", s),
+            ParseIssue::MissingExpiry => write!(f, "no expiry"),
+            ParseIssue::UnknownExpiry(e) => write!(f, "unknown expiry {:?}", e),
+            ParseIssue::BadLevels => write!(f, "bad stop/target levels"),
+            ParseIssue::Unrecognized => write!(f, "unrecognized format"),
+        }
+    }
+}
+
+/// A message that mentions a trade but could not be read as one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearMiss {
+    /// The offending line, trimmed.
+    pub line: String,
+    pub issue: ParseIssue,
+}
+
+/// What a message parsed to.
+#[derive(Debug, Clone)]
+pub enum ParseOutcome {
+    Parsed(Vec<TradeSignal>),
+    /// No trade action in it: chatter.
+    NotASignal,
+    NearMiss(NearMiss),
+}
+
+impl ParseOutcome {
+    /// The parsed signals; empty unless `Parsed`.
+    pub fn signals(self) -> Vec<TradeSignal> {
+        match self {
+            ParseOutcome::Parsed(s) => s,
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Every signal in a message. Each line is scanned for action words; from each one the
+/// longest run of tokens that [`parse_signal_on`] accepts is taken, so emoji, markdown
+/// and commentary around a call are skipped. Quoted lines ("> ...") are ignored. The
+/// whole message is refused (empty result) when a call is hedged ("maybe", "watching"
+/// before its action, a "?" on its line) or runs on into more numbers ("@ 2.50 3.00").
+pub fn parse_signals_on(text: &str, now: NaiveDateTime) -> Vec<TradeSignal> {
+    diagnose_on(text, now).signals()
+}
+
+/// [`parse_signals_on`] with the reason a message was refused. An action word followed
+/// by numbers that does not parse is a near miss naming the first failing field.
+pub fn diagnose_on(text: &str, now: NaiveDateTime) -> ParseOutcome {
+    let mut found = Vec::new();
+    let mut missed = None;
+    for line in text.lines() {
+        if line.trim_start().starts_with('>') {
+            continue;
+        }
+        let miss = |issue| {
+            ParseOutcome::NearMiss(NearMiss {
+                line: line.trim().to_string(),
+                issue,
+            })
+        };
+        let (mut sigs, unparsed) = match scan_line(line, now) {
+            Ok(scan) => scan,
+            Err(issue) => return miss(issue),
+        };
+        if !sigs.is_empty() && line.contains('?') {
+            return miss(ParseIssue::Question);
+        }
+        if let (None, Some(rest)) = (&missed, unparsed) {
+            missed = Some(miss(explain(&rest, now)));
+        }
+        found.append(&mut sigs);
+    }
+    if found.is_empty() {
+        return missed.unwrap_or(ParseOutcome::NotASignal);
+    }
+    ParseOutcome::Parsed(found)
+}
+
+/// Signals on one line, plus the text from the first action word that looked like a
+/// call but did not parse. Err when a parsed call is hedged by the words before its
+/// action, or runs on into what looks like more of it: a level the call could not
+/// take, or another number.
+fn scan_line(
+    line: &str,
+    now: NaiveDateTime,
+) -> Result<(Vec<TradeSignal>, Option<String>), ParseIssue> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let word = |t: &str| {
+        t.trim_matches(|c: char| !c.is_ascii_alphanumeric())
+            .to_ascii_uppercase()
+    };
+    let mut found = Vec::new();
+    let mut unparsed = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        if !matches!(&word(tokens[i])[..], "BTO" | "STC" | "STO" | "BTC" | "TRIM") {
+            i += 1;
+            continue;
+        }
+        let hit = (i + 1..=tokens.len()).rev().find_map(|j| {
+            let cand = tokens[i..j].join(" ");
+            parse_signal_on(trim_call(&cand), now).map(|sig| (j, sig))
+        });
+        let Some((j, sig)) = hit else {
+            let rest = tokens[i..].join(" ");
+            if unparsed.is_none() && rest.contains(|c: char| c.is_ascii_digit() || c == '@') {
+                unparsed = Some(trim_call(&rest).to_string());
+            }
+            i += 1;
+            continue;
         };
+        if let Some(m) = HEDGE.find(&tokens[..i].join(" ")) {
+            return Err(ParseIssue::Hedged(m.as_str().to_string()));
+        }
+        if let Some(next) = tokens.get(j) {
+            if matches!(&word(next)[..], "SL" | "STOP" | "PT" | "TP" | "TARGET") {
+                return Err(ParseIssue::BadLevels);
+            }
+            if next.starts_with(|c: char| c.is_ascii_digit() || c == '@') {
+                return Err(ParseIssue::RunsOn(next.to_string()));
+            }
+        }
+        found.push(sig);
+        i = j;
+    }
+    Ok((found, unparsed))
+}
+
+/// Drop markdown, emoji and punctuation around a call.
+fn trim_call(s: &str) -> &str {
+    s.trim_start_matches(|c: char| !c.is_ascii_alphanumeric())
+        .trim_end_matches(|c: char| !c.is_ascii_alphanumeric())
+}
+
+/// First field of `call` (starting at its action word) that keeps it from parsing.
+fn explain(call: &str, now: NaiveDateTime) -> ParseIssue {
+    let short = call.get(..3).is_some_and(|a| a.eq_ignore_ascii_case("STO"));
+    let Some((core, _)) = split_exit_levels(call, short) else {
+        return ParseIssue::BadLevels;
+    };
+    let Some((head, price)) = core.split_once('@') else {
+        return ParseIssue::MissingPrice;
+    };
+    let price = price.split_whitespace().next().unwrap_or("");
+    if !price.eq_ignore_ascii_case("m") && price.parse::<f64>().is_err() {
+        return ParseIssue::BadPrice(price.to_string());
+    }
+    let mut tokens = head.split_whitespace().skip(1).peekable();
+    // quantity or relative size
+    if tokens.peek().is_some_and(|t| {
+        t.starts_with(|c: char| c.is_ascii_digit())
+            || matches!(&t.to_ascii_lowercase()[..], "all" | "half")
+    }) {
+        tokens.next();
+    }
+    let Some(symbol) = tokens.next() else {
+        return ParseIssue::Unrecognized;
+    };
+    let (root, class) = symbol
+        .trim_start_matches('$')
+        .split_once('.')
+        .unwrap_or((symbol.trim_start_matches('$'), "A"));
+    let letters = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphabetic());
+    if !letters(root) || !letters(class) || class.len() > 2 {
+        return ParseIssue::BadSymbol(symbol.to_string());
+    }
+    if root.len() > 6 {
+        return ParseIssue::SymbolTooLong(symbol.to_string());
+    }
+    // stock form: nothing between symbol and price
+    if tokens.next().is_none() {
+        return ParseIssue::Unrecognized;
+    }
+    if tokens
+        .peek()
+        .is_some_and(|t| t.eq_ignore_ascii_case("C") || t.eq_ignore_ascii_case("P"))
+    {
+        tokens.next();
+    }
+    if tokens.peek().is_some_and(|t| t.eq_ignore_ascii_case("exp")) {
+        tokens.next();
+    }
+    // two-word relative expiries: "next Fri", "this week"
+    let expiry = match tokens.next() {
+        Some(w) if w.eq_ignore_ascii_case("next") || w.eq_ignore_ascii_case("this") => {
+            format!("{} {}", w, tokens.next().unwrap_or(""))
+        }
+        Some(w) => w.to_string(),
+        None => return ParseIssue::MissingExpiry,
+    };
+    if parse_expiry(&expiry, now).is_none() {
+        ParseIssue::UnknownExpiry(expiry)
+    } else {
+        ParseIssue::Unrecognized
+    }
+}
+
+/// Like [`parse_signal`], resolving year-less "MM/DD" and relative expiries against a
+/// signal posted at `now` (US/Eastern).
+pub fn parse_signal_on(text: &str, now: NaiveDateTime) -> Option<TradeSignal> {
+    // Normalize whitespace, then peel trailing stop/target levels off the core signal
+    let text = text.trim();
+    let short = text.get(..3).is_some_and(|a| a.eq_ignore_ascii_case("STO"));
+    let (t, levels) = split_exit_levels(text, short)?;
+
+    // Options: "BTO 10 AAPL 150C 08/16 @ 2.50" or market with @ m
+    if let Some(c) = OPT.captures(t) {
+        let action = parse_action(&c[1])?;
         let qty: u32 = c[2].parse().ok()?;
-        let symbol = c[3].to_uppercase();
+        let symbol = sanitize_symbol(&c[3]);
         let strike: f64 = c[4].parse().ok()?;
         let cp = c[5].chars().next().unwrap().to_ascii_uppercase();
-        let expiry = c[6].to_string();
+        let expiry = parse_expiry(&c[6], now)?;
+        let relative_expiry = parse_relative_expiry(&c[6]);
         let price_raw = c[7].to_ascii_lowercase();
 
         let (ot, lp) = if price_raw == "m" {
@@ -34,26 +334,24 @@ pub fn parse_signal(text: &str) -> Option<TradeSignal> {
 
         return Some(TradeSignal::Option(OptionSignal {
             action,
-            symbol,
-            strike,
-            call_put: cp,
-            expiry_mmdd: expiry,
+            key: OptionKey::new(&symbol, strike, cp, expiry),
             quantity: qty,
             order_type: ot,
             limit_price: lp,
+            relative_qty: None,
+            exit_levels: levels,
+            relative_expiry,
         }));
     }
 
-    if let Some(c) = re_opt_noqty.captures(t) {
-        let action = match &c[1].to_uppercase()[..] {
-            "BTO" => Action::BTO,
-            "STC" => Action::STC,
-            _ => return None,
-        };
-        let symbol = c[2].to_uppercase();
+    // --- Options without quantity: "BTO AAPL 150C 08/16 @ 2.50" ---
+    if let Some(c) = OPT_NOQTY.captures(t) {
+        let action = parse_action(&c[1])?;
+        let symbol = sanitize_symbol(&c[2]);
         let strike: f64 = c[3].parse().ok()?;
         let cp = c[4].chars().next().unwrap().to_ascii_uppercase();
-        let expiry = c[5].to_string();
+        let expiry = parse_expiry(&c[5], now)?;
+        let relative_expiry = parse_relative_expiry(&c[5]);
         let price_raw = c[6].to_ascii_lowercase();
 
         let (ot, lp) = if price_raw == "m" {
@@ -64,28 +362,127 @@ pub fn parse_signal(text: &str) -> Option<TradeSignal> {
 
         return Some(TradeSignal::Option(OptionSignal {
             action,
-            symbol,
-            strike,
-            call_put: cp,
-            expiry_mmdd: expiry,
+            key: OptionKey::new(&symbol, strike, cp, expiry),
             quantity: 1, // default when qty missing
             order_type: ot,
             limit_price: lp,
+            relative_qty: None,
+            exit_levels: levels,
+            relative_expiry,
         }));
     }
 
-    // Stocks: "BTO 100 AAPL @ m" or with a limit price
-    let re_stk = Regex::new(r"(?i)^(BTO|STC)\s+(\d+)\s+([A-Z]{1,6})\s*@\s*(m|M|[\d\.]+)$").unwrap();
-    let re_stk_noqty = Regex::new(r"(?i)^(BTO|STC)\s+([A-Z]{1,6})\s*@\s*(m|[\d\.]+)$").unwrap();
+    // --- Relative-size option exits: "STC half AAPL 150C 08/16 @ 3.10", "trim 1/3 ..." ---
+    if let Some(c) = OPT_REL.captures(t) {
+        let rel = parse_relative_qty(&c[2])?;
+        let rel_action = relative_exit_action(&c[1]);
+        let symbol = sanitize_symbol(&c[3]);
+        let strike: f64 = c[4].parse().ok()?;
+        let cp = c[5].chars().next().unwrap().to_ascii_uppercase();
+        let expiry = parse_expiry(&c[6], now)?;
+        let relative_expiry = parse_relative_expiry(&c[6]);
+        let price_raw = c[7].to_ascii_lowercase();
 
-    if let Some(c) = re_stk.captures(t) {
-        let action = match &c[1].to_uppercase()[..] {
-            "BTO" => Action::BTO,
-            "STC" => Action::STC,
-            _ => return None,
+        let (ot, lp) = if price_raw == "m" {
+            (OrderType::Market, None)
+        } else {
+            (OrderType::Limit, Some(price_raw.parse().ok()?))
         };
+
+        return Some(TradeSignal::Option(OptionSignal {
+            action: rel_action,
+            key: OptionKey::new(&symbol, strike, cp, expiry),
+            quantity: 0, // resolved against holdings at execution time
+            order_type: ot,
+            limit_price: lp,
+            relative_qty: Some(rel),
+            exit_levels: levels,
+            relative_expiry,
+        }));
+    }
+
+    // --- Spreads (quantity optional): vertical "450/455C", straddle/strangle "450C/455P" ---
+    let spread_caps = if let Some(c) = VERTICAL.captures(t) {
+        let cp = c[6].chars().next().unwrap().to_ascii_uppercase();
+        let (k1, k2): (f64, f64) = (c[4].parse().ok()?, c[5].parse().ok()?);
+        if (k1 - k2).abs() < 1e-9 {
+            return None;
+        }
+        let legs = vec![
+            SpreadLeg {
+                strike: k1,
+                call_put: cp,
+                ratio: 1,
+            },
+            SpreadLeg {
+                strike: k2,
+                call_put: cp,
+                ratio: -1,
+            },
+        ];
+        Some((c, SpreadKind::Vertical, legs, 7, 8))
+    } else if let Some(c) = STRADDLE.captures(t) {
+        let cp1 = c[5].chars().next().unwrap().to_ascii_uppercase();
+        let cp2 = c[7].chars().next().unwrap().to_ascii_uppercase();
+        if cp1 == cp2 {
+            return None; // same-type pairs must use the vertical "K1/K2C" form
+        }
+        let (k1, k2): (f64, f64) = (c[4].parse().ok()?, c[6].parse().ok()?);
+        let kind = if (k1 - k2).abs() < 1e-9 {
+            SpreadKind::Straddle
+        } else {
+            SpreadKind::Strangle
+        };
+        let legs = vec![
+            SpreadLeg {
+                strike: k1,
+                call_put: cp1,
+                ratio: 1,
+            },
+            SpreadLeg {
+                strike: k2,
+                call_put: cp2,
+                ratio: 1,
+            },
+        ];
+        Some((c, kind, legs, 8, 9))
+    } else {
+        None
+    };
+
+    if let Some((c, kind, legs, exp_i, px_i)) = spread_caps {
+        let action = parse_action(&c[1])?;
+        let qty: u32 = match c.get(2) {
+            Some(q) => q.as_str().parse().ok()?,
+            None => 1, // default when qty missing
+        };
+        let price_raw = c[px_i].to_ascii_lowercase();
+
+        let (ot, lp) = if price_raw == "m" {
+            (OrderType::Market, None)
+        } else {
+            (OrderType::Limit, Some(price_raw.parse().ok()?))
+        };
+
+        return Some(TradeSignal::Spread(SpreadSignal {
+            action,
+            symbol: sanitize_symbol(&c[3]),
+            kind,
+            legs,
+            expiry: parse_expiry(&c[exp_i], now)?,
+            relative_expiry: parse_relative_expiry(&c[exp_i]),
+            quantity: qty,
+            order_type: ot,
+            limit_price: lp,
+            relative_qty: None,
+        }));
+    }
+
+    // Stocks: "BTO 100 AAPL @ m" or with a limit price
+    if let Some(c) = STK.captures(t) {
+        let action = parse_action(&c[1])?;
         let qty: u32 = c[2].parse().ok()?;
-        let symbol = c[3].to_uppercase();
+        let symbol = sanitize_symbol(&c[3]);
         let price_raw = c[4].to_ascii_lowercase();
 
         let (ot, lp) = if price_raw == "m" {
@@ -100,16 +497,14 @@ pub fn parse_signal(text: &str) -> Option<TradeSignal> {
             quantity: qty,
             order_type: ot,
             limit_price: lp,
+            relative_qty: None,
+            exit_levels: levels,
         }));
     }
 
-    if let Some(c) = re_stk_noqty.captures(t) {
-        let action = match &c[1].to_uppercase()[..] {
-            "BTO" => Action::BTO,
-            "STC" => Action::STC,
-            _ => return None,
-        };
-        let symbol = c[2].to_uppercase();
+    if let Some(c) = STK_NOQTY.captures(t) {
+        let action = parse_action(&c[1])?;
+        let symbol = sanitize_symbol(&c[2]);
         let price_raw = c[3].to_ascii_lowercase();
 
         let (ot, lp) = if price_raw == "m" {
@@ -124,19 +519,220 @@ pub fn parse_signal(text: &str) -> Option<TradeSignal> {
             quantity: 1, // default when qty missing
             order_type: ot,
             limit_price: lp,
+            relative_qty: None,
+            exit_levels: levels,
+        }));
+    }
+
+    // --- Relative-size stock exits: "STC half AAPL @ 190", "trim 25% AAPL @ m" ---
+    if let Some(c) = STK_REL.captures(t) {
+        let rel = parse_relative_qty(&c[2])?;
+        let rel_action = relative_exit_action(&c[1]);
+        let symbol = sanitize_symbol(&c[3]);
+        let price_raw = c[4].to_ascii_lowercase();
+
+        let (ot, lp) = if price_raw == "m" {
+            (OrderType::Market, None)
+        } else {
+            (OrderType::Limit, Some(price_raw.parse().ok()?))
+        };
+
+        return Some(TradeSignal::Stock(StockSignal {
+            action: rel_action,
+            symbol,
+            quantity: 0, // resolved against holdings at execution time
+            order_type: ot,
+            limit_price: lp,
+            relative_qty: Some(rel),
+            exit_levels: levels,
         }));
     }
 
     None
 }
 
+/// A bare exit posted as a reply to its entry, without repeating the instrument.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitReply {
+    /// STC/BTC when spelled out; "out" closes whichever side the entry opened.
+    pub action: Option<Action>,
+    /// Explicit count ("STC 2 @ 3.10"); otherwise `relative_qty` applies.
+    pub quantity: Option<u32>,
+    pub relative_qty: Option<RelativeQty>,
+    pub order_type: OrderType,
+    pub limit_price: Option<f64>,
+}
+
+impl ExitReply {
+    /// The complete closing signal for `entry`, or None when `entry` is not an opening
+    /// or the reply names the wrong side ("BTC" against a long). Spreads close by count
+    /// or in full.
+    pub fn against(&self, entry: &TradeSignal) -> Option<TradeSignal> {
+        let close = match entry.action() {
+            Action::BTO => Action::STC,
+            Action::STO => Action::BTC,
+            _ => return None,
+        };
+        if self.action.is_some_and(|a| a != close) {
+            return None;
+        }
+        let quantity = self.quantity.unwrap_or(0); // 0: resolved against holdings
+        Some(match entry {
+            TradeSignal::Stock(s) => TradeSignal::Stock(StockSignal {
+                action: close,
+                symbol: s.symbol.clone(),
+                quantity,
+                order_type: self.order_type,
+                limit_price: self.limit_price,
+                relative_qty: self.relative_qty,
+                exit_levels: ExitLevels::default(),
+            }),
+            TradeSignal::Option(o) => TradeSignal::Option(OptionSignal {
+                action: close,
+                key: o.key.clone(),
+                quantity,
+                order_type: self.order_type,
+                limit_price: self.limit_price,
+                relative_qty: self.relative_qty,
+                exit_levels: ExitLevels::default(),
+                relative_expiry: o.relative_expiry,
+            }),
+            TradeSignal::Spread(sp) => TradeSignal::Spread(SpreadSignal {
+                action: close,
+                quantity,
+                relative_qty: match (self.quantity, self.relative_qty) {
+                    (Some(_), _) => None,
+                    (None, Some(RelativeQty::All)) => Some(RelativeQty::All),
+                    _ => return None,
+                },
+                order_type: self.order_type,
+                limit_price: self.limit_price,
+                ..sp.clone()
+            }),
+        })
+    }
+}
+
+/// Parse a reply-only exit: "out", "all out", "STC", "STC @ 3.10", "STC 2 @ 3.10",
+/// "trim half @ m", "BTC all @ 0.50". Without a size the whole position closes (trims
+/// must give one); without a price the exit is a market order.
+pub fn parse_exit_reply(text: &str) -> Option<ExitReply> {
+    let c = EXIT_REPLY.captures(text.trim())?;
+    let action = match &c[1].to_ascii_uppercase()[..] {
+        "BTC" => Some(Action::BTC),
+        "STC" | "TRIM" | "SOLD" => Some(Action::STC),
+        _ => None,
+    };
+    let (quantity, relative_qty) = match c.get(2).map(|m| m.as_str()) {
+        None if c[1].eq_ignore_ascii_case("TRIM") => return None, // a trim needs a size
+        None => (None, Some(RelativeQty::All)),
+        Some(n) if n.bytes().all(|b| b.is_ascii_digit()) => (Some(n.parse().ok()?), None),
+        Some(r) => (None, Some(parse_relative_qty(r)?)),
+    };
+    if quantity == Some(0) {
+        return None;
+    }
+    let (order_type, limit_price) = match c.get(3).map(|m| m.as_str().to_ascii_lowercase()) {
+        None => (OrderType::Market, None),
+        Some(p) if p == "m" => (OrderType::Market, None),
+        Some(p) => (OrderType::Limit, Some(p.parse().ok()?)),
+    };
+    Some(ExitReply {
+        action,
+        quantity,
+        relative_qty,
+        order_type,
+        limit_price,
+    })
+}
+
+/// Strip trailing "SL x" / "PT y" (aliases: STOP, TP, TARGET; optional ':') in any order.
+/// Returns None when a level repeats or the stop is on the wrong side of the target
+/// (below it for longs, above it for shorts).
+fn split_exit_levels(t: &str, short: bool) -> Option<(&str, ExitLevels)> {
+    let mut core = t;
+    let mut levels = ExitLevels::default();
+    while let Some(c) = EXIT_LEVEL.captures(core) {
+        let px: f64 = c[2].parse().ok()?;
+        let slot = match &c[1].to_ascii_uppercase()[..] {
+            "SL" | "STOP" => &mut levels.stop,
+            _ => &mut levels.target,
+        };
+        if slot.replace(px).is_some() {
+            return None;
+        }
+        core = &core[..c.get(0).unwrap().start()];
+    }
+    if let (Some(sl), Some(pt)) = (levels.stop, levels.target) {
+        if (sl >= pt && !short) || (sl <= pt && short) {
+            return None;
+        }
+    }
+    Some((core, levels))
+}
+
+fn parse_action(raw: &str) -> Option<Action> {
+    match &raw.to_ascii_uppercase()[..] {
+        "BTO" => Some(Action::BTO),
+        "STC" => Some(Action::STC),
+        "STO" => Some(Action::STO),
+        "BTC" => Some(Action::BTC),
+        _ => None,
+    }
+}
+
+/// "BTC" closes a short; "STC" / "TRIM" close a long.
+fn relative_exit_action(raw: &str) -> Action {
+    if raw.eq_ignore_ascii_case("BTC") {
+        Action::BTC
+    } else {
+        Action::STC
+    }
+}
+
+/// "all" | "half" | "N/M" | "N%" -> relative exit size; fractions must lie in (0, 1].
+fn parse_relative_qty(raw: &str) -> Option<RelativeQty> {
+    let raw = raw.to_ascii_lowercase();
+    let frac = match raw.as_str() {
+        "all" => return Some(RelativeQty::All),
+        "half" => 0.5,
+        r if r.ends_with('%') => r.trim_end_matches('%').parse::<f64>().ok()? / 100.0,
+        r => {
+            let (n, d) = r.split_once('/')?;
+            let (n, d): (f64, f64) = (n.parse().ok()?, d.parse().ok()?);
+            if d == 0.0 {
+                return None;
+            }
+            n / d
+        }
+    };
+    if frac <= 0.0 || frac > 1.0 {
+        return None;
+    }
+    if (frac - 1.0).abs() < 1e-9 {
+        return Some(RelativeQty::All);
+    }
+    Some(RelativeQty::Fraction(frac))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::{Action, OrderType, TradeSignal};
+    use crate::types::{
+        Action, OrderType, RelativeExpiry, RelativeQty, SpreadKind, SpreadLeg, TradeSignal,
+    };
+    use chrono::NaiveDate;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 8, 1).unwrap().into()
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
 
     fn must_parse(s: &str) -> TradeSignal {
-        parse_signal(s).expect(&format!("should parse: {s}"))
+        parse_signal_on(s, now()).unwrap_or_else(|| panic!("should parse: {s}"))
     }
 
     fn must_parse_stock(s: &str) -> StockSignal {
@@ -159,14 +755,10 @@ mod tests {
     fn opt_limit_c_lower_mixed_case_action() {
         let o = must_parse_option("BTO 10 AAPL 150c 08/16 @ 2.50");
         assert_eq!(o.action, Action::BTO);
-        assert_eq!(o.symbol, "AAPL"); // NOTE: current parser keeps case as-is; this line
-                                      // will FAIL with current code because it returns "AAPL" only if you uppercased in parser.
-                                      // If your current code keeps "AAPL" as-is from input, adapt accordingly:
-                                      // If your current parser keeps original case, comment the line above and uncomment this:
-                                      // assert_eq!(o.symbol, "AAPL"); // input was AAPL
-        assert_eq!(o.strike, 150.0);
-        assert_eq!(o.call_put, 'C'); // parser uppercases C/P
-        assert_eq!(o.expiry_mmdd, "08/16");
+        assert_eq!(o.key.symbol, "AAPL");
+        assert_eq!(o.key.strike, 150.0);
+        assert_eq!(o.key.call_put, 'C'); // parser uppercases C/P
+        assert_eq!(o.key.expiry, ymd(2025, 8, 16));
         assert_eq!(o.quantity, 10);
         assert_eq!(o.order_type, OrderType::Limit);
         assert_eq!(o.limit_price, Some(2.50));
@@ -175,7 +767,7 @@ mod tests {
     #[test]
     fn opt_limit_p_lowercase_ok() {
         let o = must_parse_option("BTO 10 AAPL 150p 08/16 @ 2.50");
-        assert_eq!(o.call_put, 'P');
+        assert_eq!(o.key.call_put, 'P');
         assert_eq!(o.limit_price, Some(2.50));
     }
 
@@ -187,9 +779,10 @@ mod tests {
     }
 
     #[test]
-    fn opt_no_space_before_at_should_fail() {
-        // No space before '@' -> should NOT match with current regex (\s+@\s*)
-        assert!(parse_signal("BTO 10 AAPL 150c 08/16@2.50").is_none());
+    fn opt_no_space_before_at_ok() {
+        // Regex uses \s*@\s*, so spaces around '@' are optional
+        let o = must_parse_option("BTO 10 AAPL 150c 08/16@2.50");
+        assert_eq!(o.limit_price, Some(2.50));
     }
 
     #[test]
@@ -201,20 +794,20 @@ mod tests {
 
         let o2 = must_parse_option("stc 2 tsla 200p 09/20 @ M"); // case-insensitive overall
         assert_eq!(o2.order_type, OrderType::Market);
-        assert_eq!(o2.call_put, 'P');
+        assert_eq!(o2.key.call_put, 'P');
     }
 
     #[test]
-    fn opt_symbol_lowercase_kept_as_is_currently() {
-        // Current parser keeps symbol as captured (does not uppercase)
+    fn opt_symbol_uppercased_in_parser() {
         let o = must_parse_option("BTO 3 aapl 150c 08/16 @ 2.50");
-        assert_eq!(o.symbol, "aapl");
+        assert_eq!(o.key.symbol, "AAPL");
     }
 
     #[test]
-    fn opt_missing_quantity_should_fail_currently() {
-        // Current regex requires quantity (\d+). Missing qty should fail.
-        assert!(parse_signal("BTO aapl 150c 08/16 @ 2.50").is_none());
+    fn opt_missing_qty_defaults_to_one() {
+        let o = must_parse_option("BTO aapl 150c 08/16 @ 2.50");
+        assert_eq!(o.quantity, 1);
+        assert_eq!(o.key.symbol, "AAPL");
     }
 
     // ---------- Stocks: positive cases ----------
@@ -229,14 +822,14 @@ mod tests {
 
         let s2 = must_parse_stock("stc 50 nvda @ m");
         assert_eq!(s2.action, Action::STC);
-        assert_eq!(s2.symbol, "nvda"); // kept as-is
+        assert_eq!(s2.symbol, "NVDA"); // parser uppercases symbols
         assert_eq!(s2.order_type, OrderType::Market);
         assert_eq!(s2.limit_price, None);
     }
 
     #[test]
-    fn stk_space_before_at_required() {
-        assert!(parse_signal("BTO 10 AAPL@m").is_none()); // no space before '@'
+    fn stk_space_around_at_optional() {
+        assert!(parse_signal("BTO 10 AAPL@m").is_some()); // no space before '@' -> ok
         assert!(parse_signal("BTO 10 AAPL @m").is_some()); // space before, none after -> ok
         assert!(parse_signal("BTO 10 AAPL @ m").is_some()); // space both sides -> ok
     }
@@ -256,40 +849,401 @@ mod tests {
     }
 
     #[test]
-    fn symbol_length_and_dot_not_supported_now() {
-        // current pattern = [A-Z]{1,6} (case-insensitive), so >6 letters or dot symbols fail
+    fn cashtag_class_share_and_index_symbols() {
         assert!(parse_signal("BTO 1 ABCDEFG @ m").is_none()); // 7 letters
-        assert!(parse_signal("BTO 1 BRK.B @ m").is_none()); // dot not allowed
+        assert!(parse_signal("BTO 1 BRK-B @ m").is_none());
+        assert_eq!(
+            parse_signal("BTO 1 BRK.B @ m").unwrap().underlying(),
+            "BRK.B"
+        );
+        assert_eq!(
+            parse_signal("bto 5 bf.b @ 40").unwrap().underlying(),
+            "BF.B"
+        );
+        assert_eq!(
+            parse_signal("BTO 10 $AAPL @ 190").unwrap().underlying(),
+            "AAPL"
+        );
+
+        let o = must_parse_option("BTO 1 SPXW 5600C 09/20 @ 4.20");
+        assert_eq!(o.key.symbol, "SPXW");
+        assert_eq!(o.key.underlying(), "SPX");
+        let o = must_parse_option("BTO 2 $NDXP 20000P 09/20 @ 30");
+        assert_eq!(o.key.symbol, "NDXP");
     }
 
+    // ---------- Stop / target levels ----------
+
     #[test]
-    fn leading_trailing_spaces_ok() {
-        let s = must_parse_stock("   BTO 1 AAPL @ 123.0   ");
-        assert_eq!(s.quantity, 1);
-        assert_eq!(s.limit_price, Some(123.0));
+    fn opt_entry_with_stop_and_target() {
+        let o = must_parse_option("BTO 2 AAPL 150C 08/16 @ 2.50 SL 1.80 PT 3.50");
+        assert_eq!(o.quantity, 2);
+        assert_eq!(o.limit_price, Some(2.50));
+        assert_eq!(o.exit_levels.stop, Some(1.80));
+        assert_eq!(o.exit_levels.target, Some(3.50));
+
+        // any order, aliases, optional colon
+        let o = must_parse_option("bto aapl 150c 08/16 @ m tp: 4 stop 1.5");
+        assert_eq!(o.exit_levels.stop, Some(1.5));
+        assert_eq!(o.exit_levels.target, Some(4.0));
+    }
+
+    #[test]
+    fn stk_entry_with_single_level() {
+        let s = must_parse_stock("BTO 10 NVDA @ 120 SL 110");
+        assert_eq!(s.exit_levels.stop, Some(110.0));
+        assert_eq!(s.exit_levels.target, None);
+        assert!(must_parse_stock("BTO 10 NVDA @ 120").exit_levels.is_empty());
+    }
+
+    #[test]
+    fn bad_levels_should_fail() {
+        assert!(parse_signal("BTO 10 NVDA @ 120 SL 130 PT 125").is_none()); // stop above target
+        assert!(parse_signal("BTO 10 NVDA @ 120 SL 110 SL 100").is_none()); // repeated
+        assert!(parse_signal("BTO 10 NVDA @ 120 SL x").is_none());
+    }
+
+    // ---------- Spreads ----------
+
+    fn must_parse_spread(s: &str) -> SpreadSignal {
+        match must_parse(s) {
+            TradeSignal::Spread(sp) => sp,
+            _ => panic!("expected SpreadSignal"),
+        }
     }
 
-    // ---------- Future behavior wishes (documented as #[ignore]) ----------
+    #[test]
+    fn vertical_spread() {
+        let sp = must_parse_spread("BTO 1 SPY 450/455C 09/20 @ 1.20");
+        assert_eq!(sp.kind, SpreadKind::Vertical);
+        assert_eq!(sp.symbol, "SPY");
+        assert_eq!(sp.expiry, ymd(2025, 9, 20));
+        assert_eq!(sp.limit_price, Some(1.20));
+        assert_eq!(
+            sp.legs,
+            vec![
+                SpreadLeg {
+                    strike: 450.0,
+                    call_put: 'C',
+                    ratio: 1
+                },
+                SpreadLeg {
+                    strike: 455.0,
+                    call_put: 'C',
+                    ratio: -1
+                },
+            ]
+        );
+        assert!((sp.max_loss_per_spread(1.20) - 1.20).abs() < 1e-9);
+
+        // credit put vertical: long the lower strike
+        let sp = must_parse_spread("bto 2 spy 440/445p 09/20 @ 1.50");
+        assert_eq!(sp.quantity, 2);
+        assert!((sp.max_loss_per_spread(1.50) - 3.50).abs() < 1e-9);
+    }
 
     #[test]
-    #[ignore]
-    fn future_opt_missing_qty_defaults_to_one() {
-        // When/if you relax the regex to allow missing qty, change this to assert Some(...)
-        assert!(parse_signal("BTO aapl 150c 08/16 @ 2.50").is_none());
+    fn straddle_and_strangle() {
+        let sp = must_parse_spread("BTO SPY 450C/450P 09/20 @ m");
+        assert_eq!(sp.kind, SpreadKind::Straddle);
+        assert_eq!(sp.quantity, 1);
+        assert_eq!(sp.order_type, OrderType::Market);
+        assert!(sp.legs.iter().all(|l| l.ratio == 1));
+
+        let sp = must_parse_spread("STC 3 SPY 445P/455C 09/20 @ 4.10");
+        assert_eq!(sp.kind, SpreadKind::Strangle);
+        assert_eq!(sp.action, Action::STC);
     }
 
     #[test]
-    #[ignore]
-    fn future_symbol_uppercased_in_parser() {
-        // If you decide to uppercase in parser, adapt the assertions accordingly.
-        let o = must_parse_option("BTO 3 aapl 150c 08/16 @ 2.50");
-        assert_eq!(o.symbol, "AAPL");
+    fn bad_spreads_should_fail() {
+        assert!(parse_signal("BTO 1 SPY 450/450C 09/20 @ 1").is_none()); // zero width
+        assert!(parse_signal("BTO 1 SPY 450C/455C 09/20 @ 1").is_none()); // use 450/455C
+    }
+
+    // ---------- Relative-size exits ----------
+
+    #[test]
+    fn opt_relative_exits() {
+        let o = must_parse_option("STC half AAPL 150C 08/16 @ 3.10");
+        assert_eq!(o.action, Action::STC);
+        assert_eq!(o.relative_qty, Some(RelativeQty::Fraction(0.5)));
+        assert_eq!(o.limit_price, Some(3.10));
+
+        let o = must_parse_option("trim 1/3 spy 450p 09/20 @ m");
+        assert_eq!(o.action, Action::STC);
+        assert_eq!(o.key.symbol, "SPY");
+        assert_eq!(o.relative_qty, Some(RelativeQty::Fraction(1.0 / 3.0)));
+        assert_eq!(o.order_type, OrderType::Market);
+
+        let o = must_parse_option("STC all AAPL 150C 08/16 @ m");
+        assert_eq!(o.relative_qty, Some(RelativeQty::All));
     }
 
     #[test]
-    #[ignore]
-    fn future_no_space_before_at_allowed() {
-        // If you relax to \s*@\s*, then this should pass.
-        assert!(parse_signal("BTO 10 AAPL 150c 08/16@2.50").is_some());
+    fn stk_relative_exits() {
+        let s = must_parse_stock("STC all NVDA @ m");
+        assert_eq!(s.relative_qty, Some(RelativeQty::All));
+        let s = must_parse_stock("Trim 25% NVDA @ 120");
+        assert_eq!(s.relative_qty, Some(RelativeQty::Fraction(0.25)));
+        assert_eq!(s.limit_price, Some(120.0));
+        // absolute quantities are unaffected
+        assert_eq!(must_parse_stock("STC 5 NVDA @ m").relative_qty, None);
+    }
+
+    #[test]
+    fn relative_exit_edge_cases() {
+        assert!(parse_signal("BTO half AAPL @ m").is_none()); // entries need a count
+        assert!(parse_signal("STC 3/2 AAPL @ m").is_none()); // more than the position
+        assert!(parse_signal("STC 1/0 AAPL @ m").is_none());
+        assert!(parse_signal("trim AAPL @ m").is_none()); // size is required for trims
+        assert_eq!(
+            must_parse_stock("STC 2/2 AAPL @ m").relative_qty,
+            Some(RelativeQty::All)
+        );
+        assert_eq!(RelativeQty::Fraction(0.5).resolve(3.0), 1);
+        assert_eq!(RelativeQty::Fraction(1.0 / 3.0).resolve(1.0), 1);
+        assert_eq!(RelativeQty::All.resolve(7.5), 7);
+        assert_eq!(RelativeQty::Fraction(0.5).resolve(0.0), 0);
+    }
+
+    #[test]
+    fn short_side_actions() {
+        let o = must_parse_option("sto 2 SPY 440p 09/20 @ 1.50 SL 3 PT 0.5");
+        assert_eq!(o.action, Action::STO);
+        assert_eq!(o.quantity, 2);
+        assert_eq!(o.exit_levels.stop, Some(3.0));
+        assert_eq!(o.exit_levels.target, Some(0.5));
+        // a short's stop sits above its target
+        assert!(parse_signal("STO 2 SPY 440P 09/20 @ 1.50 SL 0.5 PT 3").is_none());
+
+        assert_eq!(must_parse_stock("BTC 100 TSLA @ m").action, Action::BTC);
+        let o = must_parse_option("BTC all SPY 440P 09/20 @ 0.40");
+        assert_eq!(o.action, Action::BTC);
+        assert_eq!(o.relative_qty, Some(RelativeQty::All));
+        assert!(parse_signal("STO 1 SPY 450/455C 09/20 @ 1.20").is_none());
+    }
+
+    #[test]
+    fn expiry_year_inference_and_explicit_years() {
+        // past MM/DD rolls to next year; expiring today stays
+        let o = |s: &str| match parse_signal_on(s, ymd(2025, 12, 1).into()).unwrap() {
+            TradeSignal::Option(o) => o.key.expiry,
+            _ => panic!("expected OptionSignal"),
+        };
+        assert_eq!(o("BTO 1 SPY 450C 01/16 @ 2"), ymd(2026, 1, 16));
+        assert_eq!(o("BTO 1 SPY 450C 12/01 @ 2"), ymd(2025, 12, 1));
+        assert_eq!(o("BTO 1 SPY 450C 01/15/27 @ 2"), ymd(2027, 1, 15));
+        assert_eq!(o("BTO 1 SPY 450C 01/15/2027 @ 2"), ymd(2027, 1, 15));
+        assert_eq!(o("BTO 1 SPY 450C 2027-01-15 @ 2"), ymd(2027, 1, 15));
+        assert!(parse_signal("BTO 1 SPY 450C 13/40 @ 2").is_none());
+    }
+
+    #[test]
+    fn relative_expiries_resolve_on_the_calendar() {
+        // week of Good Friday 2025: Friday expiries move to Thursday 04/17
+        let on = |s: &str, day: NaiveDate| match parse_signal_on(s, day.into()).unwrap() {
+            TradeSignal::Option(o) => (o.key.expiry, o.relative_expiry),
+            TradeSignal::Spread(sp) => (sp.expiry, sp.relative_expiry),
+            _ => panic!("expected an option or spread"),
+        };
+        let mon = ymd(2025, 4, 14);
+        assert_eq!(
+            on("BTO SPY 450C 0DTE @ 1.10", mon),
+            (mon, Some(RelativeExpiry::Dte(0)))
+        );
+        assert_eq!(
+            on("BTO 1 SPY 450C exp Fri @ 2", mon),
+            (ymd(2025, 4, 17), Some(RelativeExpiry::Weekly))
+        );
+        assert_eq!(on("BTO 1 SPY 450C weekly @ 2", mon).0, ymd(2025, 4, 17));
+        assert_eq!(
+            on("BTO 1 SPY 450C next Friday @ 2", mon),
+            (ymd(2025, 4, 25), Some(RelativeExpiry::NextWeek))
+        );
+        assert_eq!(on("BTO 1 SPY 450C monthly @ 2", mon).0, ymd(2025, 4, 17));
+        // after this month's expiry "monthly" is next month's
+        assert_eq!(
+            on("BTO 1 SPY 450C monthly @ 2", ymd(2025, 4, 19)).0,
+            ymd(2025, 5, 16)
+        );
+        // Labor Day is skipped
+        assert_eq!(
+            on("BTO 1 QQQ 500P 1dte @ 2", ymd(2025, 8, 29)).0,
+            ymd(2025, 9, 2)
+        );
+        assert_eq!(
+            on("BTO 1 SPY 450/455C 0 DTE @ 1.20", mon),
+            (mon, Some(RelativeExpiry::Dte(0)))
+        );
+        assert_eq!(
+            must_parse_option("BTO 1 SPY 450C 09/20 @ 2").relative_expiry,
+            None
+        );
+    }
+
+    #[test]
+    fn relative_expiries_after_the_close_use_the_next_session() {
+        let at = |s: &str, h: u32, m: u32| {
+            let now = ymd(2025, 8, 1).and_hms_opt(h, m, 0).unwrap();
+            match parse_signal_on(s, now).unwrap() {
+                TradeSignal::Option(o) => o.key.expiry,
+                _ => panic!("expected OptionSignal"),
+            }
+        };
+        // Friday 2025-08-01: before the close 0DTE is today, after it Monday's
+        assert_eq!(at("BTO SPY 450C 0DTE @ 1.10", 15, 59), ymd(2025, 8, 1));
+        assert_eq!(at("BTO SPY 450C 0DTE @ 1.10", 16, 30), ymd(2025, 8, 4));
+        assert_eq!(at("BTO SPY 450C 1dte @ 1.10", 16, 30), ymd(2025, 8, 5));
+        assert_eq!(at("BTO SPY 450C weekly @ 1.10", 16, 30), ymd(2025, 8, 8));
+        // a dated expiry is unaffected by the session
+        assert_eq!(at("BTO SPY 450C 08/01 @ 1.10", 16, 30), ymd(2025, 8, 1));
+    }
+
+    #[test]
+    fn multi_line_messages_with_commentary() {
+        let msg = "🚨 **BTO 2 SPY 450C 09/20 @ 2.50** lotto size\n\
+                   also STC half AAPL @ 190. taking profits 🎉\n\
+                   > BTO 1 TSLA @ m\n\
+                   gl everyone";
+        let sigs = parse_signals_on(msg, now());
+        assert_eq!(sigs.len(), 2);
+        assert_eq!(
+            sigs[0].label(),
+            must_parse("BTO 2 SPY 450C 09/20 @ 2.50").label()
+        );
+        let TradeSignal::Stock(s) = &sigs[1] else {
+            panic!("expected StockSignal");
+        };
+        assert_eq!(s.relative_qty, Some(RelativeQty::Fraction(0.5)));
+        assert_eq!(s.limit_price, Some(190.0));
+
+        let two = parse_signals_on("STC AAPL @ 190 and BTO MSFT @ 400 SL 390", now());
+        assert_eq!(two.len(), 2);
+        assert!(parse_signals_on("good morning, no trades today", now()).is_empty());
+    }
+
+    #[test]
+    fn ambiguous_messages_are_refused() {
+        for msg in [
+            "watching SPY here, BTO SPY 455C 09/20 @ 1.00 on a break",
+            "maybe BTO AAPL @ 190",
+            "BTO AAPL @ 190?",
+            "BTO 2 SPY 450C 09/20 @ 2.50 3.00",
+            "STC AAPL @ 190\nBTO 2 SPY 450C 09/20 @ 2.50 SL 2.0x",
+        ] {
+            assert!(parse_signals_on(msg, now()).is_empty(), "{msg}");
+        }
+    }
+
+    #[test]
+    fn hedges_count_only_before_the_action_on_the_call_line() {
+        let sigs = parse_signals_on("BTO 2 SPY 450C 09/20 @ 1.20\nnot financial advice", now());
+        assert_eq!(sigs.len(), 1);
+        let sigs = parse_signals_on(
+            "If SPY holds 450 this could run\nBTO 2 SPY 450C 09/20 @ 1.20 don't chase",
+            now(),
+        );
+        assert_eq!(sigs.len(), 1);
+        let ParseOutcome::NearMiss(miss) =
+            diagnose_on("nice day\nthinking BTO 2 SPY 450C 09/20 @ 1.20", now())
+        else {
+            panic!("expected a hedged near miss");
+        };
+        assert_eq!(miss.issue, ParseIssue::Hedged("thinking".into()));
+        assert_eq!(miss.line, "thinking BTO 2 SPY 450C 09/20 @ 1.20");
+    }
+
+    #[test]
+    fn near_misses_name_the_failing_field() {
+        let issue = |msg: &str| match diagnose_on(msg, now()) {
+            ParseOutcome::NearMiss(m) => m.issue,
+            other => panic!("expected near miss for {msg}: {other:?}"),
+        };
+        assert_eq!(
+            issue("BTO 2 SPY 450C 09/20 @ 2.5x"),
+            ParseIssue::BadPrice("2.5x".into())
+        );
+        assert_eq!(issue("BTO 2 SPY 450C 09/20"), ParseIssue::MissingPrice);
+        assert_eq!(
+            issue("BTO 2 SPY 450C 13/45 @ 1"),
+            ParseIssue::UnknownExpiry("13/45".into())
+        );
+        assert_eq!(issue("BTO 2 SPY 450 C @ 1"), ParseIssue::MissingExpiry);
+        assert_eq!(
+            issue("BTO 2 SPY 450C next Sunday @ 1"),
+            ParseIssue::UnknownExpiry("next Sunday".into())
+        );
+        assert_eq!(
+            issue("BTO 10 GOOGLEX @ 5"),
+            ParseIssue::SymbolTooLong("GOOGLEX".into())
+        );
+        assert_eq!(
+            issue("BTO 1 BRK-B @ 450"),
+            ParseIssue::BadSymbol("BRK-B".into())
+        );
+        assert_eq!(issue("BTO AAPL @ 190 SL 200 PT 195"), ParseIssue::BadLevels);
+        assert_eq!(
+            issue("maybe BTO AAPL @ 190"),
+            ParseIssue::Hedged("maybe".into())
+        );
+        assert_eq!(issue("BTO AAPL @ 190 5"), ParseIssue::RunsOn("5".into()));
+        assert_eq!(issue("STC AAPL @ 190?"), ParseIssue::Question);
+
+        assert!(matches!(
+            diagnose_on("will STC later, nice run", now()),
+            ParseOutcome::NotASignal
+        ));
+        let ParseOutcome::Parsed(sigs) = diagnose_on("BTO AAPL @ 190", now()) else {
+            panic!("expected a signal");
+        };
+        assert_eq!(sigs.len(), 1);
+    }
+
+    #[test]
+    fn exit_replies_close_the_entry() {
+        let r = parse_exit_reply("STC @ 3.10").unwrap();
+        assert_eq!(r.action, Some(Action::STC));
+        assert_eq!(r.relative_qty, Some(RelativeQty::All));
+        assert_eq!(r.limit_price, Some(3.10));
+        let r = parse_exit_reply("out").unwrap();
+        assert_eq!((r.action, r.order_type), (None, OrderType::Market));
+        assert_eq!(parse_exit_reply("STC 2 @ m").unwrap().quantity, Some(2));
+        assert_eq!(
+            parse_exit_reply("trim 1/3 @ 3").unwrap().relative_qty,
+            Some(RelativeQty::Fraction(1.0 / 3.0))
+        );
+        for bad in ["trim @ 3", "STC 0", "out of here", "STC SPY"] {
+            assert!(parse_exit_reply(bad).is_none(), "{bad}");
+        }
+
+        let long = must_parse("BTO 3 SPY 450C 09/20 @ 2.50 SL 2");
+        let TradeSignal::Option(o) = parse_exit_reply("out").unwrap().against(&long).unwrap()
+        else {
+            panic!("expected OptionSignal");
+        };
+        assert_eq!((o.action, o.key.strike), (Action::STC, 450.0));
+        assert_eq!(o.exit_levels.stop, None);
+        let short = must_parse("STO 100 AAPL @ 190");
+        let TradeSignal::Stock(s) = parse_exit_reply("out half @ 180")
+            .unwrap()
+            .against(&short)
+            .unwrap()
+        else {
+            panic!("expected StockSignal");
+        };
+        assert_eq!(s.action, Action::BTC);
+        assert_eq!(s.relative_qty, Some(RelativeQty::Fraction(0.5)));
+        // wrong side, or replying to an exit
+        let stc = parse_exit_reply("STC").unwrap();
+        assert!(stc.against(&short).is_none());
+        assert!(stc.against(&must_parse("STC 1 AAPL @ 190")).is_none());
+    }
+
+    #[test]
+    fn leading_trailing_spaces_ok() {
+        let s = must_parse_stock("   BTO 1 AAPL @ 123.0   ");
+        assert_eq!(s.quantity, 1);
+        assert_eq!(s.limit_price, Some(123.0));
     }
 }
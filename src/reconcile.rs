@@ -0,0 +1,306 @@
+//! Reconciliation of local holdings against the broker snapshot.
+//!
+//! Positions are compared per instrument. Local spreads are expanded into their legs,
+//! since the broker reports each leg as a single option; leg costs are not compared.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::instrument::{InstrumentId, OptionKey};
+use crate::state::BotState;
+use crate::types::Holding;
+
+/// What to do with holdings that differ from the broker.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconcilePolicy {
+    /// Adopt the broker snapshot (quantities and costs).
+    #[default]
+    TrustBroker,
+    /// Keep local holdings; differences are only logged.
+    TrustLocal,
+    /// Keep local holdings and reject signals on instruments that differ until a later
+    /// sync finds them back in agreement.
+    Freeze,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffKind {
+    /// Both sides hold the instrument in different sizes.
+    QuantityDrift { local: f64, broker: f64 },
+    /// Same size, average cost apart by more than the tolerance.
+    CostDrift { local: f64, broker: f64 },
+    /// Held at the broker only (e.g. a manual trade).
+    Unknown { broker: f64 },
+    /// Held locally only (e.g. closed by hand, or a fill the bot missed).
+    Missing { local: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub instrument: InstrumentId,
+    pub kind: DiffKind,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            DiffKind::QuantityDrift { local, broker } => write!(
+                f,
+                "{}: quantity drift (local {}, broker {})",
+                self.instrument, local, broker
+            ),
+            DiffKind::CostDrift { local, broker } => write!(
+                f,
+                "{}: cost drift (local {:.4}, broker {:.4})",
+                self.instrument, local, broker
+            ),
+            DiffKind::Unknown { broker } => {
+                write!(
+                    f,
+                    "{}: unknown position {} at broker",
+                    self.instrument, broker
+                )
+            }
+            DiffKind::Missing { local } => {
+                write!(
+                    f,
+                    "{}: local position {} missing at broker",
+                    self.instrument, local
+                )
+            }
+        }
+    }
+}
+
+/// Net quantity and average cost per instrument; cost is None when spread legs share it.
+fn positions(holdings: &[Holding]) -> HashMap<InstrumentId, (f64, Option<f64>)> {
+    let mut out: HashMap<InstrumentId, (f64, Option<f64>)> = HashMap::new();
+    for h in holdings {
+        match h {
+            Holding::Spread {
+                symbol,
+                legs,
+                expiry,
+                quantity,
+                ..
+            } => {
+                for l in legs {
+                    let key = OptionKey::new(symbol, l.strike, l.call_put, *expiry);
+                    let e = out.entry(InstrumentId::Option(key)).or_insert((0.0, None));
+                    e.0 += (l.ratio * *quantity as i32) as f64;
+                    e.1 = None;
+                }
+            }
+            Holding::Stock { avg_cost, .. } | Holding::Option { avg_cost, .. } => {
+                let Some(id) = h.instrument() else { continue };
+                match out.get_mut(&id) {
+                    // a second row for the same instrument, or one shared with a spread
+                    Some(e) => {
+                        e.0 += h.quantity();
+                        e.1 = None;
+                    }
+                    None => {
+                        out.insert(id, (h.quantity(), Some(*avg_cost)));
+                    }
+                }
+            }
+        }
+    }
+    out.retain(|_, (q, _)| q.abs() > 1e-9);
+    out
+}
+
+/// Differences between local and broker holdings, sorted by instrument.
+/// `cost_tolerance_pct` is relative to the broker cost (0.01 = 1%).
+pub fn diff(local: &[Holding], broker: &[Holding], cost_tolerance_pct: f64) -> Vec<Discrepancy> {
+    let local = positions(local);
+    let broker = positions(broker);
+    let mut out = Vec::new();
+    for (id, (lq, lc)) in &local {
+        let kind = match broker.get(id) {
+            None => Some(DiffKind::Missing { local: *lq }),
+            Some((bq, _)) if (lq - bq).abs() > 1e-6 => Some(DiffKind::QuantityDrift {
+                local: *lq,
+                broker: *bq,
+            }),
+            Some((_, Some(bc))) => match lc {
+                Some(lc) if (lc - bc).abs() > bc.abs() * cost_tolerance_pct + 1e-9 => {
+                    Some(DiffKind::CostDrift {
+                        local: *lc,
+                        broker: *bc,
+                    })
+                }
+                _ => None,
+            },
+            Some(_) => None,
+        };
+        if let Some(kind) = kind {
+            out.push(Discrepancy {
+                instrument: id.clone(),
+                kind,
+            });
+        }
+    }
+    for (id, (bq, _)) in &broker {
+        if !local.contains_key(id) {
+            out.push(Discrepancy {
+                instrument: id.clone(),
+                kind: DiffKind::Unknown { broker: *bq },
+            });
+        }
+    }
+    out.sort_by_key(|d| d.instrument.to_string());
+    out
+}
+
+/// Log every discrepancy and update `st` according to `policy`.
+pub fn apply(
+    st: &mut BotState,
+    broker: Vec<Holding>,
+    diffs: &[Discrepancy],
+    policy: ReconcilePolicy,
+) {
+    for d in diffs {
+        warn!("reconcile: {}", d);
+    }
+    match policy {
+        ReconcilePolicy::TrustBroker => {
+            st.set_holdings(broker);
+            st.frozen.clear();
+        }
+        ReconcilePolicy::TrustLocal => st.frozen.clear(),
+        ReconcilePolicy::Freeze => {
+            let frozen: Vec<InstrumentId> = diffs.iter().map(|d| d.instrument.clone()).collect();
+            for id in &frozen {
+                if !st.frozen.contains(id) {
+                    warn!("reconcile: trading frozen on {}", id);
+                }
+            }
+            for id in &st.frozen {
+                if !frozen.contains(id) {
+                    info!("reconcile: {} back in agreement, trading resumed", id);
+                }
+            }
+            st.frozen = frozen;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SpreadKind, SpreadLeg};
+    use chrono::NaiveDate;
+
+    fn stock(symbol: &str, quantity: f64, avg_cost: f64) -> Holding {
+        Holding::Stock {
+            symbol: symbol.into(),
+            quantity,
+            avg_cost,
+        }
+    }
+
+    fn spy(strike: f64, quantity: i32) -> Holding {
+        Holding::Option {
+            key: OptionKey::new(
+                "SPY",
+                strike,
+                'C',
+                NaiveDate::from_ymd_opt(2025, 9, 20).unwrap(),
+            ),
+            quantity,
+            avg_cost: 1.0,
+        }
+    }
+
+    #[test]
+    fn classifies_each_kind_of_difference() {
+        let local = vec![
+            stock("AAPL", 10.0, 100.0),
+            stock("MSFT", 5.0, 300.0),
+            stock("TSLA", 2.0, 200.0),
+            stock("NVDA", 1.0, 100.0),
+        ];
+        let broker = vec![
+            stock("AAPL", 8.0, 100.0),
+            stock("MSFT", 5.0, 330.0),
+            stock("NVDA", 1.0, 100.5),
+            stock("AMD", 3.0, 150.0),
+        ];
+        let kinds: Vec<(String, DiffKind)> = diff(&local, &broker, 0.01)
+            .into_iter()
+            .map(|d| (d.instrument.to_string(), d.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    "AAPL".into(),
+                    DiffKind::QuantityDrift {
+                        local: 10.0,
+                        broker: 8.0
+                    }
+                ),
+                ("AMD".into(), DiffKind::Unknown { broker: 3.0 }),
+                (
+                    "MSFT".into(),
+                    DiffKind::CostDrift {
+                        local: 300.0,
+                        broker: 330.0
+                    }
+                ),
+                ("TSLA".into(), DiffKind::Missing { local: 2.0 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn spreads_compare_leg_by_leg() {
+        let spread = Holding::Spread {
+            symbol: "SPY".into(),
+            kind: SpreadKind::Vertical,
+            legs: vec![
+                SpreadLeg {
+                    strike: 450.0,
+                    call_put: 'C',
+                    ratio: 1,
+                },
+                SpreadLeg {
+                    strike: 455.0,
+                    call_put: 'C',
+                    ratio: -1,
+                },
+            ],
+            expiry: NaiveDate::from_ymd_opt(2025, 9, 20).unwrap(),
+            quantity: 2,
+            avg_cost: 1.2,
+        };
+        let local = vec![spread];
+        assert!(diff(&local, &[spy(450.0, 2), spy(455.0, -2)], 0.01).is_empty());
+        let d = diff(&local, &[spy(450.0, 2)], 0.01);
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].kind, DiffKind::Missing { local: -2.0 });
+    }
+
+    #[test]
+    fn freeze_keeps_local_and_unfreezes_when_resolved() {
+        let mut st = BotState {
+            holdings: vec![stock("AAPL", 10.0, 100.0)],
+            ..Default::default()
+        };
+        let broker = vec![stock("AAPL", 8.0, 100.0)];
+        let d = diff(&st.holdings, &broker, 0.01);
+        apply(&mut st, broker, &d, ReconcilePolicy::Freeze);
+        assert_eq!(st.position_qty_stock("AAPL"), 10.0);
+        assert_eq!(st.frozen, vec![InstrumentId::stock("AAPL")]);
+
+        let broker = vec![stock("AAPL", 10.0, 100.0)];
+        let d = diff(&st.holdings, &broker, 0.01);
+        apply(&mut st, broker, &d, ReconcilePolicy::Freeze);
+        assert!(st.frozen.is_empty());
+    }
+}
@@ -1,24 +1,113 @@
 //! Risk checks before order placement (V2).
 
+use crate::calendar;
+use crate::config::{AuthorCfg, RiskCfg};
+use crate::instrument::InstrumentId;
 use crate::state::BotState;
 use crate::types::{Action, TradeSignal};
 use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::cmp::Ordering;
+use tracing::error;
 
 pub struct RiskEngine {
     max_position_value: f64,
+    max_short_exposure: f64,
+    max_daily_loss: f64,
+    max_total_exposure: f64,
+    max_underlying_exposure: f64,
+    max_open_positions: usize,
 }
 
 impl RiskEngine {
-    pub fn new(max_value: f64) -> Self {
+    pub fn from_cfg(cfg: &RiskCfg) -> Self {
         Self {
-            max_position_value: max_value,
+            max_position_value: cfg.max_position_value,
+            max_short_exposure: cfg.max_short_exposure,
+            max_daily_loss: cfg.max_daily_loss,
+            max_total_exposure: cfg.max_total_exposure,
+            max_underlying_exposure: cfg.max_underlying_exposure,
+            max_open_positions: cfg.max_open_positions,
         }
     }
 
-    pub fn pre_check(&self, signal: &TradeSignal, est_price: f64, state: &BotState) -> Result<()> {
+    pub fn daily_loss_enabled(&self) -> bool {
+        self.max_daily_loss > 0.0
+    }
+
+    /// Halt entries for `today` once P/L realized today plus `unrealized` (open positions
+    /// at current marks) is a loss of at least `max_daily_loss`. Returns true when the
+    /// halt is newly set.
+    pub fn update_daily_loss(
+        &self,
+        state: &mut BotState,
+        today: NaiveDate,
+        unrealized: f64,
+    ) -> bool {
+        if !self.daily_loss_enabled() || state.halted_on == Some(today) {
+            return false;
+        }
+        let realized: f64 = state
+            .daily_pl
+            .iter()
+            .filter(|e| e.date == today)
+            .map(|e| e.realized_pl)
+            .sum();
+        let day = realized + unrealized;
+        if day > -self.max_daily_loss {
+            return false;
+        }
+        error!(
+            "Daily loss ${:.2} (realized {:.2}, unrealized {:.2}) hit max_daily_loss ${:.2}: entries halted for {}",
+            -day, realized, unrealized, self.max_daily_loss, today
+        );
+        state.halted_on = Some(today);
+        true
+    }
+
+    /// `author` is the configured author the signal came from; their own notional cap
+    /// and daily loss budget apply on top of the global limits.
+    pub fn pre_check(
+        &self,
+        signal: &TradeSignal,
+        est_price: f64,
+        state: &BotState,
+        today: NaiveDate,
+        author: Option<&AuthorCfg>,
+    ) -> Result<()> {
+        if signal.action().is_opening() && state.halted_on == Some(today) {
+            anyhow::bail!("Daily loss limit hit: new entries halted for {}", today);
+        }
+        if let Some(a) = author {
+            let budget = a.max_daily_loss.unwrap_or(0.0);
+            let realized = state.author_realized_pl(a.key(), today);
+            if signal.action().is_opening() && budget > 0.0 && realized <= -budget {
+                anyhow::bail!(
+                    "{} lost ${:.2} today, max_daily_loss ${:.2}: entries refused",
+                    a.name,
+                    -realized,
+                    budget
+                );
+            }
+        }
+        let ids = match signal {
+            TradeSignal::Spread(sp) => sp
+                .legs
+                .iter()
+                .map(|l| InstrumentId::Option(sp.leg_key(l)))
+                .collect(),
+            _ => signal.instrument().into_iter().collect::<Vec<_>>(),
+        };
+        if let Some(id) = ids.iter().find(|id| state.frozen.contains(id)) {
+            anyhow::bail!("Trading on {} frozen: holdings differ from broker", id);
+        }
         let notional = match signal {
             TradeSignal::Stock(s) => est_price * (s.quantity as f64),
-            TradeSignal::Option(o) => est_price * (o.quantity as f64) * 100.0,
+            TradeSignal::Option(o) => est_price * (o.quantity as f64) * o.key.multiplier(),
+            // Spreads are capped on worst-case loss, not gross leg premium
+            TradeSignal::Spread(sp) => {
+                sp.max_loss_per_spread(est_price) * (sp.quantity as f64) * sp.multiplier()
+            }
         };
         if notional > self.max_position_value {
             anyhow::bail!(
@@ -27,29 +116,86 @@ impl RiskEngine {
                 self.max_position_value
             );
         }
+        if let Some((name, cap)) = author.and_then(|a| Some((&a.name, a.max_position_value?))) {
+            if notional > cap {
+                anyhow::bail!(
+                    "Order notional ${:.2} exceeds {}'s max_position_value ${:.2}",
+                    notional,
+                    name,
+                    cap
+                );
+            }
+        }
+        if signal.action().is_opening() {
+            self.check_portfolio(signal, notional, state)?;
+        }
         match signal {
-            TradeSignal::Stock(s) if s.action == Action::STC => {
+            TradeSignal::Stock(s) => {
                 let have = state.position_qty_stock(&s.symbol);
-                if have + 1e-9 < s.quantity as f64 {
-                    anyhow::bail!(
-                        "Cannot STC {} shares of {}: holding {:.4}",
-                        s.quantity,
-                        s.symbol,
-                        have
-                    );
+                let qty = s.quantity as f64;
+                match s.action {
+                    Action::BTO if have < 0.0 => {
+                        anyhow::bail!("Cannot BTO {}: short {:.4}, BTC first", s.symbol, -have)
+                    }
+                    Action::STO if have > 0.0 => {
+                        anyhow::bail!("Cannot STO {}: long {:.4}, STC first", s.symbol, have)
+                    }
+                    Action::STC if have + 1e-9 < qty => {
+                        anyhow::bail!(
+                            "Cannot STC {} shares of {}: holding {:.4}",
+                            s.quantity,
+                            s.symbol,
+                            have
+                        );
+                    }
+                    Action::BTC if -have + 1e-9 < qty => {
+                        anyhow::bail!(
+                            "Cannot BTC {} shares of {}: short {:.4}",
+                            s.quantity,
+                            s.symbol,
+                            (-have).max(0.0)
+                        );
+                    }
+                    Action::STO => self.check_short_exposure(est_price * qty, state)?,
+                    _ => {}
+                }
+            }
+            TradeSignal::Option(o) => {
+                let have = state.position_qty_option(&o.key);
+                let qty = o.quantity as i32;
+                let label = &o.key;
+                match o.action {
+                    Action::BTO if have < 0 => {
+                        anyhow::bail!("Cannot BTO {}: short {}, BTC first", label, -have)
+                    }
+                    Action::STO if have > 0 => {
+                        anyhow::bail!("Cannot STO {}: long {}, STC first", label, have)
+                    }
+                    Action::STC if have < qty => {
+                        anyhow::bail!("Cannot STC {}x {}: holding {}", o.quantity, label, have);
+                    }
+                    Action::BTC if -have < qty => {
+                        anyhow::bail!(
+                            "Cannot BTC {}x {}: short {}",
+                            o.quantity,
+                            label,
+                            (-have).max(0)
+                        );
+                    }
+                    Action::STO => self.check_short_exposure(
+                        o.key.strike * (o.quantity as f64) * o.key.multiplier(),
+                        state,
+                    )?,
+                    _ => {}
                 }
             }
-            TradeSignal::Option(o) if o.action == Action::STC => {
-                let have =
-                    state.position_qty_option(&o.symbol, o.strike, o.call_put, &o.expiry_mmdd);
-                if have < o.quantity {
+            TradeSignal::Spread(sp) if sp.action == Action::STC => {
+                let have = state.position_qty_spread(sp);
+                if have < sp.quantity {
                     anyhow::bail!(
-                        "Cannot STC {}x {} {}{} {}: holding {}",
-                        o.quantity,
-                        o.symbol,
-                        o.strike,
-                        o.call_put,
-                        o.expiry_mmdd,
+                        "Cannot STC {}x {}: holding {}",
+                        sp.quantity,
+                        sp.label(),
                         have
                     );
                 }
@@ -58,4 +204,192 @@ impl RiskEngine {
         }
         Ok(())
     }
+
+    /// Aggregate caps for an entry adding `added` USD of exposure.
+    fn check_portfolio(&self, signal: &TradeSignal, added: f64, state: &BotState) -> Result<()> {
+        if self.max_total_exposure > 0.0 {
+            let total = state.gross_exposure() + added;
+            if total > self.max_total_exposure {
+                anyhow::bail!(
+                    "Total exposure ${:.2} would exceed max_total_exposure ${:.2}",
+                    total,
+                    self.max_total_exposure
+                );
+            }
+        }
+        if self.max_underlying_exposure > 0.0 {
+            let symbol = signal.underlying();
+            let total = state.underlying_exposure(symbol) + added;
+            if total > self.max_underlying_exposure {
+                anyhow::bail!(
+                    "Exposure to {} ${:.2} would exceed max_underlying_exposure ${:.2}",
+                    symbol,
+                    total,
+                    self.max_underlying_exposure
+                );
+            }
+        }
+        if self.max_open_positions > 0 {
+            let held = match signal {
+                TradeSignal::Stock(s) => state.position_qty_stock(&s.symbol).abs() > 1e-9,
+                TradeSignal::Option(o) => state.position_qty_option(&o.key) != 0,
+                TradeSignal::Spread(sp) => state.position_qty_spread(sp) > 0,
+            };
+            let open = state.open_positions();
+            if !held && open >= self.max_open_positions {
+                anyhow::bail!(
+                    "{} open positions: max_open_positions {} reached",
+                    open,
+                    self.max_open_positions
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Options cannot trade once the regular session of their expiry day has closed.
+    /// `now` is exchange-local time.
+    pub fn check_session(&self, signal: &TradeSignal, now: NaiveDateTime) -> Result<()> {
+        let expiry = match signal {
+            TradeSignal::Stock(_) => return Ok(()),
+            TradeSignal::Option(o) => o.key.expiry,
+            TradeSignal::Spread(sp) => sp.expiry,
+        };
+        let closed = match expiry.cmp(&now.date()) {
+            Ordering::Less => true,
+            Ordering::Equal => calendar::regular_hours(expiry).is_none_or(|(_, c)| now.time() >= c),
+            Ordering::Greater => false,
+        };
+        if closed {
+            anyhow::bail!(
+                "{} expired {}: trading in it has closed",
+                signal.label(),
+                expiry
+            );
+        }
+        Ok(())
+    }
+
+    fn check_short_exposure(&self, added: f64, state: &BotState) -> Result<()> {
+        if self.max_short_exposure <= 0.0 {
+            anyhow::bail!("Short selling disabled (risk.max_short_exposure is 0)");
+        }
+        let total = state.short_exposure() + added;
+        if total > self.max_short_exposure {
+            anyhow::bail!(
+                "Short exposure ${:.2} would exceed max_short_exposure ${:.2}",
+                total,
+                self.max_short_exposure
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::OptionKey;
+    use crate::types::{Holding, OrderType, StockSignal};
+
+    fn engine() -> RiskEngine {
+        RiskEngine::from_cfg(&RiskCfg {
+            max_position_value: 10_000.0,
+            max_short_exposure: 0.0,
+            max_daily_loss: 0.0,
+            max_total_exposure: 5_000.0,
+            max_underlying_exposure: 3_000.0,
+            max_open_positions: 2,
+        })
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 8, 1).unwrap()
+    }
+
+    fn stock(action: Action, symbol: &str, quantity: u32) -> TradeSignal {
+        TradeSignal::Stock(StockSignal {
+            action,
+            symbol: symbol.into(),
+            quantity,
+            order_type: OrderType::Market,
+            limit_price: None,
+            relative_qty: None,
+            exit_levels: Default::default(),
+        })
+    }
+
+    fn bto(symbol: &str, quantity: u32) -> TradeSignal {
+        stock(Action::BTO, symbol, quantity)
+    }
+
+    #[test]
+    fn caps_aggregate_exposure_per_underlying_and_count() {
+        let aapl_call = OptionKey::new("AAPL", 200.0, 'C', today());
+        let st = BotState {
+            holdings: vec![
+                Holding::Stock {
+                    symbol: "AAPL".into(),
+                    quantity: 10.0,
+                    avg_cost: 200.0,
+                },
+                Holding::Option {
+                    key: aapl_call,
+                    quantity: 2,
+                    avg_cost: 2.5,
+                },
+            ],
+            ..Default::default()
+        };
+        let risk = engine();
+        // AAPL already carries 2000 + 500
+        let err = risk.pre_check(&bto("AAPL", 5), 200.0, &st, today(), None);
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("max_underlying_exposure"));
+        risk.pre_check(&bto("AAPL", 2), 200.0, &st, today(), None)
+            .unwrap();
+        // two holdings open: a new symbol is refused, adding to one held is not
+        let err = risk.pre_check(&bto("MSFT", 1), 100.0, &st, today(), None);
+        assert!(err.unwrap_err().to_string().contains("max_open_positions"));
+
+        let st = BotState {
+            holdings: st.holdings[..1].to_vec(),
+            ..Default::default()
+        };
+        let err = risk.pre_check(&bto("MSFT", 10), 310.0, &st, today(), None);
+        assert!(err.unwrap_err().to_string().contains("max_total_exposure"));
+        risk.pre_check(&bto("MSFT", 10), 290.0, &st, today(), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn options_stop_trading_at_their_expiry_close() {
+        let sig = |msg: &str| crate::parser::parse_signal_on(msg, today().into()).unwrap();
+        let at = |h, m| today().and_hms_opt(h, m, 0).unwrap();
+        let risk = engine();
+        let expiring = sig("STC 1 SPY 450C 08/01 @ 2");
+        risk.check_session(&expiring, at(15, 59)).unwrap();
+        assert!(risk.check_session(&expiring, at(16, 0)).is_err());
+        risk.check_session(&sig("BTO 1 SPY 450C 08/04 @ 2"), at(17, 0))
+            .unwrap();
+        risk.check_session(&bto("AAPL", 1), at(17, 0)).unwrap();
+    }
+
+    #[test]
+    fn exits_ignore_portfolio_caps() {
+        let st = BotState {
+            holdings: (0..3)
+                .map(|i| Holding::Stock {
+                    symbol: format!("S{}", i),
+                    quantity: 10.0,
+                    avg_cost: 1_000.0,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let stc = stock(Action::STC, "S0", 10);
+        engine().pre_check(&stc, 900.0, &st, today(), None).unwrap();
+    }
 }
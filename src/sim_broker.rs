@@ -0,0 +1,428 @@
+//! Deterministic in-memory broker. Orders fill against scripted quotes so the full
+//! parse -> risk -> execute -> monitor pipeline can run without a Webull login.
+//!
+//! Fill rules (evaluated on placement and on every `get_order_info` poll):
+//! * market orders fill completely at the current quote;
+//! * limit buys fill at the limit when the quote is at or below it;
+//! * limit sells fill at the limit when the quote is at or above it.
+//!
+//! Sells beyond the held quantity open a short; buys against a short cover it first.
+//!
+//! Each poll of a still-working order advances that ticker's quote script by one step;
+//! the last scripted price sticks once the script is exhausted.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use webull_unofficial::models::{OptionContract, OrderAction, TimeInForce};
+
+use crate::broker::{Broker, OrderInfo, OrderStatus};
+use crate::calendar;
+use crate::instrument::OptionKey;
+use crate::state::BotState;
+use crate::types::{Action, Holding};
+
+#[derive(Debug, Clone)]
+enum SimInstrument {
+    Stock(String),
+    Option(OptionContract),
+}
+
+#[derive(Debug, Clone)]
+struct SimOrder {
+    ticker_id: i64,
+    side: OrderAction,
+    qty: f64,
+    limit: Option<f64>,
+    info: OrderInfo,
+}
+
+#[derive(Default)]
+struct SimInner {
+    next_ticker_id: i64,
+    next_order_id: u64,
+    instruments: HashMap<i64, SimInstrument>,
+    quotes: HashMap<i64, VecDeque<f64>>,
+    orders: HashMap<String, SimOrder>,
+    book: BotState,
+    clock: Option<NaiveDateTime>,
+    account_value: Option<f64>,
+    extended_orders: usize,
+}
+
+#[derive(Default)]
+pub struct SimBroker {
+    inner: Mutex<SimInner>,
+}
+
+impl SimBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a tradable stock and return its ticker id.
+    pub fn add_stock(&self, symbol: &str) -> i64 {
+        let mut g = self.inner.lock().unwrap();
+        g.next_ticker_id += 1;
+        let id = g.next_ticker_id;
+        g.instruments
+            .insert(id, SimInstrument::Stock(symbol.to_ascii_uppercase()));
+        id
+    }
+
+    /// Register a listed option contract (`expiration_date` as "YYYY-MM-DD") and return its ticker id.
+    pub fn add_option(&self, symbol: &str, strike: f64, cp: char, expiration_date: &str) -> i64 {
+        let mut g = self.inner.lock().unwrap();
+        g.next_ticker_id += 1;
+        let id = g.next_ticker_id;
+        let option_type = if cp.eq_ignore_ascii_case(&'C') {
+            "CALL"
+        } else {
+            "PUT"
+        };
+        g.instruments.insert(
+            id,
+            SimInstrument::Option(OptionContract {
+                ticker_id: id,
+                symbol: symbol.to_ascii_uppercase(),
+                strike_price: strike,
+                expiration_date: expiration_date.to_string(),
+                option_type: option_type.to_string(),
+            }),
+        );
+        id
+    }
+
+    /// Replace the quote script for a ticker with a single fixed price.
+    pub fn set_price(&self, ticker_id: i64, price: f64) {
+        self.script_prices(ticker_id, vec![price]);
+    }
+
+    /// Replace the quote script for a ticker. Prices are consumed one per poll.
+    pub fn script_prices(&self, ticker_id: i64, prices: Vec<f64>) {
+        let mut g = self.inner.lock().unwrap();
+        g.quotes.insert(ticker_id, prices.into());
+    }
+
+    /// Seed a broker-side position (as if it existed before the bot started).
+    pub fn seed_holding(&self, holding: Holding) {
+        let mut g = self.inner.lock().unwrap();
+        g.book.holdings.push(holding);
+    }
+
+    /// Pin the simulated clock (UTC; used for P/L dates and the market session);
+    /// wall-clock time is used until set.
+    pub fn set_time(&self, now: NaiveDateTime) {
+        self.inner.lock().unwrap().clock = Some(now);
+    }
+
+    /// Starting account value; net liquidation is this plus P/L realized since.
+    pub fn set_account_value(&self, value: f64) {
+        self.inner.lock().unwrap().account_value = Some(value);
+    }
+
+    /// Number of orders placed so far (including canceled ones).
+    pub fn order_count(&self) -> usize {
+        self.inner.lock().unwrap().orders.len()
+    }
+
+    /// How many of those were extended-hours stock orders.
+    pub fn extended_order_count(&self) -> usize {
+        self.inner.lock().unwrap().extended_orders
+    }
+}
+
+impl SimInner {
+    /// US/Eastern time on the simulated clock.
+    fn now(&self) -> NaiveDateTime {
+        calendar::eastern(self.clock.unwrap_or_else(|| Utc::now().naive_utc()))
+    }
+
+    fn today(&self) -> NaiveDate {
+        self.now().date()
+    }
+
+    fn current_price(&self, ticker_id: i64) -> Option<f64> {
+        self.quotes.get(&ticker_id).and_then(|q| q.front().copied())
+    }
+
+    fn advance(&mut self, ticker_id: i64) {
+        if let Some(q) = self.quotes.get_mut(&ticker_id) {
+            if q.len() > 1 {
+                q.pop_front();
+            }
+        }
+    }
+
+    fn place(&mut self, ticker_id: i64, qty: f64, side: OrderAction, limit: Option<f64>) -> String {
+        self.next_order_id += 1;
+        let id = format!("SIM-{}", self.next_order_id);
+        self.orders.insert(
+            id.clone(),
+            SimOrder {
+                ticker_id,
+                side,
+                qty,
+                limit,
+                info: OrderInfo {
+                    status: OrderStatus::Working,
+                    filled_qty: 0.0,
+                    avg_fill_price: 0.0,
+                },
+            },
+        );
+        self.try_fill(&id);
+        id
+    }
+
+    /// Fill a working order completely if the current quote allows it.
+    fn try_fill(&mut self, order_id: &str) {
+        let Some(o) = self.orders.get(order_id).cloned() else {
+            return;
+        };
+        if o.info.status != OrderStatus::Working {
+            return;
+        }
+        let Some(px) = self.current_price(o.ticker_id) else {
+            return;
+        };
+        let fill_px = match (o.limit, &o.side) {
+            (None, _) => px,
+            (Some(l), OrderAction::Buy) if px <= l + 1e-9 => l,
+            (Some(l), OrderAction::Sell) if px + 1e-9 >= l => l,
+            _ => return,
+        };
+        self.apply_fill(o.ticker_id, &o.side, o.qty, fill_px);
+        if let Some(o) = self.orders.get_mut(order_id) {
+            o.info = OrderInfo {
+                status: OrderStatus::Filled,
+                filled_qty: o.qty,
+                avg_fill_price: fill_px,
+            };
+        }
+    }
+
+    /// Book a fill the way a margin account nets it: a buy first covers any short and a
+    /// sell first closes any long; the rest opens a position on that side.
+    fn apply_fill(&mut self, ticker_id: i64, side: &OrderAction, qty: f64, px: f64) {
+        let date = self.today();
+        match self.instruments.get(&ticker_id).cloned() {
+            Some(SimInstrument::Stock(sym)) => {
+                let held = self.book.position_qty_stock(&sym);
+                for (action, q) in split_fill(side, held, qty) {
+                    if q > 0.0 {
+                        self.book.apply_stock_fill(action, &sym, q, px, date);
+                    }
+                }
+            }
+            Some(SimInstrument::Option(c)) => {
+                let Some(key) = crate::utils::contract_key(&c) else {
+                    return;
+                };
+                let held = self.book.position_qty_option(&key) as f64;
+                for (action, q) in split_fill(side, held, qty) {
+                    let q = q as u32;
+                    if q == 0 {
+                        continue;
+                    }
+                    match action {
+                        Action::BTO => self.book.upsert_option_buy_with_cost(&key, q, px),
+                        Action::STO => self.book.upsert_option_short_with_proceeds(&key, q, px),
+                        Action::STC => {
+                            self.book.realize_option_sell(&key, q, px, date);
+                        }
+                        Action::BTC => {
+                            self.book.realize_option_cover(&key, q, px, date);
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// (closing, opening) parts of a fill of `qty` on `side` against a net position `held`.
+fn split_fill(side: &OrderAction, held: f64, qty: f64) -> [(Action, f64); 2] {
+    match side {
+        OrderAction::Buy => {
+            let cover = qty.min((-held).max(0.0));
+            [(Action::BTC, cover), (Action::BTO, qty - cover)]
+        }
+        OrderAction::Sell => {
+            let close = qty.min(held.max(0.0));
+            [(Action::STC, close), (Action::STO, qty - close)]
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Broker for SimBroker {
+    fn trade_date(&self) -> NaiveDate {
+        self.inner.lock().unwrap().today()
+    }
+
+    fn market_time(&self) -> NaiveDateTime {
+        self.inner.lock().unwrap().now()
+    }
+
+    async fn find_stock_ticker_id(&self, symbol: &str) -> Result<i64> {
+        let g = self.inner.lock().unwrap();
+        g.instruments
+            .iter()
+            .filter_map(|(id, ins)| match ins {
+                SimInstrument::Stock(s) if s.eq_ignore_ascii_case(symbol) => Some(*id),
+                _ => None,
+            })
+            .min()
+            .context("no ticker found")
+    }
+
+    async fn find_option_contract(&self, key: &OptionKey) -> Result<OptionContract> {
+        let g = self.inner.lock().unwrap();
+        g.instruments
+            .values()
+            .filter_map(|ins| match ins {
+                SimInstrument::Option(c) if crate::utils::contract_key(c).as_ref() == Some(key) => {
+                    Some(c)
+                }
+                _ => None,
+            })
+            .min_by_key(|c| c.ticker_id)
+            .cloned()
+            .context("option contract not found (by strike/type/expiry)")
+    }
+
+    async fn listed_expiries(&self, key: &OptionKey) -> Result<Vec<NaiveDate>> {
+        let g = self.inner.lock().unwrap();
+        let mut expiries: Vec<NaiveDate> = g
+            .instruments
+            .values()
+            .filter_map(|ins| match ins {
+                SimInstrument::Option(c) => crate::utils::contract_key(c),
+                _ => None,
+            })
+            .filter(|k| OptionKey::new(&key.symbol, key.strike, key.call_put, k.expiry) == *k)
+            .map(|k| k.expiry)
+            .collect();
+        expiries.sort();
+        expiries.dedup();
+        Ok(expiries)
+    }
+
+    async fn mid_price(&self, ticker_id: i64) -> Result<f64> {
+        let g = self.inner.lock().unwrap();
+        g.current_price(ticker_id).context("no quote scripted")
+    }
+
+    async fn net_liquidation(&self) -> Result<f64> {
+        let g = self.inner.lock().unwrap();
+        let start = g.account_value.context("no account value set")?;
+        Ok(start + g.book.daily_pl.iter().map(|e| e.realized_pl).sum::<f64>())
+    }
+
+    async fn positions_simple(&self) -> Result<Vec<Holding>> {
+        Ok(self.inner.lock().unwrap().book.holdings.clone())
+    }
+
+    async fn get_order_info(&self, order_id: &str) -> Result<OrderInfo> {
+        let mut g = self.inner.lock().unwrap();
+        let ticker_id = g
+            .orders
+            .get(order_id)
+            .context("unknown order id")?
+            .ticker_id;
+        g.try_fill(order_id);
+        let info = g.orders[order_id].info.clone();
+        if info.status == OrderStatus::Working {
+            g.advance(ticker_id);
+        }
+        Ok(info)
+    }
+
+    async fn cancel_order(&self, order_id: &str) -> Result<()> {
+        let mut g = self.inner.lock().unwrap();
+        let o = g.orders.get_mut(order_id).context("unknown order id")?;
+        if matches!(
+            o.info.status,
+            OrderStatus::Working | OrderStatus::PartiallyFilled
+        ) {
+            o.info.status = OrderStatus::Canceled;
+        }
+        Ok(())
+    }
+
+    async fn place_stock_market(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        _tif: &TimeInForce,
+    ) -> Result<String> {
+        let tid = self.find_stock_ticker_id(symbol).await?;
+        Ok(self.inner.lock().unwrap().place(tid, qty, side, None))
+    }
+
+    async fn place_stock_limit(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        _tif: &TimeInForce,
+    ) -> Result<String> {
+        let tid = self.find_stock_ticker_id(symbol).await?;
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .place(tid, qty, side, Some(limit)))
+    }
+
+    async fn place_stock_limit_extended(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        tif: &TimeInForce,
+    ) -> Result<String> {
+        let id = self
+            .place_stock_limit(symbol, qty, side, limit, tif)
+            .await?;
+        self.inner.lock().unwrap().extended_orders += 1;
+        Ok(id)
+    }
+
+    async fn place_option_market(
+        &self,
+        contract: &OptionContract,
+        qty: f64,
+        side: OrderAction,
+        _tif: &TimeInForce,
+    ) -> Result<String> {
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .place(contract.ticker_id, qty, side, None))
+    }
+
+    async fn place_option_limit(
+        &self,
+        contract: &OptionContract,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        _tif: &TimeInForce,
+    ) -> Result<String> {
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .place(contract.ticker_id, qty, side, Some(limit)))
+    }
+}
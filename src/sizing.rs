@@ -0,0 +1,133 @@
+//! Entry sizing: recompute an entry's quantity from a dollar amount, a share of account
+//! value or a loss budget against the signal's stop. Runs before the risk check, which
+//! then sees the sized order.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::types::TradeSignal;
+
+/// How entry quantities are chosen. Exits keep the signal's size.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SizingMode {
+    /// Quantity as posted (1 when omitted).
+    #[default]
+    Signal,
+    /// Spend about `amount` USD per entry.
+    FixedDollar { amount: f64 },
+    /// Spend `pct` of account net liquidation per entry (0.05 = 5%).
+    PercentOfAccount { pct: f64 },
+    /// Lose at most `max_loss` USD if the entry is stopped out. Without a stop the
+    /// whole premium is at risk for options and spreads; stock entries need a stop.
+    RiskBased { max_loss: f64 },
+}
+
+impl SizingMode {
+    pub fn needs_account_value(&self) -> bool {
+        matches!(self, SizingMode::PercentOfAccount { .. })
+    }
+
+    /// Whole units (shares, contracts or spreads) for an entry priced at `price` per
+    /// unit, or None to keep the signal's quantity. `account` is net liquidation, only
+    /// read by `PercentOfAccount`.
+    pub fn size(
+        &self,
+        signal: &TradeSignal,
+        price: f64,
+        account: Option<f64>,
+    ) -> Result<Option<u32>> {
+        let budget = match *self {
+            SizingMode::Signal => return Ok(None),
+            SizingMode::FixedDollar { amount } => amount,
+            SizingMode::PercentOfAccount { pct } => match account {
+                Some(v) => v * pct,
+                None => bail!("percent_of_account sizing needs the account value"),
+            },
+            SizingMode::RiskBased { max_loss } => max_loss,
+        };
+        let per_unit = match self {
+            SizingMode::RiskBased { .. } => unit_risk(signal, price)?,
+            _ => unit_cost(signal, price),
+        };
+        if per_unit <= 0.0 {
+            bail!("no price to size {} from", signal.label());
+        }
+        let qty = (budget / per_unit + 1e-9).floor();
+        if qty < 1.0 {
+            bail!(
+                "sizing budget ${:.2} is below one unit of {} (${:.2})",
+                budget,
+                signal.label(),
+                per_unit
+            );
+        }
+        Ok(Some(qty as u32))
+    }
+}
+
+/// USD per unit at `price`: share price, premium × multiplier, or worst-case spread loss
+/// × multiplier.
+fn unit_cost(signal: &TradeSignal, price: f64) -> f64 {
+    match signal {
+        TradeSignal::Stock(_) => price,
+        TradeSignal::Option(o) => price * o.key.multiplier(),
+        TradeSignal::Spread(sp) => sp.max_loss_per_spread(price) * sp.multiplier(),
+    }
+}
+
+/// USD lost per unit if the signal's stop is hit.
+fn unit_risk(signal: &TradeSignal, price: f64) -> Result<f64> {
+    Ok(match signal {
+        TradeSignal::Stock(s) => match s.exit_levels.stop {
+            Some(stop) => (price - stop).abs(),
+            None => bail!("risk_based sizing needs a stop on {}", s.symbol),
+        },
+        TradeSignal::Option(o) => match o.exit_levels.stop {
+            Some(stop) => (price - stop).abs() * o.key.multiplier(),
+            None => price * o.key.multiplier(),
+        },
+        TradeSignal::Spread(_) => unit_cost(signal, price),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_signal_on;
+    use chrono::NaiveDate;
+
+    fn sig(msg: &str) -> TradeSignal {
+        parse_signal_on(msg, NaiveDate::from_ymd_opt(2025, 8, 1).unwrap().into()).unwrap()
+    }
+
+    #[test]
+    fn sizes_by_dollars_account_and_risk() {
+        let stock = sig("BTO AAPL @ 190 SL 185");
+        let fixed = SizingMode::FixedDollar { amount: 2_000.0 };
+        assert_eq!(fixed.size(&stock, 190.0, None).unwrap(), Some(10));
+        let pct = SizingMode::PercentOfAccount { pct: 0.05 };
+        assert_eq!(pct.size(&stock, 190.0, Some(50_000.0)).unwrap(), Some(13));
+        assert!(pct.size(&stock, 190.0, None).is_err());
+        let risk = SizingMode::RiskBased { max_loss: 100.0 };
+        assert_eq!(risk.size(&stock, 190.0, None).unwrap(), Some(20));
+
+        let option = sig("BTO SPY 450C 09/20 @ 2.50");
+        assert_eq!(fixed.size(&option, 2.5, None).unwrap(), Some(8));
+        // no stop: the whole premium is at risk
+        let risk = SizingMode::RiskBased { max_loss: 500.0 };
+        assert_eq!(risk.size(&option, 2.5, None).unwrap(), Some(2));
+        let stopped = sig("BTO SPY 450C 09/20 @ 2.50 SL 2.00");
+        assert_eq!(risk.size(&stopped, 2.5, None).unwrap(), Some(10));
+        assert_eq!(SizingMode::Signal.size(&option, 2.5, None).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_budgets_below_one_unit_and_stock_without_stop() {
+        let stock = sig("BTO AAPL @ 190");
+        let fixed = SizingMode::FixedDollar { amount: 100.0 };
+        assert!(fixed.size(&stock, 190.0, None).is_err());
+        let risk = SizingMode::RiskBased { max_loss: 100.0 };
+        assert!(risk.size(&stock, 190.0, None).is_err());
+    }
+}
@@ -1,10 +1,13 @@
-//! Persisted bot state. V3: store full holdings and realized daily P/L entries.
+//! Bot state: full holdings and realized daily P/L entries. Persisted by `store`;
+//! JSON is kept for importing old state files and for backtest output.
 
+use anyhow::Context;
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::{fs, path::Path};
 
-use crate::types::{Holding, PlEntry};
+use crate::instrument::{contract_multiplier, InstrumentId, OptionKey};
+use crate::types::{Action, ExitBracket, Holding, PlEntry, SpreadSignal, TradeSignal};
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct BotState {
@@ -12,21 +15,26 @@ pub struct BotState {
     pub holdings: Vec<Holding>,
     /// Realized P/L entries by day.
     pub daily_pl: Vec<PlEntry>,
+    /// Armed stop-loss / profit-target exits for open holdings.
+    #[serde(default)]
+    pub brackets: Vec<ExitBracket>,
+    /// Instruments frozen by reconciliation; signals on them are rejected.
+    #[serde(default)]
+    pub frozen: Vec<InstrumentId>,
+    /// Trading date on which the daily loss limit was hit; entries stay halted that day.
+    #[serde(default)]
+    pub halted_on: Option<NaiveDate>,
 }
 
 impl BotState {
-    pub fn load(path: &str) -> Self {
-        if Path::new(path).exists() {
-            if let Ok(s) = fs::read_to_string(path) {
-                if let Ok(me) = serde_json::from_str::<Self>(&s) {
-                    return me;
-                }
-            }
-        }
-        Self::default()
+    /// Read a JSON state file (the format used before the SQLite store).
+    pub fn load_json(path: &str) -> anyhow::Result<Self> {
+        let s = fs::read_to_string(path).with_context(|| format!("read {}", path))?;
+        serde_json::from_str(&s).with_context(|| format!("parse state file {}", path))
     }
 
-    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+    /// Write the state as pretty-printed JSON (backtest output).
+    pub fn save_json(&self, path: &str) -> anyhow::Result<()> {
         if let Some(parent) = Path::new(path).parent() {
             std::fs::create_dir_all(parent)?;
         }
@@ -35,10 +43,81 @@ impl BotState {
         Ok(())
     }
 
-    pub fn set_holdings(&mut self, new_holdings: Vec<Holding>) {
+    /// Replace holdings with a broker snapshot. The broker reports spread legs as single
+    /// options, so locally tracked spreads are kept whole and their legs taken out of the
+    /// snapshot; a spread whose long leg is gone at the broker is dropped.
+    pub fn set_holdings(&mut self, mut new_holdings: Vec<Holding>) {
+        let mut spreads = Vec::new();
+        for h in self.holdings.drain(..) {
+            let Holding::Spread {
+                symbol,
+                kind,
+                legs,
+                expiry,
+                quantity,
+                avg_cost,
+            } = h
+            else {
+                continue;
+            };
+            let keys: Vec<OptionKey> = legs
+                .iter()
+                .map(|l| OptionKey::new(&symbol, l.strike, l.call_put, expiry))
+                .collect();
+            let mut qty = quantity;
+            for (leg, key) in legs.iter().zip(&keys) {
+                let held = new_holdings
+                    .iter()
+                    .find(|h| option_matches(h, key))
+                    .map(|h| match h {
+                        Holding::Option { quantity, .. } => *quantity,
+                        _ => 0,
+                    })
+                    .unwrap_or(0);
+                if leg.ratio > 0 {
+                    qty = qty.min(held.max(0) as u32);
+                }
+            }
+            if qty == 0 {
+                continue;
+            }
+            for (leg, key) in legs.iter().zip(&keys) {
+                new_holdings.retain_mut(|h| {
+                    if !option_matches(h, key) {
+                        return true;
+                    }
+                    // long legs show up as positive broker qty, short legs as negative
+                    if let Holding::Option { quantity, .. } = h {
+                        *quantity -= leg.ratio * qty as i32;
+                        return *quantity != 0;
+                    }
+                    true
+                });
+            }
+            spreads.push(Holding::Spread {
+                symbol,
+                kind,
+                legs,
+                expiry,
+                quantity: qty,
+                avg_cost,
+            });
+        }
+        new_holdings.extend(spreads);
         self.holdings = new_holdings;
     }
 
+    /// Arm (or re-arm) the stop/target exit for an instrument.
+    pub fn set_bracket(&mut self, bracket: ExitBracket) {
+        self.remove_bracket(bracket.ticker_id);
+        self.brackets.push(bracket);
+    }
+
+    pub fn remove_bracket(&mut self, ticker_id: i64) {
+        self.brackets.retain(|b| b.ticker_id != ticker_id);
+    }
+
+    /// Net shares held; negative for a short.
     pub fn position_qty_stock(&self, symbol: &str) -> f64 {
         let sym = symbol.to_ascii_uppercase();
         self.holdings.iter().fold(0.0, |acc, h| match h {
@@ -49,34 +128,201 @@ impl BotState {
         })
     }
 
-    pub fn position_qty_option(
-        &self,
-        symbol: &str,
-        strike: f64,
-        cp: char,
-        expiry_mmdd: &str,
-    ) -> u32 {
-        let sym = symbol.to_ascii_uppercase();
-        let cp_u = cp.to_ascii_uppercase();
-        self.holdings.iter().fold(0u32, |acc, h| match h {
+    /// Net contracts held; negative for a short.
+    pub fn position_qty_option(&self, key: &OptionKey) -> i32 {
+        self.holdings.iter().fold(0i32, |acc, h| match h {
             Holding::Option {
-                symbol,
-                strike: s,
-                call_put,
-                expiry_mmdd,
-                quantity,
-                ..
-            } if symbol.eq_ignore_ascii_case(&sym)
-                && (*s - strike).abs() < 1e-6
-                && call_put.to_ascii_uppercase() == cp_u
-                && expiry_mmdd == expiry_mmdd =>
-            {
-                acc + *quantity
-            }
+                key: k, quantity, ..
+            } if k == key => acc + *quantity,
             _ => acc,
         })
     }
 
+    pub fn position_qty_spread(&self, sp: &SpreadSignal) -> u32 {
+        self.holdings
+            .iter()
+            .filter(|h| spread_matches(h, sp))
+            .fold(0u32, |acc, h| match h {
+                Holding::Spread { quantity, .. } => acc + *quantity,
+                _ => acc,
+            })
+    }
+
+    /// Quantity `exit` can still close: the long for STC, the short (counted positive)
+    /// for BTC; negative when the position is on the other side.
+    pub fn held_for(&self, exit: &TradeSignal) -> f64 {
+        let net = match exit {
+            TradeSignal::Stock(s) => self.position_qty_stock(&s.symbol),
+            TradeSignal::Option(o) => self.position_qty_option(&o.key) as f64,
+            TradeSignal::Spread(sp) => self.position_qty_spread(sp) as f64,
+        };
+        if exit.action() == Action::BTC {
+            -net
+        } else {
+            net
+        }
+    }
+
+    /// Gross value at risk on open shorts: shares × average sale price for stock,
+    /// contracts × strike × contract multiplier for options.
+    pub fn short_exposure(&self) -> f64 {
+        self.holdings
+            .iter()
+            .map(|h| match h {
+                Holding::Stock {
+                    quantity, avg_cost, ..
+                } if *quantity < 0.0 => -*quantity * *avg_cost,
+                Holding::Option { key, quantity, .. } if *quantity < 0 => {
+                    -(*quantity as f64) * key.strike * key.multiplier()
+                }
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    /// Cost basis of all open holdings (see `Holding::cost_basis`).
+    pub fn gross_exposure(&self) -> f64 {
+        self.holdings.iter().map(Holding::cost_basis).sum()
+    }
+
+    /// Cost basis of the stock and every option or spread on `symbol`.
+    pub fn underlying_exposure(&self, symbol: &str) -> f64 {
+        self.holdings
+            .iter()
+            .filter(|h| h.underlying().eq_ignore_ascii_case(symbol))
+            .map(Holding::cost_basis)
+            .sum()
+    }
+
+    /// Number of open holdings; a spread counts once.
+    pub fn open_positions(&self) -> usize {
+        self.holdings
+            .iter()
+            .filter(|h| h.quantity().abs() > 1e-9)
+            .count()
+    }
+
+    /// Attribute P/L entries recorded from index `since` on to `author`.
+    pub fn attribute_pl(&mut self, since: usize, author: &str) {
+        for e in self.daily_pl.iter_mut().skip(since) {
+            e.author = Some(author.to_string());
+        }
+    }
+
+    /// Realized P/L on `date` attributed to `author`.
+    pub fn author_realized_pl(&self, author: &str, date: NaiveDate) -> f64 {
+        self.daily_pl
+            .iter()
+            .filter(|e| e.date == date && e.author.as_deref() == Some(author))
+            .map(|e| e.realized_pl)
+            .sum()
+    }
+
+    /// Weighted-average add for spread fills; `net_price` is the net debit per spread.
+    pub fn upsert_spread_buy_with_cost(
+        &mut self,
+        sp: &SpreadSignal,
+        fill_qty: u32,
+        net_price: f64,
+    ) {
+        if let Some(Holding::Spread {
+            quantity, avg_cost, ..
+        }) = self.holdings.iter_mut().find(|h| spread_matches(h, sp))
+        {
+            let total_cost = *avg_cost * (*quantity as f64) + net_price * (fill_qty as f64);
+            *quantity += fill_qty;
+            *avg_cost = if *quantity > 0 {
+                total_cost / (*quantity as f64)
+            } else {
+                0.0
+            };
+        } else {
+            self.holdings.push(Holding::Spread {
+                symbol: sp.symbol.to_ascii_uppercase(),
+                kind: sp.kind,
+                legs: sp.legs.clone(),
+                expiry: sp.expiry,
+                quantity: fill_qty,
+                avg_cost: net_price,
+            });
+        }
+    }
+
+    /// Realize combined P/L for closing spreads at `net_credit` per spread. Returns realized P/L.
+    pub fn realize_spread_sell(
+        &mut self,
+        sp: &SpreadSignal,
+        sell_qty: u32,
+        net_credit: f64,
+        date: NaiveDate,
+    ) -> f64 {
+        let Some(i) = self.holdings.iter().position(|h| spread_matches(h, sp)) else {
+            return 0.0;
+        };
+        let Holding::Spread {
+            quantity, avg_cost, ..
+        } = &mut self.holdings[i]
+        else {
+            return 0.0;
+        };
+        let q = sell_qty.min(*quantity);
+        let realized = (net_credit - *avg_cost) * (q as f64) * contract_multiplier(&sp.symbol);
+        *quantity -= q;
+        if *quantity == 0 {
+            self.holdings.remove(i);
+        }
+        self.daily_pl.push(PlEntry {
+            date,
+            asset: sp.label(),
+            qty: q as f64,
+            realized_pl: realized,
+            author: None,
+        });
+        realized
+    }
+
+    /// Book a stock fill: BTO / STO add to the long / short, STC / BTC realize P/L.
+    pub fn apply_stock_fill(
+        &mut self,
+        action: Action,
+        symbol: &str,
+        qty: f64,
+        price: f64,
+        date: NaiveDate,
+    ) {
+        match action {
+            Action::BTO => self.upsert_stock_buy_with_cost(symbol, qty, price),
+            Action::STO => self.upsert_stock_short_with_proceeds(symbol, qty, price),
+            Action::STC => {
+                self.realize_stock_sell(symbol, qty, price, date);
+            }
+            Action::BTC => {
+                self.realize_stock_cover(symbol, qty, price, date);
+            }
+        }
+    }
+
+    /// Option counterpart of [`BotState::apply_stock_fill`].
+    pub fn apply_option_fill(
+        &mut self,
+        action: Action,
+        key: &OptionKey,
+        qty: u32,
+        price: f64,
+        date: NaiveDate,
+    ) {
+        match action {
+            Action::BTO => self.upsert_option_buy_with_cost(key, qty, price),
+            Action::STO => self.upsert_option_short_with_proceeds(key, qty, price),
+            Action::STC => {
+                self.realize_option_sell(key, qty, price, date);
+            }
+            Action::BTC => {
+                self.realize_option_cover(key, qty, price, date);
+            }
+        }
+    }
+
     /// Weighted-average add for stock BUY fills.
     pub fn upsert_stock_buy_with_cost(&mut self, symbol: &str, fill_qty: f64, fill_price: f64) {
         let sym = symbol.to_ascii_uppercase();
@@ -105,28 +351,27 @@ impl BotState {
     }
 
     /// Weighted-average add for option BUY fills.
-    pub fn upsert_option_buy_with_cost(
-        &mut self,
-        symbol: &str,
-        strike: f64,
-        cp: char,
-        expiry_mmdd: &str,
-        fill_qty: u32,
-        fill_price: f64,
-    ) {
-        let sym = symbol.to_ascii_uppercase();
-        let cp_u = cp.to_ascii_uppercase();
-        let exp = expiry_mmdd.to_string();
-        if let Some(h) = self.holdings.iter_mut().find(|h| {
-            matches!(h, Holding::Option { symbol, strike: s, call_put, expiry_mmdd, .. }
-                if symbol.eq_ignore_ascii_case(&sym) && (*s - strike).abs() < 1e-6 && call_put.to_ascii_uppercase() == cp_u && expiry_mmdd == &exp)
-        }) {
-            if let Holding::Option { quantity, avg_cost, .. } = h {
-                let qf = *quantity as f64; let total_cost = *avg_cost * qf + fill_price * (fill_qty as f64);
-                *quantity += fill_qty; *avg_cost = if *quantity > 0 { total_cost / (*quantity as f64) } else { 0.0 };
+    pub fn upsert_option_buy_with_cost(&mut self, key: &OptionKey, fill_qty: u32, fill_price: f64) {
+        if let Some(h) = self.holdings.iter_mut().find(|h| option_matches(h, key)) {
+            if let Holding::Option {
+                quantity, avg_cost, ..
+            } = h
+            {
+                let qf = *quantity as f64;
+                let total_cost = *avg_cost * qf + fill_price * (fill_qty as f64);
+                *quantity += fill_qty as i32;
+                *avg_cost = if *quantity > 0 {
+                    total_cost / (*quantity as f64)
+                } else {
+                    0.0
+                };
             }
         } else {
-            self.holdings.push(Holding::Option { symbol: sym, strike, call_put: cp_u, expiry_mmdd: exp, quantity: fill_qty, avg_cost: fill_price });
+            self.holdings.push(Holding::Option {
+                key: key.clone(),
+                quantity: fill_qty as i32,
+                avg_cost: fill_price,
+            });
         }
     }
 
@@ -149,7 +394,7 @@ impl BotState {
             } = h
             {
                 if symbol.eq_ignore_ascii_case(&sym) {
-                    let q = sell_qty.min(*quantity);
+                    let q = sell_qty.min((*quantity).max(0.0));
                     realized = (sell_price - *avg_cost) * q;
                     *quantity -= q;
                     if *quantity <= 1e-9 {
@@ -160,6 +405,7 @@ impl BotState {
                         asset: sym.clone(),
                         qty: q,
                         realized_pl: realized,
+                        author: None,
                     });
                     break;
                 }
@@ -174,46 +420,34 @@ impl BotState {
     /// Realize P/L for option sell; decrease position by contracts. Returns realized P/L.
     pub fn realize_option_sell(
         &mut self,
-        symbol: &str,
-        strike: f64,
-        cp: char,
-        expiry_mmdd: &str,
+        key: &OptionKey,
         sell_qty: u32,
         sell_price: f64,
         date: NaiveDate,
     ) -> f64 {
-        let sym = symbol.to_ascii_uppercase();
-        let cp_u = cp.to_ascii_uppercase();
         let mut realized = 0.0;
         let mut remove_idx: Option<usize> = None;
         for (i, h) in self.holdings.iter_mut().enumerate() {
             if let Holding::Option {
-                symbol,
-                strike: s,
-                call_put,
-                expiry_mmdd: exp,
+                key: k,
                 quantity,
                 avg_cost,
             } = h
             {
-                if symbol.eq_ignore_ascii_case(&sym)
-                    && (*s - strike).abs() < 1e-6
-                    && call_put.to_ascii_uppercase() == cp_u
-                    && exp == expiry_mmdd
-                {
-                    let q = sell_qty.min(*quantity);
-                    // Options PL is per contract × 100 shares
-                    realized = (sell_price - *avg_cost) * (q as f64) * 100.0;
-                    *quantity -= q;
+                if k == key {
+                    let q = sell_qty.min((*quantity).max(0) as u32);
+                    // Options PL is per contract × multiplier (100 shares for equities)
+                    realized = (sell_price - *avg_cost) * (q as f64) * key.multiplier();
+                    *quantity -= q as i32;
                     if *quantity == 0 {
                         remove_idx = Some(i);
                     }
-                    let asset = format!("{} {}{} {}", sym, strike, cp_u, expiry_mmdd);
                     self.daily_pl.push(PlEntry {
                         date,
-                        asset,
+                        asset: key.to_string(),
                         qty: q as f64,
                         realized_pl: realized,
+                        author: None,
                     });
                     break;
                 }
@@ -224,4 +458,246 @@ impl BotState {
         }
         realized
     }
+
+    /// Weighted-average add for stock STO fills; `avg_cost` is the average sale price.
+    pub fn upsert_stock_short_with_proceeds(
+        &mut self,
+        symbol: &str,
+        fill_qty: f64,
+        fill_price: f64,
+    ) {
+        let sym = symbol.to_ascii_uppercase();
+        if let Some(Holding::Stock {
+            quantity, avg_cost, ..
+        }) = self.holdings.iter_mut().find(|h| {
+            matches!(h, Holding::Stock { symbol, quantity, .. }
+                if symbol.eq_ignore_ascii_case(&sym) && *quantity < 0.0)
+        }) {
+            let short = -*quantity + fill_qty;
+            *avg_cost = (*avg_cost * -*quantity + fill_price * fill_qty) / short;
+            *quantity = -short;
+        } else {
+            self.holdings.push(Holding::Stock {
+                symbol: sym,
+                quantity: -fill_qty,
+                avg_cost: fill_price,
+            });
+        }
+    }
+
+    /// Realize P/L for a stock BTC; shrinks the short by qty. Returns realized P/L.
+    pub fn realize_stock_cover(
+        &mut self,
+        symbol: &str,
+        cover_qty: f64,
+        cover_price: f64,
+        date: NaiveDate,
+    ) -> f64 {
+        let sym = symbol.to_ascii_uppercase();
+        let Some(i) = self.holdings.iter().position(|h| {
+            matches!(h, Holding::Stock { symbol, quantity, .. }
+                if symbol.eq_ignore_ascii_case(&sym) && *quantity < 0.0)
+        }) else {
+            return 0.0;
+        };
+        let Holding::Stock {
+            quantity, avg_cost, ..
+        } = &mut self.holdings[i]
+        else {
+            return 0.0;
+        };
+        let q = cover_qty.min(-*quantity);
+        let realized = (*avg_cost - cover_price) * q;
+        *quantity += q;
+        if *quantity >= -1e-9 {
+            self.holdings.remove(i);
+        }
+        self.daily_pl.push(PlEntry {
+            date,
+            asset: sym,
+            qty: q,
+            realized_pl: realized,
+            author: None,
+        });
+        realized
+    }
+
+    /// Weighted-average add for option STO fills; `avg_cost` is the average premium received.
+    pub fn upsert_option_short_with_proceeds(
+        &mut self,
+        key: &OptionKey,
+        fill_qty: u32,
+        fill_price: f64,
+    ) {
+        if let Some(Holding::Option {
+            quantity, avg_cost, ..
+        }) = self.holdings.iter_mut().find(|h| {
+            option_matches(h, key) && matches!(h, Holding::Option { quantity, .. } if *quantity < 0)
+        }) {
+            let short = -*quantity + fill_qty as i32;
+            *avg_cost =
+                (*avg_cost * -(*quantity as f64) + fill_price * fill_qty as f64) / short as f64;
+            *quantity = -short;
+        } else {
+            self.holdings.push(Holding::Option {
+                key: key.clone(),
+                quantity: -(fill_qty as i32),
+                avg_cost: fill_price,
+            });
+        }
+    }
+
+    /// Realize P/L for an option BTC; shrinks the short by contracts. Returns realized P/L.
+    pub fn realize_option_cover(
+        &mut self,
+        key: &OptionKey,
+        cover_qty: u32,
+        cover_price: f64,
+        date: NaiveDate,
+    ) -> f64 {
+        let Some(i) = self.holdings.iter().position(|h| {
+            option_matches(h, key) && matches!(h, Holding::Option { quantity, .. } if *quantity < 0)
+        }) else {
+            return 0.0;
+        };
+        let Holding::Option {
+            quantity, avg_cost, ..
+        } = &mut self.holdings[i]
+        else {
+            return 0.0;
+        };
+        let q = cover_qty.min(quantity.unsigned_abs());
+        let realized = (*avg_cost - cover_price) * (q as f64) * key.multiplier();
+        *quantity += q as i32;
+        if *quantity == 0 {
+            self.holdings.remove(i);
+        }
+        self.daily_pl.push(PlEntry {
+            date,
+            asset: key.to_string(),
+            qty: q as f64,
+            realized_pl: realized,
+            author: None,
+        });
+        realized
+    }
+}
+
+fn option_matches(h: &Holding, key: &OptionKey) -> bool {
+    matches!(h, Holding::Option { key: k, .. } if k == key)
+}
+
+fn spread_matches(h: &Holding, sp: &SpreadSignal) -> bool {
+    matches!(h, Holding::Spread { symbol, legs, expiry, .. }
+    if symbol.eq_ignore_ascii_case(&sp.symbol)
+        && *expiry == sp.expiry
+        && legs.len() == sp.legs.len()
+        && legs.iter().zip(&sp.legs).all(|(a, b)| {
+            (a.strike - b.strike).abs() < 1e-6
+                && a.call_put.eq_ignore_ascii_case(&b.call_put)
+                && a.ratio == b.ratio
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{OrderType, SpreadKind, SpreadLeg};
+    use chrono::Datelike;
+
+    fn sep20() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 9, 20).unwrap()
+    }
+
+    fn spy(strike: f64, cp: char) -> OptionKey {
+        OptionKey::new("SPY", strike, cp, sep20())
+    }
+
+    fn opt(strike: f64, quantity: i32) -> Holding {
+        Holding::Option {
+            key: spy(strike, 'C'),
+            quantity,
+            avg_cost: 1.0,
+        }
+    }
+
+    #[test]
+    fn broker_sync_keeps_spreads_whole() {
+        let sp = SpreadSignal {
+            action: Action::BTO,
+            symbol: "SPY".into(),
+            kind: SpreadKind::Vertical,
+            legs: vec![
+                SpreadLeg {
+                    strike: 450.0,
+                    call_put: 'C',
+                    ratio: 1,
+                },
+                SpreadLeg {
+                    strike: 455.0,
+                    call_put: 'C',
+                    ratio: -1,
+                },
+            ],
+            expiry: sep20(),
+            quantity: 2,
+            order_type: OrderType::Limit,
+            limit_price: Some(1.2),
+            relative_expiry: None,
+            relative_qty: None,
+        };
+        let mut st = BotState::default();
+        st.upsert_spread_buy_with_cost(&sp, 2, 1.2);
+
+        // broker shows both legs plus one extra long 450C bought by hand
+        st.set_holdings(vec![opt(450.0, 3), opt(455.0, -2)]);
+        assert_eq!(st.position_qty_spread(&sp), 2);
+        assert_eq!(st.position_qty_option(&spy(450.0, 'C')), 1);
+        assert_eq!(st.position_qty_option(&spy(455.0, 'C')), 0);
+
+        // long leg closed outside the bot -> spread dropped, short leg stays short
+        st.set_holdings(vec![opt(455.0, -2)]);
+        assert_eq!(st.position_qty_spread(&sp), 0);
+        assert_eq!(st.position_qty_option(&spy(455.0, 'C')), -2);
+    }
+
+    #[test]
+    fn short_fills_average_proceeds_and_cover_realizes() {
+        let d = NaiveDate::from_ymd_opt(2025, 9, 2).unwrap();
+        let mut st = BotState::default();
+        st.upsert_option_short_with_proceeds(&spy(440.0, 'P'), 2, 2.0);
+        st.upsert_option_short_with_proceeds(&spy(440.0, 'P'), 2, 3.0);
+        assert_eq!(st.position_qty_option(&spy(440.0, 'P')), -4);
+        assert!((st.short_exposure() - 4.0 * 440.0 * 100.0).abs() < 1e-6);
+
+        let pl = st.realize_option_cover(&spy(440.0, 'P'), 3, 1.0, d);
+        assert!((pl - 450.0).abs() < 1e-6);
+        assert_eq!(st.position_qty_option(&spy(440.0, 'P')), -1);
+
+        st.upsert_stock_short_with_proceeds("TSLA", 10.0, 200.0);
+        let pl = st.realize_stock_cover("TSLA", 10.0, 210.0, d);
+        assert!((pl + 100.0).abs() < 1e-6);
+        assert_eq!(st.position_qty_stock("TSLA"), 0.0);
+    }
+
+    #[test]
+    fn mmdd_state_files_migrate_to_dated_expiries() {
+        let old = r#"{
+            "holdings": [{"Option": {"symbol": "SPY", "strike": 450.0, "call_put": "C",
+                "expiry_mmdd": "09/20", "quantity": 1, "avg_cost": 2.0}}],
+            "daily_pl": []
+        }"#;
+        let st: BotState = serde_json::from_str(old).unwrap();
+        let Holding::Option { key, .. } = &st.holdings[0] else {
+            panic!("expected option holding");
+        };
+        let expiry = &key.expiry;
+        let today = crate::calendar::eastern_now().date();
+        assert_eq!((expiry.month(), expiry.day()), (9, 20));
+        assert!(*expiry >= today && *expiry < today + chrono::Duration::days(366));
+        // re-saved state carries the full date
+        assert!(serde_json::to_string(&st)
+            .unwrap()
+            .contains(&expiry.to_string()));
+    }
 }
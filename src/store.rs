@@ -0,0 +1,597 @@
+//! SQLite persistence (bundled, single file). Holdings, realized P/L, armed brackets,
+//! frozen instruments and the trading halt mirror `BotState`; signals are append-only
+//! history, `orders` is the order journal and `queued` holds signals waiting for the
+//! open.
+//!
+//! Every `save` runs in one transaction, so a crash mid-write leaves the previous
+//! snapshot intact. P/L entries are append-only and only new rows are written.
+//!
+//! A journaled order stays open until its monitor books the fills; booking and closing
+//! the journal entry share one transaction, so a restart never books a fill twice.
+//! Orders link to their signal, and signals to the Discord message they came from.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::calendar;
+use crate::state::BotState;
+use crate::types::{Author, ExitBracket, Holding, PlEntry, Side, TradeSignal};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS holdings (
+    id          INTEGER PRIMARY KEY,
+    instrument  TEXT NOT NULL,
+    quantity    REAL NOT NULL,
+    data        TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS pl_entries (
+    id          INTEGER PRIMARY KEY,
+    date        TEXT NOT NULL,
+    asset       TEXT NOT NULL,
+    qty         REAL NOT NULL,
+    realized_pl REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS brackets (
+    ticker_id   INTEGER PRIMARY KEY,
+    data        TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS signals (
+    id          INTEGER PRIMARY KEY,
+    received_at TEXT NOT NULL,
+    action      TEXT NOT NULL,
+    instrument  TEXT NOT NULL,
+    data        TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS orders (
+    order_id    TEXT PRIMARY KEY,
+    signal_id   INTEGER REFERENCES signals(id),
+    instrument  TEXT NOT NULL,
+    side        TEXT NOT NULL,
+    qty         REAL NOT NULL,
+    limit_price REAL,
+    placed_at   TEXT NOT NULL
+);
+";
+
+/// Schema changes applied in order on open; `PRAGMA user_version` counts applied steps.
+const MIGRATIONS: &[&str] = &[
+    // order journal: enough to resume a monitor after a restart
+    "ALTER TABLE orders ADD COLUMN signal TEXT;
+     ALTER TABLE orders ADD COLUMN deadline TEXT;
+     ALTER TABLE orders ADD COLUMN leg INTEGER;
+     ALTER TABLE orders ADD COLUMN spread_group TEXT;
+     ALTER TABLE orders ADD COLUMN done INTEGER NOT NULL DEFAULT 0;
+     UPDATE orders SET done = 1;",
+    // small JSON values that do not need a table of their own
+    "CREATE TABLE IF NOT EXISTS meta (
+         key   TEXT PRIMARY KEY,
+         value TEXT NOT NULL
+     );",
+    // per-author attribution of signals, orders and realized P/L
+    "ALTER TABLE signals ADD COLUMN author TEXT;
+     ALTER TABLE orders ADD COLUMN author TEXT;
+     ALTER TABLE pl_entries ADD COLUMN author TEXT;",
+    // Discord message a signal came from, to follow edits and deletions
+    "ALTER TABLE signals ADD COLUMN message_id TEXT;
+     CREATE INDEX IF NOT EXISTS signals_message ON signals (message_id);",
+    // Discord user ID of a signal's author, stable across renames
+    "ALTER TABLE signals ADD COLUMN author_id TEXT;",
+    // signals held for the next regular open, so a restart does not drop them
+    "CREATE TABLE IF NOT EXISTS queued (
+         id         INTEGER PRIMARY KEY,
+         key        TEXT,
+         author     TEXT,
+         author_id  TEXT,
+         signal_id  INTEGER REFERENCES signals(id),
+         signal     TEXT NOT NULL
+     );",
+];
+
+/// Journal entry for a placed order: what its monitor needs to resume after a restart.
+#[derive(Debug, Clone)]
+pub struct OrderRecord {
+    pub order_id: String,
+    pub signal_id: Option<i64>,
+    /// Signal as executed (symbol normalized, relative size resolved).
+    pub signal: TradeSignal,
+    pub side: Side,
+    pub qty: f64,
+    /// None for MARKET orders.
+    pub limit: Option<f64>,
+    /// When the timeout rule applies (US/Eastern): entries are canceled, exits converted
+    /// to MARKET.
+    pub deadline: NaiveDateTime,
+    /// Spread legs: leg index and the order id of the spread's first leg.
+    pub leg: Option<(usize, String)>,
+    /// Attribution key (`AuthorCfg::key`) of the configured author the signal came from;
+    /// its realized P/L is attributed to them.
+    pub author: Option<String>,
+}
+
+/// A signal held for the next regular open (`exec.outside_hours`).
+#[derive(Debug, Clone)]
+pub struct QueuedSignal {
+    /// Journal key of the message it came from, so edits and deletions can drop it.
+    pub key: Option<String>,
+    pub author: Option<Author>,
+    pub signal_id: Option<i64>,
+    pub signal: TradeSignal,
+}
+
+impl OrderRecord {
+    fn instrument(&self) -> String {
+        match (&self.signal, &self.leg) {
+            (TradeSignal::Spread(sp), Some((i, _))) => sp
+                .legs
+                .get(*i)
+                .map(|l| sp.leg_key(l).to_string())
+                .unwrap_or_else(|| sp.label()),
+            _ => self.signal.label(),
+        }
+    }
+}
+
+pub struct StateStore {
+    conn: Mutex<Connection>,
+}
+
+impl StateStore {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("open state db {}", path))?;
+        Self::init(conn).with_context(|| {
+            format!(
+                "init state db {} (a JSON state file is migrated with state.import_json)",
+                path
+            )
+        })
+    }
+
+    /// Throwaway database for tests and backtests.
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        let version: usize = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        for (i, step) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                step,
+                i + 1
+            ))?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// True until the first snapshot has been saved.
+    pub fn is_empty(&self) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let n: i64 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM holdings) + (SELECT COUNT(*) FROM pl_entries)
+                  + (SELECT COUNT(*) FROM brackets)",
+            [],
+            |r| r.get(0),
+        )?;
+        Ok(n == 0)
+    }
+
+    pub fn load(&self) -> Result<BotState> {
+        let conn = self.conn.lock().unwrap();
+        let mut st = BotState::default();
+
+        let mut q = conn.prepare("SELECT data FROM holdings ORDER BY id")?;
+        for data in q.query_map([], |r| r.get::<_, String>(0))? {
+            let data = data?;
+            st.holdings.push(
+                serde_json::from_str::<Holding>(&data)
+                    .with_context(|| format!("bad holding row: {}", data))?,
+            );
+        }
+
+        let mut q = conn
+            .prepare("SELECT date, asset, qty, realized_pl, author FROM pl_entries ORDER BY id")?;
+        for e in q.query_map([], |r| {
+            Ok(PlEntry {
+                date: r.get::<_, NaiveDate>(0)?,
+                asset: r.get(1)?,
+                qty: r.get(2)?,
+                realized_pl: r.get(3)?,
+                author: r.get(4)?,
+            })
+        })? {
+            st.daily_pl.push(e?);
+        }
+
+        if let Some(frozen) = meta_get(&conn, "frozen")? {
+            st.frozen = frozen;
+        }
+        st.halted_on = meta_get(&conn, "halted_on")?.flatten();
+
+        let mut q = conn.prepare("SELECT data FROM brackets ORDER BY rowid")?;
+        for data in q.query_map([], |r| r.get::<_, String>(0))? {
+            let data = data?;
+            st.brackets.push(
+                serde_json::from_str::<ExitBracket>(&data)
+                    .with_context(|| format!("bad bracket row: {}", data))?,
+            );
+        }
+        Ok(st)
+    }
+
+    /// Write the snapshot in one transaction: holdings and brackets are replaced, P/L
+    /// entries not yet stored are appended.
+    pub fn save(&self, st: &BotState) -> Result<()> {
+        self.save_finished(st, &[])
+    }
+
+    /// `save`, also closing the journal entries of `order_ids` in the same transaction.
+    pub fn save_finished(&self, st: &BotState, order_ids: &[&str]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM holdings", [])?;
+        for h in &st.holdings {
+            tx.execute(
+                "INSERT INTO holdings (instrument, quantity, data) VALUES (?1, ?2, ?3)",
+                params![h.label(), h.quantity(), serde_json::to_string(h)?],
+            )?;
+        }
+
+        tx.execute("DELETE FROM brackets", [])?;
+        for b in &st.brackets {
+            tx.execute(
+                "INSERT OR REPLACE INTO brackets (ticker_id, data) VALUES (?1, ?2)",
+                params![b.ticker_id, serde_json::to_string(b)?],
+            )?;
+        }
+
+        meta_put(&tx, "frozen", &st.frozen)?;
+        meta_put(&tx, "halted_on", &st.halted_on)?;
+
+        let stored: i64 = tx.query_row("SELECT COUNT(*) FROM pl_entries", [], |r| r.get(0))?;
+        let mut from = stored as usize;
+        if from > st.daily_pl.len() {
+            // history was rewritten in memory; store it as is
+            tx.execute("DELETE FROM pl_entries", [])?;
+            from = 0;
+        }
+        for e in &st.daily_pl[from..] {
+            tx.execute(
+                "INSERT INTO pl_entries (date, asset, qty, realized_pl, author)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![e.date, e.asset, e.qty, e.realized_pl, e.author],
+            )?;
+        }
+
+        for id in order_ids {
+            tx.execute(
+                "UPDATE orders SET done = 1 WHERE order_id = ?1",
+                params![id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Load a JSON state file written by earlier versions and store it. Unlike the
+    /// old loader, a file that does not parse is an error rather than an empty state.
+    pub fn import_json(&self, path: &str) -> Result<BotState> {
+        let st = BotState::load_json(path)?;
+        self.save(&st)?;
+        Ok(st)
+    }
+
+    /// Append a received signal with its author's username and user ID; returns its id
+    /// for linking orders.
+    pub fn record_signal(
+        &self,
+        signal: &TradeSignal,
+        author: Option<&Author>,
+        message_id: Option<&str>,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO signals
+                (received_at, action, instrument, data, author, author_id, message_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                calendar::eastern_now(),
+                format!("{:?}", signal.action()),
+                signal.label(),
+                serde_json::to_string(signal)?,
+                author.map(|a| &a.name),
+                author.and_then(|a| a.id.as_ref()),
+                message_id
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Latest signal recorded for a Discord message.
+    pub fn message_signal(&self, message_id: &str) -> Result<Option<TradeSignal>> {
+        let conn = self.conn.lock().unwrap();
+        let data: Option<String> = conn
+            .query_row(
+                "SELECT data FROM signals WHERE message_id = ?1 ORDER BY id DESC LIMIT 1",
+                params![message_id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        data.map(|d| serde_json::from_str(&d).context("bad recorded signal"))
+            .transpose()
+    }
+
+    /// Journal a placed order as open.
+    pub fn record_order(&self, o: &OrderRecord) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO orders
+                (order_id, signal_id, instrument, side, qty, limit_price, placed_at,
+                 signal, deadline, leg, spread_group, author, done)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0)",
+            params![
+                o.order_id,
+                o.signal_id,
+                o.instrument(),
+                format!("{:?}", o.side),
+                o.qty,
+                o.limit,
+                calendar::eastern_now(),
+                serde_json::to_string(&o.signal)?,
+                o.deadline,
+                o.leg.as_ref().map(|(i, _)| *i as i64),
+                o.leg.as_ref().map(|(_, g)| g.as_str()),
+                o.author,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Journal entries still open, oldest first.
+    pub fn open_orders(&self) -> Result<Vec<OrderRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let orders = query_orders(&conn, "done = 0", [])?;
+        Ok(orders.into_iter().map(|(o, _)| o).collect())
+    }
+
+    /// Hold a signal for the next regular open.
+    pub fn queue_signal(&self, q: &QueuedSignal) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO queued (key, author, author_id, signal_id, signal)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                q.key,
+                q.author.as_ref().map(|a| &a.name),
+                q.author.as_ref().and_then(|a| a.id.as_ref()),
+                q.signal_id,
+                serde_json::to_string(&q.signal)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Signals held for the next regular open, oldest first.
+    pub fn queued_signals(&self) -> Result<Vec<QueuedSignal>> {
+        let conn = self.conn.lock().unwrap();
+        let mut q = conn
+            .prepare("SELECT key, author, author_id, signal_id, signal FROM queued ORDER BY id")?;
+        let rows = q.query_map([], |r| {
+            Ok((
+                r.get::<_, Option<String>>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, Option<String>>(2)?,
+                r.get::<_, Option<i64>>(3)?,
+                r.get::<_, String>(4)?,
+            ))
+        })?;
+        let mut out = Vec::new();
+        for row in rows {
+            let (key, name, id, signal_id, signal) = row?;
+            out.push(QueuedSignal {
+                key,
+                author: name.map(|name| Author { id, name }),
+                signal_id,
+                signal: serde_json::from_str(&signal).context("bad queued signal")?,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Drop the queued signals of a message, or all of them when `key` is None.
+    pub fn unqueue_signals(&self, key: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match key {
+            Some(k) => conn.execute("DELETE FROM queued WHERE key = ?1", params![k])?,
+            None => conn.execute("DELETE FROM queued", [])?,
+        };
+        Ok(())
+    }
+
+    /// Orders placed for signals from a Discord message, with whether each is finished.
+    pub fn message_orders(&self, message_id: &str) -> Result<Vec<(OrderRecord, bool)>> {
+        let conn = self.conn.lock().unwrap();
+        query_orders(
+            &conn,
+            "signal_id IN (SELECT id FROM signals WHERE message_id = ?1)",
+            params![message_id],
+        )
+    }
+}
+
+/// Journal rows matching `filter` (an SQL condition), oldest first, with their done flag.
+fn query_orders(
+    conn: &Connection,
+    filter: &str,
+    args: impl rusqlite::Params,
+) -> Result<Vec<(OrderRecord, bool)>> {
+    let mut q = conn.prepare(&format!(
+        "SELECT order_id, signal_id, signal, side, qty, limit_price, deadline, leg,
+                spread_group, author, done
+         FROM orders WHERE signal IS NOT NULL AND {} ORDER BY rowid",
+        filter
+    ))?;
+    let rows = q.query_map(args, |r| {
+        Ok((
+            r.get::<_, String>(0)?,
+            r.get::<_, Option<i64>>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, String>(3)?,
+            r.get::<_, f64>(4)?,
+            r.get::<_, Option<f64>>(5)?,
+            r.get::<_, NaiveDateTime>(6)?,
+            r.get::<_, Option<i64>>(7)?,
+            r.get::<_, Option<String>>(8)?,
+            r.get::<_, Option<String>>(9)?,
+            r.get::<_, bool>(10)?,
+        ))
+    })?;
+    let mut out = Vec::new();
+    for row in rows {
+        let (order_id, signal_id, signal, side, qty, limit, deadline, leg, group, author, done) =
+            row?;
+        let signal = serde_json::from_str(&signal)
+            .with_context(|| format!("bad journaled signal for order {}", order_id))?;
+        let rec = OrderRecord {
+            order_id,
+            signal_id,
+            signal,
+            side: if side == "Buy" { Side::Buy } else { Side::Sell },
+            qty,
+            limit,
+            deadline,
+            leg: leg.zip(group).map(|(i, g)| (i as usize, g)),
+            author,
+        };
+        out.push((rec, done));
+    }
+    Ok(out)
+}
+
+fn meta_get<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>> {
+    let v: Option<String> = conn
+        .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |r| {
+            r.get(0)
+        })
+        .optional()?;
+    v.map(|v| serde_json::from_str(&v).with_context(|| format!("bad meta value {}", key)))
+        .transpose()
+}
+
+fn meta_put<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+        params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_signal_on;
+
+    fn d(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 9, day).unwrap()
+    }
+
+    #[test]
+    fn snapshot_round_trip_appends_pl() {
+        let store = StateStore::in_memory().unwrap();
+        assert!(store.is_empty().unwrap());
+
+        let mut st = BotState::default();
+        st.upsert_stock_buy_with_cost("AAPL", 10.0, 100.0);
+        st.realize_stock_sell("AAPL", 4.0, 110.0, d(2));
+        store.save(&st).unwrap();
+        st.realize_stock_sell("AAPL", 6.0, 90.0, d(3));
+        store.save(&st).unwrap();
+
+        let back = store.load().unwrap();
+        assert!(back.holdings.is_empty());
+        let pl: Vec<f64> = back.daily_pl.iter().map(|e| e.realized_pl).collect();
+        assert_eq!(pl, vec![40.0, -60.0]);
+        assert_eq!(back.daily_pl[1].date, d(3));
+    }
+
+    #[test]
+    fn import_rejects_unparsable_json() {
+        let dir = std::env::temp_dir();
+        let bad = dir.join(format!("dwt-bad-state-{}.json", std::process::id()));
+        std::fs::write(&bad, "{ not json").unwrap();
+        let store = StateStore::in_memory().unwrap();
+        assert!(store.import_json(bad.to_str().unwrap()).is_err());
+        assert!(store.is_empty().unwrap());
+
+        let good = dir.join(format!("dwt-good-state-{}.json", std::process::id()));
+        let mut st = BotState::default();
+        st.upsert_stock_buy_with_cost("TSLA", 5.0, 200.0);
+        st.save_json(good.to_str().unwrap()).unwrap();
+        store.import_json(good.to_str().unwrap()).unwrap();
+        assert_eq!(store.load().unwrap().position_qty_stock("TSLA"), 5.0);
+        let _ = std::fs::remove_file(bad);
+        let _ = std::fs::remove_file(good);
+    }
+
+    #[test]
+    fn journal_keeps_orders_open_until_finished() {
+        let store = StateStore::in_memory().unwrap();
+        let sig = parse_signal_on("BTO 1 SPY 450C 09/20 @ 2", d(1).into()).unwrap();
+        let bob = Author {
+            id: Some("42".into()),
+            name: "bob".into(),
+        };
+        let id = store.record_signal(&sig, Some(&bob), Some("m1")).unwrap();
+        let rec = |order_id: &str| OrderRecord {
+            order_id: order_id.into(),
+            signal_id: Some(id),
+            signal: sig.clone(),
+            side: Side::Buy,
+            qty: 1.0,
+            limit: Some(2.0),
+            deadline: d(1).and_hms_opt(10, 0, 0).unwrap(),
+            leg: None,
+            author: Some("42".into()),
+        };
+        store.record_order(&rec("A1")).unwrap();
+        store.record_order(&rec("A2")).unwrap();
+
+        let open = store.open_orders().unwrap();
+        assert_eq!(open.len(), 2);
+        assert_eq!(open[0].order_id, "A1");
+        assert_eq!(open[0].signal.label(), "SPY 450C 2025-09-20");
+        assert_eq!(open[0].deadline, rec("A1").deadline);
+        assert_eq!(open[0].author.as_deref(), Some("42"));
+
+        store.save_finished(&BotState::default(), &["A1"]).unwrap();
+        let open = store.open_orders().unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].order_id, "A2");
+        assert_eq!(open[0].signal_id, Some(id));
+
+        let by_message = store.message_orders("m1").unwrap();
+        let done: Vec<(&str, bool)> = by_message
+            .iter()
+            .map(|(o, d)| (o.order_id.as_str(), *d))
+            .collect();
+        assert_eq!(done, vec![("A1", true), ("A2", false)]);
+        assert_eq!(
+            store.message_signal("m1").unwrap().unwrap().label(),
+            sig.label()
+        );
+        assert!(store.message_signal("m2").unwrap().is_none());
+    }
+}
@@ -1,12 +1,52 @@
 //! Core domain types for signals, orders, holdings and realized P/L.
 
-use chrono::NaiveDate;
-use serde::{Deserialize, Serialize};
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::calendar;
+use crate::instrument::{contract_multiplier, index_underlying, InstrumentId, OptionKey};
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Action {
+    /// Buy to open (long).
     BTO,
+    /// Sell to close (long).
     STC,
+    /// Sell to open (short).
+    STO,
+    /// Buy to close (short).
+    BTC,
+}
+
+impl Action {
+    /// BTO / STO add to a position; STC / BTC reduce one.
+    pub fn is_opening(self) -> bool {
+        matches!(self, Action::BTO | Action::STO)
+    }
+
+    /// STO / BTC act on short positions.
+    pub fn is_short(self) -> bool {
+        matches!(self, Action::STO | Action::BTC)
+    }
+}
+
+/// Who posted a signal: the Discord user ID, stable across renames (unknown for history
+/// exported with names only), and the username at the time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Author {
+    pub id: Option<String>,
+    pub name: String,
+}
+
+impl Author {
+    /// An author known by name only.
+    pub fn named(name: &str) -> Self {
+        Self {
+            id: None,
+            name: name.to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -24,8 +64,8 @@ pub enum Side {
 impl From<Action> for Side {
     fn from(a: Action) -> Self {
         match a {
-            Action::BTO => Side::Buy,
-            Action::STC => Side::Sell,
+            Action::BTO | Action::BTC => Side::Buy,
+            Action::STC | Action::STO => Side::Sell,
         }
     }
 }
@@ -36,6 +76,84 @@ pub enum OrderType {
     Limit,
 }
 
+/// Exit size relative to the current position ("STC half", "trim 1/3", "STC all").
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RelativeQty {
+    All,
+    /// Fraction of the position in (0, 1].
+    Fraction(f64),
+}
+
+impl RelativeQty {
+    /// Whole shares/contracts to sell out of `held`. Trims round down but never to zero
+    /// while at least one unit is held.
+    pub fn resolve(&self, held: f64) -> u32 {
+        let held = held.max(0.0).floor();
+        match self {
+            RelativeQty::All => held as u32,
+            RelativeQty::Fraction(f) => {
+                let q = (held * f).floor() as u32;
+                if held >= 1.0 {
+                    q.clamp(1, held as u32)
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+/// Expiry written relative to the signal date ("0DTE", "weekly", "next Fri", "monthly").
+/// The parser resolves it on the market calendar; when that date is not listed for the
+/// contract, execution falls back to the closest listed expiry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RelativeExpiry {
+    /// `n` trading days out: 0DTE expires on the signal's trading day.
+    Dte(u32),
+    /// This week's Friday expiry.
+    Weekly,
+    /// Next week's Friday expiry.
+    NextWeek,
+    /// Third-Friday expiry of this month, or next month's once it has passed.
+    Monthly,
+}
+
+impl RelativeExpiry {
+    /// Expiry date for a signal posted at `now` (US/Eastern). Days count from the
+    /// session it trades in: after the regular close, or on a weekend or holiday, that
+    /// is the next trading day.
+    pub fn resolve(&self, now: NaiveDateTime) -> NaiveDate {
+        let day = calendar::next_open(now).date();
+        match self {
+            RelativeExpiry::Dte(n) => calendar::add_trading_days(day, *n),
+            RelativeExpiry::Weekly => calendar::weekly_expiry(day),
+            RelativeExpiry::NextWeek => calendar::weekly_expiry(day + Duration::days(7)),
+            RelativeExpiry::Monthly => {
+                let this = calendar::monthly_expiry(day.year(), day.month());
+                if this >= day {
+                    this
+                } else {
+                    let next = day.with_day(1).unwrap() + Months::new(1);
+                    calendar::monthly_expiry(next.year(), next.month())
+                }
+            }
+        }
+    }
+}
+
+/// Stop-loss / profit-target prices attached to an entry ("... SL 1.80 PT 3.50").
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExitLevels {
+    pub stop: Option<f64>,
+    pub target: Option<f64>,
+}
+
+impl ExitLevels {
+    pub fn is_empty(&self) -> bool {
+        self.stop.is_none() && self.target.is_none()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StockSignal {
     pub action: Action,
@@ -43,49 +161,290 @@ pub struct StockSignal {
     pub quantity: u32,
     pub order_type: OrderType,
     pub limit_price: Option<f64>,
+    /// When set, `quantity` is resolved against current holdings at execution time.
+    #[serde(default)]
+    pub relative_qty: Option<RelativeQty>,
+    #[serde(default)]
+    pub exit_levels: ExitLevels,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OptionSignal {
     pub action: Action,
-    pub symbol: String,
+    #[serde(flatten)]
+    pub key: OptionKey,
+    pub quantity: u32,
+    pub order_type: OrderType,
+    pub limit_price: Option<f64>,
+    /// When set, `quantity` is resolved against current holdings at execution time.
+    #[serde(default)]
+    pub relative_qty: Option<RelativeQty>,
+    #[serde(default)]
+    pub exit_levels: ExitLevels,
+    /// How the expiry was written when it was relative; `key.expiry` holds its date.
+    #[serde(default)]
+    pub relative_expiry: Option<RelativeExpiry>,
+}
+
+/// Expiries are stored as "YYYY-MM-DD". State written before expiries carried a year has
+/// bare "MM/DD" strings; those are migrated to the nearest date on or after today.
+pub(crate) fn de_expiry<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDate, D::Error> {
+    let s = String::deserialize(d)?;
+    crate::utils::parse_expiry(&s, calendar::eastern_now())
+        .ok_or_else(|| serde::de::Error::custom(format!("bad option expiry: {}", s)))
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SpreadKind {
+    /// Same type, two strikes: first strike is bought, second sold ("450/455C").
+    Vertical,
+    /// Call and put at the same strike, both bought ("450C/450P").
+    Straddle,
+    /// Call and put at different strikes, both bought ("445P/455C").
+    Strangle,
+}
+
+/// One leg of a spread; `ratio` is +1 for a long leg and -1 for a short leg per spread.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct SpreadLeg {
     pub strike: f64,
-    pub call_put: char, // 'C' or 'P'
-    pub expiry_mmdd: String,
+    pub call_put: char,
+    pub ratio: i32,
+}
+
+/// Multi-leg option signal; `limit_price` is the net debit per spread.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpreadSignal {
+    pub action: Action,
+    pub symbol: String,
+    pub kind: SpreadKind,
+    pub legs: Vec<SpreadLeg>,
+    #[serde(alias = "expiry_mmdd", deserialize_with = "de_expiry")]
+    pub expiry: NaiveDate,
     pub quantity: u32,
     pub order_type: OrderType,
     pub limit_price: Option<f64>,
+    /// When set, `quantity` is resolved against the spreads held at execution time.
+    #[serde(default)]
+    pub relative_qty: Option<RelativeQty>,
+    /// How the expiry was written when it was relative; `expiry` holds its date.
+    #[serde(default)]
+    pub relative_expiry: Option<RelativeExpiry>,
+}
+
+impl SpreadSignal {
+    /// Worst-case loss per spread (in premium units, ×100 for USD) given the net price.
+    /// Debit spreads lose at most what was paid; credit verticals lose width minus credit.
+    pub fn max_loss_per_spread(&self, net_price: f64) -> f64 {
+        match (self.kind, self.legs.as_slice()) {
+            (SpreadKind::Vertical, [long, short]) => {
+                let width = (long.strike - short.strike).abs();
+                let is_debit = match long.call_put {
+                    'C' => long.strike < short.strike,
+                    _ => long.strike > short.strike,
+                };
+                if is_debit {
+                    net_price
+                } else {
+                    (width - net_price).max(0.0)
+                }
+            }
+            _ => net_price,
+        }
+    }
+
+    /// Display label, e.g. "SPY 450/455C 2025-09-20" or "SPY 450C/450P 2025-09-20".
+    pub fn label(&self) -> String {
+        spread_label(&self.symbol, self.kind, &self.legs, self.expiry)
+    }
+
+    pub fn leg_key(&self, leg: &SpreadLeg) -> OptionKey {
+        OptionKey::new(&self.symbol, leg.strike, leg.call_put, self.expiry)
+    }
+
+    /// Contract size of the legs (see `contract_multiplier`).
+    pub fn multiplier(&self) -> f64 {
+        contract_multiplier(&self.symbol)
+    }
+}
+
+fn spread_label(symbol: &str, kind: SpreadKind, legs: &[SpreadLeg], expiry: NaiveDate) -> String {
+    let legs = match (kind, legs) {
+        (SpreadKind::Vertical, [a, b]) => format!("{}/{}{}", a.strike, b.strike, a.call_put),
+        _ => legs
+            .iter()
+            .map(|l| format!("{}{}", l.strike, l.call_put))
+            .collect::<Vec<_>>()
+            .join("/"),
+    };
+    format!("{} {} {}", symbol, legs, expiry)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TradeSignal {
     Stock(StockSignal),
     Option(OptionSignal),
+    Spread(SpreadSignal),
+}
+
+impl TradeSignal {
+    pub fn action(&self) -> Action {
+        match self {
+            TradeSignal::Stock(s) => s.action,
+            TradeSignal::Option(o) => o.action,
+            TradeSignal::Spread(sp) => sp.action,
+        }
+    }
+
+    /// Single instrument traded by the signal; None for multi-leg spreads.
+    pub fn instrument(&self) -> Option<InstrumentId> {
+        match self {
+            TradeSignal::Stock(s) => Some(InstrumentId::stock(&s.symbol)),
+            TradeSignal::Option(o) => Some(InstrumentId::Option(o.key.clone())),
+            TradeSignal::Spread(_) => None,
+        }
+    }
+
+    /// Absolute size, for resizing; None when it is relative to holdings ("STC half").
+    pub fn quantity_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            TradeSignal::Stock(s) if s.relative_qty.is_none() => Some(&mut s.quantity),
+            TradeSignal::Option(o) if o.relative_qty.is_none() => Some(&mut o.quantity),
+            TradeSignal::Spread(sp) if sp.relative_qty.is_none() => Some(&mut sp.quantity),
+            _ => None,
+        }
+    }
+
+    /// Stock symbol, or the underlying of an option or spread.
+    pub fn underlying(&self) -> &str {
+        match self {
+            TradeSignal::Stock(s) => &s.symbol,
+            TradeSignal::Option(o) => o.key.underlying(),
+            TradeSignal::Spread(sp) => index_underlying(&sp.symbol).unwrap_or(&sp.symbol),
+        }
+    }
+
+    /// Instrument or spread label, e.g. "AAPL", "SPY 450C 2025-09-20".
+    pub fn label(&self) -> String {
+        match self {
+            TradeSignal::Spread(sp) => sp.label(),
+            _ => self.instrument().map(|i| i.to_string()).unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Holding {
-    /// Stock holding with average cost per share.
+    /// Stock holding with average cost per share. Negative quantity is a short, with
+    /// `avg_cost` the average sale price.
     Stock {
         symbol: String,
         quantity: f64,
         avg_cost: f64,
     },
-    /// Option holding with average premium per contract.
+    /// Option holding with average premium per contract. Negative quantity is a short.
     Option {
+        #[serde(flatten)]
+        key: OptionKey,
+        quantity: i32,
+        avg_cost: f64,
+    },
+    /// Multi-leg option position tracked as one unit, with average net debit per spread.
+    Spread {
         symbol: String,
-        strike: f64,
-        call_put: char,
-        expiry_mmdd: String,
+        kind: SpreadKind,
+        legs: Vec<SpreadLeg>,
+        #[serde(alias = "expiry_mmdd", deserialize_with = "de_expiry")]
+        expiry: NaiveDate,
         quantity: u32,
         avg_cost: f64,
     },
 }
 
+impl Holding {
+    /// Single instrument held; None for spreads.
+    pub fn instrument(&self) -> Option<InstrumentId> {
+        match self {
+            Holding::Stock { symbol, .. } => Some(InstrumentId::stock(symbol)),
+            Holding::Option { key, .. } => Some(InstrumentId::Option(key.clone())),
+            Holding::Spread { .. } => None,
+        }
+    }
+
+    /// Instrument or spread label, as used for realized P/L assets.
+    pub fn label(&self) -> String {
+        match self {
+            Holding::Spread {
+                symbol,
+                kind,
+                legs,
+                expiry,
+                ..
+            } => spread_label(symbol, *kind, legs, *expiry),
+            _ => self.instrument().map(|i| i.to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// Stock symbol, or the underlying of an option or spread.
+    pub fn underlying(&self) -> &str {
+        match self {
+            Holding::Stock { symbol, .. } => symbol,
+            Holding::Spread { symbol, .. } => index_underlying(symbol).unwrap_or(symbol),
+            Holding::Option { key, .. } => key.underlying(),
+        }
+    }
+
+    /// Capital tied up at cost: shares × average price, premium × contract multiplier
+    /// for options and spreads. Shorts count by absolute size.
+    pub fn cost_basis(&self) -> f64 {
+        match self {
+            Holding::Stock {
+                quantity, avg_cost, ..
+            } => quantity.abs() * avg_cost.abs(),
+            Holding::Option {
+                key,
+                quantity,
+                avg_cost,
+            } => quantity.unsigned_abs() as f64 * avg_cost.abs() * key.multiplier(),
+            Holding::Spread {
+                symbol,
+                quantity,
+                avg_cost,
+                ..
+            } => *quantity as f64 * avg_cost.abs() * contract_multiplier(symbol),
+        }
+    }
+
+    /// Signed position size (shares, contracts or spreads).
+    pub fn quantity(&self) -> f64 {
+        match self {
+            Holding::Stock { quantity, .. } => *quantity,
+            Holding::Option { quantity, .. } => *quantity as f64,
+            Holding::Spread { quantity, .. } => *quantity as f64,
+        }
+    }
+}
+
+/// Armed stop/target for an open holding. `exit` is the ready-to-fire STC / BTC signal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitBracket {
+    pub ticker_id: i64,
+    pub exit: TradeSignal,
+    pub levels: ExitLevels,
+    /// Attribution key (`AuthorCfg::key`) of the entry's configured author; the exit is
+    /// handled as theirs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlEntry {
     pub date: NaiveDate,
-    pub asset: String,    // e.g., "AAPL" or "AAPL 150C 08/16"
+    pub asset: String,    // e.g., "AAPL" or "AAPL 150C 2025-08-16"
     pub qty: f64,         // shares or contracts
     pub realized_pl: f64, // USD; options already ×100 accounted where recorded
+    /// Attribution key (`AuthorCfg::key`) of the configured author whose signal closed
+    /// the position, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
 }
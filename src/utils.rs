@@ -1,5 +1,11 @@
 //! Small helpers.
 
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use webull_unofficial::models::OptionContract;
+
+use crate::instrument::OptionKey;
+use crate::types::RelativeExpiry;
+
 pub fn tif_from_str(s: &str) -> webull_unofficial::models::TimeInForce {
     match s.to_ascii_uppercase().as_str() {
         "GTC" => webull_unofficial::models::TimeInForce::GoodTillCancel,
@@ -7,26 +13,179 @@ pub fn tif_from_str(s: &str) -> webull_unofficial::models::TimeInForce {
     }
 }
 
+/// Upper-cased symbol without a cashtag `$`: " $brk.b" -> "BRK.B".
 pub fn sanitize_symbol(sym: &str) -> String {
-    sym.trim().to_uppercase()
+    sym.trim().trim_start_matches('$').to_uppercase()
 }
 
-pub fn mmdd_digits(s: &str) -> Option<String> {
-    // Convert "08/16" or "08-16" to "0816"
-    let d: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
-    if d.len() == 4 {
-        Some(d)
+/// Case-insensitive name match: equal, or containing `pattern` when `substring` is set.
+pub fn name_matches(pattern: &str, name: &str, substring: bool) -> bool {
+    if substring {
+        name.to_lowercase().contains(&pattern.to_lowercase())
     } else {
-        None
+        name.to_lowercase() == pattern.to_lowercase()
     }
 }
 
-pub fn last4_digits(s: &str) -> Option<String> {
-    // Extract last 4 digits of a date-like string, e.g., "2025-08-16" -> "0816"
-    let d: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
-    if d.len() >= 4 {
-        Some(d[d.len() - 4..].to_string())
-    } else {
-        None
+/// Parse an option expiry: "YYYY-MM-DD", "MM/DD/YY", "MM/DD/YYYY", a bare "MM/DD"
+/// resolved to the nearest date on or after today, or a relative expiry (see
+/// [`parse_relative_expiry`]) resolved on the market calendar at `now` (US/Eastern).
+pub fn parse_expiry(s: &str, now: NaiveDateTime) -> Option<NaiveDate> {
+    let s = s.trim();
+    if let Some(rel) = parse_relative_expiry(s) {
+        return Some(rel.resolve(now));
+    }
+    let today = now.date();
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some(d);
+    }
+    let parts: Vec<&str> = s.split('/').collect();
+    let (m, d): (u32, u32) = match parts.as_slice() {
+        [m, d] | [m, d, _] => (m.parse().ok()?, d.parse().ok()?),
+        _ => return None,
+    };
+    if let [_, _, y] = parts.as_slice() {
+        let y: i32 = match y.len() {
+            2 => 2000 + y.parse::<i32>().ok()?,
+            4 => y.parse().ok()?,
+            _ => return None,
+        };
+        return NaiveDate::from_ymd_opt(y, m, d);
+    }
+    // Feb 29 may be up to four years out
+    (today.year()..=today.year() + 4)
+        .filter_map(|y| NaiveDate::from_ymd_opt(y, m, d))
+        .find(|d| *d >= today)
+}
+
+/// Relative expiry words, optionally after "exp": "0DTE", "2 dte", "weekly", "Fri",
+/// "this Friday", "next Fri", "next week", "monthly", "opex".
+pub fn parse_relative_expiry(s: &str) -> Option<RelativeExpiry> {
+    let s = s.trim().to_ascii_lowercase();
+    let s = s
+        .strip_prefix("exp")
+        .map_or(&s[..], |r| r.trim_start_matches(['.', ':']).trim_start());
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let rel = match words.as_slice() {
+        [w] if w.ends_with("dte") => RelativeExpiry::Dte(w.trim_end_matches("dte").parse().ok()?),
+        [n, "dte"] => RelativeExpiry::Dte(n.parse().ok()?),
+        ["weekly" | "weeklies" | "fri" | "friday"] | ["this", "fri" | "friday" | "week"] => {
+            RelativeExpiry::Weekly
+        }
+        ["next", "fri" | "friday" | "week"] => RelativeExpiry::NextWeek,
+        ["monthly" | "monthlies" | "opex"] => RelativeExpiry::Monthly,
+        _ => return None,
+    };
+    Some(rel)
+}
+
+/// Canonical key of a broker option contract (`expiration_date` as "YYYY-MM-DD").
+pub fn contract_key(c: &OptionContract) -> Option<OptionKey> {
+    let expiry = NaiveDate::parse_from_str(c.expiration_date.trim(), "%Y-%m-%d").ok()?;
+    let cp = c.option_type.trim().chars().next()?.to_ascii_uppercase();
+    if !matches!(cp, 'C' | 'P') {
+        return None;
+    }
+    Some(OptionKey::new(&c.symbol, c.strike_price, cp, expiry))
+}
+
+/// Key of a chain contract with its root taken from `symbol` when that is an OCC ticker.
+fn listed_key(c: &OptionContract) -> Option<OptionKey> {
+    OptionKey::from_occ(&c.symbol).or_else(|| contract_key(c))
+}
+
+/// Contract strike, side and expiry per chain entry; `symbol` is not compared.
+fn chain_keys<'a>(
+    chain: &'a [OptionContract],
+    key: &'a OptionKey,
+) -> impl Iterator<Item = (usize, OptionKey)> + 'a {
+    chain.iter().enumerate().filter_map(move |(i, c)| {
+        let k = contract_key(c)?;
+        (k.call_put == key.call_put && (k.strike - key.strike).abs() < 1e-6).then_some((i, k))
+    })
+}
+
+/// Position of the contract for `key` in a chain already scoped to its underlying: the
+/// one at the key's strike, side and expiry. The feed's `symbol` may be an OCC ticker or
+/// a display name, so the root only decides between several matches (an index chain
+/// listing both SPX and SPXW).
+pub fn chain_contract(chain: &[OptionContract], key: &OptionKey) -> Option<usize> {
+    let same: Vec<usize> = chain_keys(chain, key)
+        .filter(|(_, k)| k.expiry == key.expiry)
+        .map(|(i, _)| i)
+        .collect();
+    if let [i] = same[..] {
+        return Some(i);
+    }
+    let mut rooted = same
+        .into_iter()
+        .filter(|i| listed_key(&chain[*i]).as_ref() == Some(key));
+    match (rooted.next(), rooted.next()) {
+        (Some(i), None) => Some(i),
+        _ => None,
+    }
+}
+
+/// Expiries at which [`chain_contract`] finds a contract for the key's strike and side.
+pub fn chain_expiries(chain: &[OptionContract], key: &OptionKey) -> Vec<NaiveDate> {
+    let mut expiries: Vec<NaiveDate> = chain_keys(chain, key).map(|(_, k)| k.expiry).collect();
+    expiries.sort();
+    expiries.dedup();
+    expiries.retain(|d| {
+        let at = OptionKey::new(&key.symbol, key.strike, key.call_put, *d);
+        chain_contract(chain, &at).is_some()
+    });
+    expiries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract(id: i64, symbol: &str, strike: f64, cp: &str, expiry: &str) -> OptionContract {
+        OptionContract {
+            ticker_id: id,
+            symbol: symbol.into(),
+            strike_price: strike,
+            expiration_date: expiry.into(),
+            option_type: cp.into(),
+        }
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn chain_matches_on_strike_side_and_expiry_not_symbol() {
+        // the feed labels equity contracts with an OCC ticker or a display name
+        let chain = [
+            contract(1, "AAPL250816C00150000", 150.0, "call", "2025-08-16"),
+            contract(2, "Apple Inc", 150.0, "put", "2025-08-16"),
+            contract(3, "Apple Inc", 150.0, "call", "2025-08-22"),
+        ];
+        let key = |cp, d| OptionKey::new("AAPL", 150.0, cp, ymd(2025, 8, d));
+        assert_eq!(chain_contract(&chain, &key('C', 16)), Some(0));
+        assert_eq!(chain_contract(&chain, &key('P', 16)), Some(1));
+        assert_eq!(chain_contract(&chain, &key('P', 22)), None);
+        assert_eq!(
+            chain_expiries(&chain, &key('C', 1)),
+            vec![ymd(2025, 8, 16), ymd(2025, 8, 22)]
+        );
+
+        // an index chain lists several roots; the key's root decides
+        let spx = [
+            contract(4, "SPX", 5000.0, "call", "2025-08-15"),
+            contract(5, "SPXW", 5000.0, "call", "2025-08-15"),
+            contract(6, "SPXW", 5000.0, "call", "2025-08-14"),
+        ];
+        let spxw = OptionKey::new("SPXW", 5000.0, 'C', ymd(2025, 8, 15));
+        assert_eq!(chain_contract(&spx, &spxw), Some(1));
+        assert_eq!(
+            chain_expiries(&spx, &spxw),
+            vec![ymd(2025, 8, 14), ymd(2025, 8, 15)]
+        );
+        let spx_monthly = OptionKey::new("SPX", 5000.0, 'C', ymd(2025, 8, 15));
+        assert_eq!(chain_contract(&spx, &spx_monthly), Some(0));
     }
 }
@@ -1,6 +1,9 @@
 //! Thin wrapper over `webull_unofficial` for login, discovery, quotes, orders and basic order status.
+//! Implements [`Broker`] for the live/paper Webull account.
 
 use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
 use serde_json::Value;
 use tracing::{error, info};
 use webull_unofficial::{
@@ -9,30 +12,16 @@ use webull_unofficial::{
     WebullClient,
 };
 
+use crate::broker::{Broker, OrderInfo, OrderStatus};
+use crate::instrument::OptionKey;
 use crate::types::Holding;
+use crate::utils::{chain_contract, chain_expiries};
 
 pub struct WbCtx {
     pub client: WebullClient,
     pub is_live: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum OrderStatus {
-    Working,
-    PartiallyFilled,
-    Filled,
-    Canceled,
-    Rejected,
-    Unknown(String),
-}
-
-#[derive(Debug, Clone)]
-pub struct OrderInfo {
-    pub status: OrderStatus,
-    pub filled_qty: f64,
-    pub avg_fill_price: f64,
-}
-
 impl WbCtx {
     /// Login using the crate's recommended builder style with an interactive MFA fallback.
     ///
@@ -118,51 +107,47 @@ impl WbCtx {
 
         Ok(Self { client, is_live })
     }
+}
 
+#[async_trait(?Send)]
+impl Broker for WbCtx {
     // ---------- Discovery ----------
 
-    pub async fn find_stock_ticker_id(&self, symbol: &str) -> Result<i64> {
+    /// Prefers the search result whose symbol matches ignoring class-share separators
+    /// ("BRK.B" is listed as "BRK B" or "BRK-B" on some feeds), else the first result.
+    async fn find_stock_ticker_id(&self, symbol: &str) -> Result<i64> {
         let found = self.client.find_ticker(symbol).await?;
-        let first = found.first().context("no ticker found")?;
+        let bare = |s: &str| {
+            s.chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_uppercase()
+        };
+        let first = found
+            .iter()
+            .find(|t| bare(&t.symbol) == bare(symbol))
+            .or(found.first())
+            .context("no ticker found")?;
         Ok(first.ticker_id)
     }
 
-    pub async fn find_option_contract(
-        &self,
-        symbol: &str,
-        strike: f64,
-        cp: char,
-        expiry_mmdd: &str,
-    ) -> Result<OptionContract> {
-        let chain = self.client.get_options(symbol).await?;
-        let want_mmdd = crate::utils::mmdd_digits(expiry_mmdd).context("bad MM/DD")?;
-        let upper_cp = if cp.to_ascii_uppercase() == 'C' {
-            "CALL"
-        } else {
-            "PUT"
-        };
-        let mut best: Option<OptionContract> = None;
-        for c in chain.into_iter() {
-            if (c.option_type.eq_ignore_ascii_case(upper_cp))
-                && (c.strike_price - strike).abs() < 1e-6
-            {
-                if let (Some(a), Some(b)) = (
-                    crate::utils::last4_digits(&c.expiration_date),
-                    Some(want_mmdd.clone()),
-                ) {
-                    if a == b {
-                        best = Some(c);
-                        break;
-                    }
-                }
-            }
-        }
-        best.context("option contract not found (by strike/type/expiry)")
+    /// Matched by strike, side and expiry in the underlying's chain (index option roots
+    /// such as SPXW are listed in their index's chain); see [`chain_contract`].
+    async fn find_option_contract(&self, key: &OptionKey) -> Result<OptionContract> {
+        let mut chain = self.client.get_options(key.underlying()).await?;
+        let i = chain_contract(&chain, key)
+            .with_context(|| format!("option contract not found: {}", key.to_occ()))?;
+        Ok(chain.swap_remove(i))
+    }
+
+    async fn listed_expiries(&self, key: &OptionKey) -> Result<Vec<NaiveDate>> {
+        let chain = self.client.get_options(key.underlying()).await?;
+        Ok(chain_expiries(&chain, key))
     }
 
     // ---------- Quotes ----------
 
-    pub async fn mid_price(&self, ticker_id: i64) -> Result<f64> {
+    async fn mid_price(&self, ticker_id: i64) -> Result<f64> {
         let q: Quote = self.client.get_quotes(&ticker_id.to_string()).await?;
         if let (Some(bid), Some(ask)) = (q.bid, q.ask) {
             if ask > 0.0 && bid > 0.0 {
@@ -172,8 +157,17 @@ impl WbCtx {
         Ok(q.close)
     }
 
+    // ---------- Account & positions ----------
+
+    async fn net_liquidation(&self) -> Result<f64> {
+        let account = self.client.get_account().await?;
+        account
+            .net_liquidation
+            .context("account details carry no netLiquidation")
+    }
+
     /// Return a simplified holdings snapshot parsed from Webull positions.
-    pub async fn positions_simple(&self) -> Result<Vec<Holding>> {
+    async fn positions_simple(&self) -> Result<Vec<Holding>> {
         let raw_positions = self.client.get_positions().await?;
         let v: Value = serde_json::to_value(raw_positions)?;
         let mut out: Vec<Holding> = Vec::new();
@@ -224,7 +218,8 @@ impl WbCtx {
                     .or_else(|| it.get("expirationDate"))
                     .or_else(|| it.get("expire_date"))
                     .and_then(|s| s.as_str());
-                let qty = it.get("position").and_then(|x| x.as_i64()).unwrap_or(0) as u32;
+                // Webull reports short positions with a negative quantity
+                let qty = it.get("position").and_then(|x| x.as_i64()).unwrap_or(0) as i32;
                 let avg = it
                     .get("cost")
                     .or_else(|| it.get("avgPrice"))
@@ -233,13 +228,12 @@ impl WbCtx {
                 if let (Some(under), Some(strk), Some(cp_ch), Some(exp)) =
                     (underlying, strike, cp, exp_raw)
                 {
-                    let mmdd =
-                        crate::utils::last4_digits(exp).unwrap_or_else(|| "0000".to_string());
+                    let Some(expiry) = crate::utils::parse_expiry(exp, self.market_time()) else {
+                        error!("skipping option position with bad expiry: {}", exp);
+                        continue;
+                    };
                     out.push(Holding::Option {
-                        symbol: under.to_string(),
-                        strike: strk,
-                        call_put: cp_ch.to_ascii_uppercase(),
-                        expiry_mmdd: mmdd,
+                        key: OptionKey::new(under, strk, cp_ch, expiry),
                         quantity: qty,
                         avg_cost: avg,
                     });
@@ -251,7 +245,7 @@ impl WbCtx {
 
     // ---------- Order status & actions ----------
 
-    pub async fn get_order_info(&self, order_id: &str) -> Result<OrderInfo> {
+    async fn get_order_info(&self, order_id: &str) -> Result<OrderInfo> {
         // Use get_orders(None) and filter locally
         let arr = self.client.get_orders(None).await?;
         let vv: Value = serde_json::to_value(arr)?;
@@ -321,14 +315,14 @@ impl WbCtx {
         })
     }
 
-    pub async fn cancel_order(&self, order_id: &str) -> Result<()> {
+    async fn cancel_order(&self, order_id: &str) -> Result<()> {
         self.client.cancel_order(order_id).await?;
         Ok(())
     }
 
     // ---------- Orders (Stocks) ----------
 
-    pub async fn place_stock_market(
+    async fn place_stock_market(
         &self,
         symbol: &str,
         qty: f64,
@@ -347,7 +341,27 @@ impl WbCtx {
         Ok(order_id)
     }
 
-    pub async fn place_stock_limit(
+    async fn place_stock_limit(
+        &self,
+        symbol: &str,
+        qty: f64,
+        side: OrderAction,
+        limit: f64,
+        tif: &TimeInForce,
+    ) -> Result<String> {
+        let tid = self.find_stock_ticker_id(symbol).await?;
+        let order_id = self
+            .client
+            .place_limit_order_with(limit)
+            .ticker_id(tid)
+            .quantity(qty)
+            .action(side)
+            .time_in_force(tif.clone())
+            .await?;
+        Ok(order_id)
+    }
+
+    async fn place_stock_limit_extended(
         &self,
         symbol: &str,
         qty: f64,
@@ -363,13 +377,14 @@ impl WbCtx {
             .quantity(qty)
             .action(side)
             .time_in_force(tif.clone())
+            .extended_hours()
             .await?;
         Ok(order_id)
     }
 
     // ---------- Orders (Options) ----------
 
-    pub async fn place_option_market(
+    async fn place_option_market(
         &self,
         contract: &OptionContract,
         qty: f64,
@@ -387,7 +402,7 @@ impl WbCtx {
         Ok(order_id)
     }
 
-    pub async fn place_option_limit(
+    async fn place_option_limit(
         &self,
         contract: &OptionContract,
         qty: f64,
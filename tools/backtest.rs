@@ -0,0 +1,77 @@
+// tools/backtest.rs
+// Replay an exported Discord channel history against a local price file and report the
+// holdings and realized P/L the bot would have produced by following the given author(s).
+//
+// Run with:
+//   cargo run --bin backtest -- --messages export.json --prices prices.csv --author alice
+// Options:
+//   --messages <path>   JSON or CSV message export (required)
+//   --prices <path>     CSV rows of timestamp,instrument,price (required)
+//   --author <name>     tracked username (repeatable); defaults to the discord.tracked_* rules
+//   --config <path>     config file for risk/exec settings (default: config.yaml)
+//   --out <path>        where to write the resulting state JSON (default: backtest_state.json)
+//   --capital <usd>     starting account value for percent_of_account sizing (default: 100000)
+
+use anyhow::{bail, Context, Result};
+use discord_webull_trader::{backtest, config::AppConfig, discord::AuthorFilter};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive(Level::INFO.into()))
+        .init();
+
+    let mut messages = None;
+    let mut prices = None;
+    let mut authors: Vec<String> = Vec::new();
+    let mut config = "config.yaml".to_string();
+    let mut out = "backtest_state.json".to_string();
+    let mut capital = 100_000.0;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        let mut val = || args.next().with_context(|| format!("{} needs a value", a));
+        match a.as_str() {
+            "--messages" => messages = Some(val()?),
+            "--prices" => prices = Some(val()?),
+            "--author" => authors.push(val()?),
+            "--config" => config = val()?,
+            "--out" => out = val()?,
+            "--capital" => {
+                let v = val()?;
+                capital = v.parse().with_context(|| format!("bad --capital {}", v))?;
+            }
+            other => bail!("unknown argument: {}", other),
+        }
+    }
+    let messages = messages.context("--messages is required")?;
+    let prices = prices.context("--prices is required")?;
+
+    let cfg = AppConfig::load(&config).with_context(|| format!("load {}", config))?;
+    let authors = if authors.is_empty() {
+        AuthorFilter::from_cfg(&cfg.discord)
+    } else {
+        AuthorFilter {
+            usernames: authors,
+            substring: cfg.discord.substring_match,
+            ..Default::default()
+        }
+    };
+
+    let msgs = backtest::load_messages(&messages)?;
+    let pxs = backtest::load_prices(&prices)?;
+    let report = backtest::replay(cfg, &authors, &msgs, &pxs, capital).await?;
+
+    report.state.save_json(&out)?;
+    println!("{}", serde_json::to_string_pretty(&report.summary)?);
+    for e in &report.state.daily_pl {
+        println!(
+            "{}  {:<24} qty={:<6} pl={:>10.2}",
+            e.date, e.asset, e.qty, e.realized_pl
+        );
+    }
+    println!("Wrote {}", out);
+    Ok(())
+}